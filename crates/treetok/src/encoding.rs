@@ -0,0 +1,247 @@
+//! Text encoding detection, and transcoding to UTF-8 for counting.
+//!
+//! Detection looks at a file's first bytes only: a byte-order mark, the NUL
+//! pattern of BOM-less UTF-16, UTF-8 validity, and finally whether the bytes
+//! read as Latin-1 text.  Anything else is binary.
+
+/// Share of decoded characters (in hundredths) that may be control
+/// characters before content is judged binary.
+const MAX_CONTROL_PERCENT: usize = 1;
+/// Share of byte pairs (in tenths) that must carry a NUL in the same half
+/// for BOM-less content to be taken as UTF-16.
+const MIN_UTF16_NUL_TENTHS: usize = 4;
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+/// Windows-1252's characters for bytes `0x80..=0x9F`, which Latin-1 leaves
+/// to C1 controls.  The five bytes it does not define stay controls.
+const CP1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8D}', 'Ž', '\u{8F}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9D}', 'ž', 'Ÿ',
+];
+
+/// A detected text encoding.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// UTF-8 without a byte-order mark.
+    Utf8,
+    /// UTF-8 with a byte-order mark, which is dropped before counting.
+    Utf8Bom,
+    /// UTF-16, little-endian (with or without a BOM).
+    Utf16Le,
+    /// UTF-16, big-endian (with or without a BOM).
+    Utf16Be,
+    /// Latin-1, read as Windows-1252 like the WHATWG Encoding Standard does
+    /// (so `0x80..=0x9F` are curly quotes and dashes, not controls).
+    Latin1,
+}
+
+impl Encoding {
+    /// Lower-case name used in JSON output.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Utf8 => "utf-8",
+            Self::Utf8Bom => "utf-8-bom",
+            Self::Utf16Le => "utf-16le",
+            Self::Utf16Be => "utf-16be",
+            Self::Latin1 => "latin-1",
+        }
+    }
+
+    /// Detect the encoding of a file that starts with `sniff`, or `None` if
+    /// it is binary.  `utf8` says whether `sniff` is valid UTF-8 (allowing
+    /// a sequence cut off at its end).
+    #[must_use]
+    pub fn detect(sniff: &[u8], utf8: bool) -> Option<Self> {
+        if sniff.starts_with(UTF8_BOM) {
+            return utf8.then_some(Self::Utf8Bom);
+        }
+        let utf16 = if sniff.starts_with(UTF16LE_BOM) {
+            Some(Self::Utf16Le)
+        } else if sniff.starts_with(UTF16BE_BOM) {
+            Some(Self::Utf16Be)
+        } else {
+            guess_utf16(sniff)
+        };
+        if let Some(encoding) = utf16 {
+            return looks_like_text(&encoding.decode_lossy(sniff)).then_some(encoding);
+        }
+        if sniff.contains(&0) {
+            return None;
+        }
+        if utf8 {
+            return Some(Self::Utf8);
+        }
+        looks_like_text(&Self::Latin1.decode_lossy(sniff)).then_some(Self::Latin1)
+    }
+
+    /// Whether a chunk of this encoding may be cut at byte `at` of `buf`
+    /// without splitting a character.
+    #[must_use]
+    pub fn is_boundary(self, buf: &[u8], at: usize) -> bool {
+        match self {
+            Self::Utf8 | Self::Utf8Bom => buf.get(at).is_none_or(|&b| b & 0xC0 != 0x80),
+            Self::Utf16Le | Self::Utf16Be => {
+                // Even, and not just after a high surrogate.
+                let high = usize::from(self == Self::Utf16Le);
+                at.is_multiple_of(2) && (at < 2 || !(0xD8..=0xDB).contains(&buf[at - 2 + high]))
+            }
+            Self::Latin1 => true,
+        }
+    }
+
+    /// Whether the first `at` bytes of `buf` end with a line feed, so a
+    /// chunk may be cut after it.
+    #[must_use]
+    pub fn ends_line(self, buf: &[u8], at: usize) -> bool {
+        let head = &buf[..at];
+        match self {
+            Self::Utf8 | Self::Utf8Bom | Self::Latin1 => head.ends_with(b"\n"),
+            Self::Utf16Le => at.is_multiple_of(2) && head.ends_with(b"\n\0"),
+            Self::Utf16Be => at.is_multiple_of(2) && head.ends_with(b"\0\n"),
+        }
+    }
+
+    /// Transcode `bytes` to UTF-8, dropping a leading byte-order mark.
+    /// Malformed sequences (including a character cut off at the end)
+    /// become U+FFFD.
+    #[must_use]
+    pub fn decode_lossy(self, bytes: &[u8]) -> String {
+        match self {
+            Self::Utf8 | Self::Utf8Bom => {
+                let bytes = bytes.strip_prefix(UTF8_BOM).unwrap_or(bytes);
+                String::from_utf8_lossy(bytes).into_owned()
+            }
+            Self::Utf16Le => decode_utf16(
+                bytes.strip_prefix(UTF16LE_BOM).unwrap_or(bytes),
+                u16::from_le_bytes,
+            ),
+            Self::Utf16Be => decode_utf16(
+                bytes.strip_prefix(UTF16BE_BOM).unwrap_or(bytes),
+                u16::from_be_bytes,
+            ),
+            Self::Latin1 => bytes
+                .iter()
+                .map(|&b| match b {
+                    0x80..=0x9F => CP1252_HIGH[usize::from(b - 0x80)],
+                    _ => char::from(b),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// UTF-16 without a BOM, recognised by the NULs that pad ASCII characters:
+/// the high half of most code units is zero, the low half rarely is.
+fn guess_utf16(sniff: &[u8]) -> Option<Encoding> {
+    let (pairs, _) = sniff.as_chunks::<2>();
+    if pairs.len() < 2 {
+        return None;
+    }
+    let even = pairs.iter().filter(|[a, _]| *a == 0).count();
+    let odd = pairs.iter().filter(|[_, b]| *b == 0).count();
+    let common = |n: usize| n * 10 >= pairs.len() * MIN_UTF16_NUL_TENTHS;
+    let rare = |n: usize| n * 10 < pairs.len();
+    if common(odd) && rare(even) {
+        Some(Encoding::Utf16Le)
+    } else if common(even) && rare(odd) {
+        Some(Encoding::Utf16Be)
+    } else {
+        None
+    }
+}
+
+fn decode_utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> String {
+    let (pairs, rest) = bytes.as_chunks::<2>();
+    let mut text: String = char::decode_utf16(pairs.iter().map(|&pair| unit(pair)))
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect();
+    if !rest.is_empty() {
+        text.push(char::REPLACEMENT_CHARACTER);
+    }
+    text
+}
+
+/// Whether decoded `text` has few enough control characters (other than
+/// whitespace and escape) and replacement characters to be text.
+fn looks_like_text(text: &str) -> bool {
+    let mut chars = 0;
+    let mut suspect = 0;
+    for c in text.chars() {
+        chars += 1;
+        let control = c.is_control() && !matches!(c, '\t' | '\n' | '\r' | '\x0C' | '\x1B');
+        if control || c == char::REPLACEMENT_CHARACTER {
+            suspect += 1;
+        }
+    }
+    // A character cut off at the end of the sniff is not suspect.
+    if text.ends_with(char::REPLACEMENT_CHARACTER) {
+        suspect -= 1;
+    }
+    suspect * 100 <= chars * MAX_CONTROL_PERCENT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    fn utf16be(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_be_bytes).collect()
+    }
+
+    #[rstest]
+    #[case::utf8(b"fn main() {}\n".to_vec(), Some(Encoding::Utf8))]
+    #[case::utf8_bom(b"\xEF\xBB\xBFname,value\n".to_vec(), Some(Encoding::Utf8Bom))]
+    #[case::utf16le_bom([UTF16LE_BOM, &utf16le("<root/>\r\n")].concat(), Some(Encoding::Utf16Le))]
+    #[case::utf16be_bom([UTF16BE_BOM, &utf16be("<root/>\r\n")].concat(), Some(Encoding::Utf16Be))]
+    #[case::utf16le_no_bom(
+        utf16le("Microsoft Visual Studio Solution File"),
+        Some(Encoding::Utf16Le)
+    )]
+    #[case::utf16be_no_bom(
+        utf16be("Microsoft Visual Studio Solution File"),
+        Some(Encoding::Utf16Be)
+    )]
+    #[case::latin1(b"/* Fran\xE7ois, \x93quoted\x94 */\n".to_vec(), Some(Encoding::Latin1))]
+    #[case::nul_bytes(b"ab\0cd\0\0\0ef".to_vec(), None)]
+    #[case::c1_controls(b"\x80\x81\x82\x83".to_vec(), None)]
+    #[case::control_bytes(b"\x01\x02\x03\x04\xff\xfe\xfd".to_vec(), None)]
+    fn detects_encoding(#[case] sniff: Vec<u8>, #[case] expected: Option<Encoding>) {
+        let utf8 = std::str::from_utf8(&sniff).is_ok();
+        assert_eq!(Encoding::detect(&sniff, utf8), expected);
+    }
+
+    #[rstest]
+    #[case::utf8_bom(Encoding::Utf8Bom, b"\xEF\xBB\xBFcaf\xC3\xA9".to_vec())]
+    #[case::utf16le(Encoding::Utf16Le, [UTF16LE_BOM, &utf16le("café")].concat())]
+    #[case::utf16be(Encoding::Utf16Be, utf16be("café"))]
+    #[case::latin1(Encoding::Latin1, b"caf\xE9".to_vec())]
+    fn decodes_to_utf8(#[case] encoding: Encoding, #[case] bytes: Vec<u8>) {
+        assert_eq!(encoding.decode_lossy(&bytes), "café");
+    }
+
+    #[test]
+    fn latin1_reads_windows_1252_quotes() {
+        assert_eq!(Encoding::Latin1.decode_lossy(b"\x93hi\x94 \x80"), "“hi” €");
+    }
+
+    /// UTF-16 is never cut between the halves of a surrogate pair.
+    #[test]
+    fn utf16_boundaries_keep_surrogate_pairs() {
+        let bytes = utf16le("a😀b");
+        assert!(Encoding::Utf16Le.is_boundary(&bytes, 2));
+        assert!(!Encoding::Utf16Le.is_boundary(&bytes, 3));
+        assert!(!Encoding::Utf16Le.is_boundary(&bytes, 4));
+        assert!(Encoding::Utf16Le.is_boundary(&bytes, 6));
+        assert!(Encoding::Utf16Le.ends_line(&utf16le("a\n"), 4));
+        assert!(!Encoding::Utf16Le.ends_line(&utf16le("\u{a00}"), 2));
+    }
+}
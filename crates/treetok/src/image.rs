@@ -0,0 +1,318 @@
+//! Image dimensions from file headers, and the per-model formulas that turn
+//! them into prompt tokens.
+//!
+//! Only the header is read: the formulas depend on width and height alone,
+//! so pixel data is never decoded.
+
+/// A recognised image container.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// Portable Network Graphics.
+    Png,
+    /// JPEG / JFIF / Exif.
+    Jpeg,
+    /// `GIF87a` / `GIF89a`.
+    Gif,
+    /// WebP (lossy, lossless, or extended).
+    Webp,
+}
+
+impl ImageFormat {
+    /// Lower-case name used in JSON output.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg => "jpeg",
+            Self::Gif => "gif",
+            Self::Webp => "webp",
+        }
+    }
+
+    /// Identify the container from its magic bytes.
+    #[must_use]
+    pub fn sniff(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(Self::Png)
+        } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            Some(Self::Jpeg)
+        } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+            Some(Self::Gif)
+        } else if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+            Some(Self::Webp)
+        } else {
+            None
+        }
+    }
+}
+
+/// An image's container and pixel dimensions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageInfo {
+    /// Container format.
+    pub format: ImageFormat,
+    /// Width in pixels.
+    pub width: u32,
+    /// Height in pixels.
+    pub height: u32,
+}
+
+impl ImageInfo {
+    /// Parse the dimensions from the start of an image file.  Returns `None`
+    /// for unrecognised formats, truncated headers, and zero-sized images.
+    #[must_use]
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let format = ImageFormat::sniff(bytes)?;
+        let (width, height) = match format {
+            ImageFormat::Png => png_size(bytes),
+            ImageFormat::Jpeg => jpeg_size(bytes),
+            ImageFormat::Gif => Some((u32::from(le16(bytes, 6)?), u32::from(le16(bytes, 8)?))),
+            ImageFormat::Webp => webp_size(bytes),
+        }?;
+        (width > 0 && height > 0).then_some(Self {
+            format,
+            width,
+            height,
+        })
+    }
+}
+
+fn be16(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn le16(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn be32(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn le24(bytes: &[u8], at: usize) -> Option<u32> {
+    let b = bytes.get(at..at + 3)?;
+    Some(u32::from(b[0]) | (u32::from(b[1]) << 8) | (u32::from(b[2]) << 16))
+}
+
+/// `IHDR` is always the first chunk: width and height follow its tag.
+fn png_size(bytes: &[u8]) -> Option<(u32, u32)> {
+    (bytes.get(12..16)? == b"IHDR").then_some(())?;
+    Some((be32(bytes, 16)?, be32(bytes, 20)?))
+}
+
+/// Walk the marker segments to the first start-of-frame, which may follow
+/// Exif or ICC segments of any length.
+fn jpeg_size(bytes: &[u8]) -> Option<(u32, u32)> {
+    let mut at = 2;
+    loop {
+        // Markers may be preceded by any number of 0xFF fill bytes.
+        while *bytes.get(at)? == 0xFF && *bytes.get(at + 1)? == 0xFF {
+            at += 1;
+        }
+        if *bytes.get(at)? != 0xFF {
+            return None;
+        }
+        let marker = *bytes.get(at + 1)?;
+        let len = usize::from(be16(bytes, at + 2)?);
+        // SOF0–SOF15, except DHT (C4), JPG (C8), and DAC (CC).
+        if (0xC0..=0xCF).contains(&marker) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+            let height = be16(bytes, at + 5)?;
+            let width = be16(bytes, at + 7)?;
+            return Some((u32::from(width), u32::from(height)));
+        }
+        at += 2 + len;
+    }
+}
+
+/// The first chunk after the RIFF header says which WebP flavour this is.
+fn webp_size(bytes: &[u8]) -> Option<(u32, u32)> {
+    match bytes.get(12..16)? {
+        b"VP8 " => {
+            // Lossy: 14-bit sizes after the frame tag and start code.
+            let width = le16(bytes, 26)? & 0x3FFF;
+            let height = le16(bytes, 28)? & 0x3FFF;
+            Some((u32::from(width), u32::from(height)))
+        }
+        b"VP8L" => {
+            // Lossless: two 14-bit (size − 1) fields after the signature byte.
+            let b = bytes.get(21..25)?;
+            let bits = u32::from_le_bytes(b.try_into().ok()?);
+            Some(((bits & 0x3FFF) + 1, ((bits >> 14) & 0x3FFF) + 1))
+        }
+        b"VP8X" => Some((le24(bytes, 24)? + 1, le24(bytes, 27)? + 1)),
+        _ => None,
+    }
+}
+
+// ─── Per-model formulas ──────────────────────────────────────────────────────
+
+/// Scale `(w, h)` down, preserving aspect ratio, so the long edge is at most
+/// `max`.
+fn fit_long_edge(w: u64, h: u64, max: u64) -> (u64, u64) {
+    let long = w.max(h);
+    if long <= max {
+        return (w, h);
+    }
+    ((w * max / long).max(1), (h * max / long).max(1))
+}
+
+/// Claude: `w × h / 750` after resizing so the long edge is at most 1568 px.
+/// Larger images are scaled down to about 1.2 MP, so one image costs at most
+/// 1600 tokens.
+#[must_use]
+pub fn claude_tokens(width: u32, height: u32) -> usize {
+    const MAX_EDGE: u64 = 1568;
+    const PIXELS_PER_TOKEN: u64 = 750;
+    const MAX_TOKENS: u64 = 1600;
+    let (w, h) = fit_long_edge(width.into(), height.into(), MAX_EDGE);
+    let tokens = (w * h).div_ceil(PIXELS_PER_TOKEN).min(MAX_TOKENS);
+    usize::try_from(tokens).unwrap_or(usize::MAX)
+}
+
+/// `OpenAI` (GPT-4o family, high detail): fit within 2048 × 2048, scale the
+/// short side down to 768 px, then charge 170 tokens per 512 px tile plus a
+/// base of 85.
+#[must_use]
+pub fn openai_tokens(width: u32, height: u32) -> usize {
+    const MAX_EDGE: u64 = 2048;
+    const SHORT_EDGE: u64 = 768;
+    const TILE: u64 = 512;
+    const BASE: u64 = 85;
+    const PER_TILE: u64 = 170;
+    let (w, h) = fit_long_edge(width.into(), height.into(), MAX_EDGE);
+    let short = w.min(h);
+    let (w, h) = if short > SHORT_EDGE {
+        (
+            (w * SHORT_EDGE / short).max(1),
+            (h * SHORT_EDGE / short).max(1),
+        )
+    } else {
+        (w, h)
+    };
+    let tiles = w.div_ceil(TILE) * h.div_ceil(TILE);
+    usize::try_from(BASE + PER_TILE * tiles).unwrap_or(usize::MAX)
+}
+
+/// Tokens Gemini charges per image tile, and per PDF page.
+pub const GEMINI_TILE_TOKENS: usize = 258;
+
+/// Gemini: [`GEMINI_TILE_TOKENS`] for an image within 384 × 384, otherwise
+/// that much per 768 × 768 tile.
+#[must_use]
+pub fn gemini_tokens(width: u32, height: u32) -> usize {
+    const SMALL: u32 = 384;
+    const TILE: u32 = 768;
+    if width <= SMALL && height <= SMALL {
+        return GEMINI_TILE_TOKENS;
+    }
+    let tiles = width.div_ceil(TILE) as usize * height.div_ceil(TILE) as usize;
+    GEMINI_TILE_TOKENS * tiles
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    /// A minimal PNG header (signature + IHDR) for a `w × h` image.
+    pub fn png_header(w: u32, h: u32) -> Vec<u8> {
+        let mut b = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        b.extend_from_slice(&w.to_be_bytes());
+        b.extend_from_slice(&h.to_be_bytes());
+        b.extend_from_slice(&[8, 6, 0, 0, 0]);
+        b
+    }
+
+    fn jpeg_header(w: u16, h: u16) -> Vec<u8> {
+        let mut b = vec![0xFF, 0xD8];
+        // An APP1 segment to skip over.
+        b.extend_from_slice(&[0xFF, 0xE1, 0x00, 0x06, b'E', b'x', b'i', b'f']);
+        b.extend_from_slice(&[0xFF, 0xC2, 0x00, 0x11, 0x08]);
+        b.extend_from_slice(&h.to_be_bytes());
+        b.extend_from_slice(&w.to_be_bytes());
+        b.extend_from_slice(&[0x03; 12]);
+        b
+    }
+
+    fn gif_header(w: u16, h: u16) -> Vec<u8> {
+        let mut b = b"GIF89a".to_vec();
+        b.extend_from_slice(&w.to_le_bytes());
+        b.extend_from_slice(&h.to_le_bytes());
+        b
+    }
+
+    fn webp_header(chunk: [u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut b = b"RIFF\0\0\0\0WEBP".to_vec();
+        b.extend_from_slice(&chunk);
+        b.extend_from_slice(&[0; 4]);
+        b.extend_from_slice(payload);
+        b
+    }
+
+    fn info(format: ImageFormat, width: u32, height: u32) -> ImageInfo {
+        ImageInfo {
+            format,
+            width,
+            height,
+        }
+    }
+
+    #[rstest]
+    #[case::png(png_header(640, 480), info(ImageFormat::Png, 640, 480))]
+    #[case::jpeg(jpeg_header(1920, 1080), info(ImageFormat::Jpeg, 1920, 1080))]
+    #[case::gif(gif_header(32, 16), info(ImageFormat::Gif, 32, 16))]
+    #[case::webp_lossy(
+        // Frame tag, start code, then 14-bit width/height.
+        webp_header(*b"VP8 ", &[0, 0, 0, 0x9D, 0x01, 0x2A, 0x20, 0x03, 0x58, 0x02]),
+        info(ImageFormat::Webp, 800, 600)
+    )]
+    #[case::webp_lossless(
+        // Signature, then (w − 1) | (h − 1) << 14 = 99 | 49 << 14.
+        webp_header(*b"VP8L", &[0x2F, 0x63, 0x40, 0x0C, 0x00]),
+        info(ImageFormat::Webp, 100, 50)
+    )]
+    #[case::webp_extended(
+        webp_header(*b"VP8X", &[0, 0, 0, 0, 0x7F, 0x07, 0x00, 0x37, 0x04, 0x00]),
+        info(ImageFormat::Webp, 1920, 1080)
+    )]
+    fn header_dimensions_are_parsed(#[case] bytes: Vec<u8>, #[case] expected: ImageInfo) {
+        assert_eq!(ImageInfo::parse(&bytes), Some(expected));
+    }
+
+    #[rstest]
+    #[case::not_an_image(b"hello world".to_vec())]
+    #[case::truncated_png(png_header(1, 1)[..18].to_vec())]
+    #[case::zero_sized(png_header(0, 10))]
+    #[case::jpeg_without_frame(vec![0xFF, 0xD8, 0xFF, 0xD9])]
+    fn bad_headers_are_rejected(#[case] bytes: Vec<u8>) {
+        assert_eq!(ImageInfo::parse(&bytes), None);
+    }
+
+    #[rstest]
+    // Small images are w × h / 750.
+    #[case(200, 200, 54)]
+    #[case(1000, 1000, 1334)]
+    // 1568 × 1568 is over the pixel cap.
+    #[case(4000, 4000, 1600)]
+    fn claude_formula(#[case] w: u32, #[case] h: u32, #[case] tokens: usize) {
+        assert_eq!(claude_tokens(w, h), tokens);
+    }
+
+    #[rstest]
+    // OpenAI's worked examples: 1024² → 768² (4 tiles), 2048 × 4096 →
+    // 1024 × 2048 → 768 × 1536 (6 tiles).
+    #[case(1024, 1024, 765)]
+    #[case(2048, 4096, 1105)]
+    #[case(100, 100, 255)]
+    fn openai_formula(#[case] w: u32, #[case] h: u32, #[case] tokens: usize) {
+        assert_eq!(openai_tokens(w, h), tokens);
+    }
+
+    #[rstest]
+    #[case(384, 384, 258)]
+    #[case(385, 100, 258)]
+    #[case(1920, 1080, 258 * 6)]
+    fn gemini_formula(#[case] w: u32, #[case] h: u32, #[case] tokens: usize) {
+        assert_eq!(gemini_tokens(w, h), tokens);
+    }
+}
@@ -1,6 +1,9 @@
 //! treetok library — directory walking and LLM token counting.
 
+pub mod encoding;
+pub mod image;
 pub mod output;
+pub mod pdf;
 pub mod tokenize;
 pub mod tree;
 pub mod walk;
@@ -4,9 +4,9 @@ use std::collections::BTreeMap;
 use std::io::IsTerminal;
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
-use treetok::output::{CountFormat, OutputOptions, TokenCount};
+use treetok::output::{CountFormat, OutputOptions, Threshold, TokenCount};
 use treetok::tokenize::TokenizerId;
 use treetok::{output, tokenize, walk};
 
@@ -16,17 +16,44 @@ use treetok::{output, tokenize, walk};
 #[command(
     name = "treetok",
     about = "Display directory trees with LLM token counts",
-    version
+    version,
+    args_conflicts_with_subcommands = true
 )]
 #[allow(clippy::struct_excessive_bools)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Paths to display (default: current directory).
     paths: Vec<PathBuf>,
 
-    /// Tokenizer(s) to use (repeatable).  Available: o200k, claude.
+    /// Tokenizer(s) to use (repeatable).  Available: o200k, `o200k_base`, cl100k,
+    /// p50k, `p50k_edit`, r50k, gemini, claude, ctoc,
+    /// a `HuggingFace` tokenizer.json as `hf:PATH`, a tiktoken rank file as
+    /// `tiktoken:PATH`, or a llama.cpp / vLLM / TGI server as `server:URL`.
+    /// Prefix these specs with `NAME=` to set the column label.
     #[arg(short = 't', value_name = "TOKENIZER")]
     tokenizers: Vec<String>,
 
+    /// Count for a model by name, e.g. `gpt-4o` or `claude-sonnet-4-6`
+    /// (repeatable).  Adds the model's tokenizer alongside any `-t`.
+    #[arg(short = 'm', long = "model", value_name = "MODEL")]
+    models: Vec<String>,
+
+    /// Pre-tokenization regex for `tiktoken:` tokenizers (default: o200k's).
+    #[arg(long, value_name = "REGEX")]
+    tiktoken_pattern: Option<String>,
+
+    /// Special token for `tiktoken:` tokenizers as `TOKEN=RANK` (repeatable);
+    /// counted as one token where it appears in a file.
+    #[arg(long, value_name = "TOKEN=RANK", value_parser = parse_special_token)]
+    tiktoken_special: Vec<(String, u32)>,
+
+    /// Show usage against a context window: a token count (`200000`, `200k`)
+    /// or a model name whose window is used (e.g. `gpt-4o`).
+    #[arg(long, value_name = "N|MODEL", conflicts_with = "count")]
+    budget: Option<String>,
+
     /// Output only the total token count as a bare number.
     #[arg(long, conflicts_with_all = ["json", "flat", "sort"])]
     count: bool,
@@ -47,23 +74,268 @@ struct Cli {
     #[arg(long)]
     no_ignore: bool,
 
-    /// Limit tree depth.
-    #[arg(long, value_name = "N")]
+    /// Limit the depth of the displayed tree (or flat list).  Deeper files
+    /// are still counted, collapsed into one summary row per directory.
+    #[arg(long, value_name = "N", conflicts_with = "count")]
     depth: Option<usize>,
 
-    /// Skip online tokenizers (Claude) even if `ANTHROPIC_API_KEY` is set.
+    /// Show one row per directory with its subtotal and file count, like
+    /// `du`.  Files below `--depth` count toward their ancestors.
+    #[arg(long, visible_alias = "dirs-only", conflicts_with = "count")]
+    summarize: bool,
+
+    /// Hide files with fewer than N tokens.  The hidden files of each
+    /// directory are folded into one "… more files" row.
+    #[arg(long, value_name = "N", conflicts_with_all = ["count", "summarize", "json"])]
+    min_tokens: Option<usize>,
+
+    /// Show only the N largest files of each directory (of the whole list
+    /// with `--flat` or `--top-global`).  The rest are folded into one
+    /// "… more files" row.
+    #[arg(long, value_name = "N", conflicts_with_all = ["count", "summarize", "json"])]
+    top: Option<usize>,
+
+    /// Rank `--top` across the whole tree instead of per directory.
+    #[arg(long, requires = "top")]
+    top_global: bool,
+
+    /// Show each row's share of the total token count.
+    #[arg(long, conflicts_with_all = ["count", "json"])]
+    percent: bool,
+
+    /// Show a bar per file scaled to the largest (per directory with
+    /// `--summarize`).
+    #[arg(long, conflicts_with_all = ["count", "json"])]
+    bars: bool,
+
+    /// Colour file counts at or above this yellow: a token count (`4000`,
+    /// `4k`) or a percentile of all file counts (`p90`).
+    #[arg(
+        long,
+        value_name = "N|pN",
+        value_parser = parse_threshold,
+        conflicts_with_all = ["count", "json"]
+    )]
+    warn: Option<Threshold>,
+
+    /// Colour file counts at or above this red, like `--warn`.
+    #[arg(
+        long,
+        value_name = "N|pN",
+        value_parser = parse_threshold,
+        conflicts_with_all = ["count", "json"]
+    )]
+    crit: Option<Threshold>,
+
+    /// Only count files matching GLOB, gitignore-style (repeatable).
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+
+    /// Skip files and directories matching GLOB (repeatable).
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// Only count files of TYPE, e.g. `rust` or `py` (repeatable).  Uses
+    /// ripgrep's type names.
+    #[arg(long = "type", value_name = "TYPE")]
+    types: Vec<String>,
+
+    /// Skip files of TYPE, e.g. `md` (repeatable).
+    #[arg(long = "type-not", value_name = "TYPE")]
+    types_not: Vec<String>,
+
+    /// Largest file to count, e.g. `500K`, `1G`, or `none` for no limit.
+    /// Text above 3 MB is counted in chunks; images are never too large.
+    #[arg(long, value_name = "SIZE", default_value = "100M", value_parser = parse_max_size)]
+    max_size: u64,
+
+    /// Skip online tokenizers (Claude, Gemini) even if an API key is set.
     #[arg(long)]
     offline: bool,
 
+    /// Anthropic API root, e.g. a gateway or local stub.
+    #[arg(long, value_name = "URL", env = "TREETOK_API_BASE")]
+    api_base: Option<String>,
+
+    /// Count Gemini with Google's `countTokens` API (uploads file contents;
+    /// needs `GEMINI_API_KEY` or `GOOGLE_API_KEY`).
+    #[arg(long)]
+    gemini_api: bool,
+
+    /// Generative Language API root for Gemini counts, e.g. a local stub.
+    #[arg(long, value_name = "URL", env = "TREETOK_GEMINI_API_BASE")]
+    gemini_api_base: Option<String>,
+
+    /// Header that carries the API key (default: `x-api-key`).  With
+    /// `Authorization`, the key is sent as a bearer token.
+    #[arg(long, value_name = "NAME", value_parser = parse_header_name)]
+    auth_header: Option<String>,
+
+    /// Extra header sent with every Claude request (repeatable).
+    #[arg(long, value_name = "NAME:VALUE", value_parser = parse_api_header)]
+    api_header: Vec<(String, String)>,
+
+    /// Most requests in flight at once per API tokenizer (Claude, `server:`);
+    /// the rate-limit scheduler adapts below this as the API pushes back.
+    #[arg(long, value_name = "N", default_value_t = tokenize::DEFAULT_CONCURRENCY,
+          value_parser = parse_concurrency)]
+    concurrency: usize,
+
+    /// Retries of a throttled API request before giving up on the file.
+    #[arg(long, value_name = "N", default_value_t = tokenize::DEFAULT_MAX_RETRIES)]
+    max_retries: u32,
+
+    /// Always query the Claude and Gemini APIs instead of reusing cached
+    /// counts.
+    #[arg(long)]
+    no_cache: bool,
+
     /// Disable ANSI colors.
     #[arg(long)]
     no_color: bool,
 }
 
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Manage the on-disk cache of API token counts.
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum CacheAction {
+    /// Delete every cached count.
+    Clear,
+    /// Show the cache location, size, and entries per model.
+    Stats,
+}
+
 // ─── Helpers ──────────────────────────────────────────────────────────────────
 
-fn read_stdin_or_exit() -> walk::WalkResult {
-    match walk::read_stdin() {
+/// Parse a `--tiktoken-special` value.  Splits on the last `=` so the token
+/// text itself may contain one.
+fn parse_special_token(s: &str) -> Result<(String, u32), String> {
+    let (token, rank) = s
+        .rsplit_once('=')
+        .ok_or_else(|| format!("expected TOKEN=RANK, got {s:?}"))?;
+    if token.is_empty() {
+        return Err("special token text is empty".to_string());
+    }
+    let rank = rank
+        .parse()
+        .map_err(|e| format!("bad rank {rank:?}: {e}"))?;
+    Ok((token.to_string(), rank))
+}
+
+/// Parse a `--warn` / `--crit` value: a token count (`4000`, `4k`) or a
+/// percentile (`p90`).
+fn parse_threshold(s: &str) -> Result<Threshold, String> {
+    if let Some(pct) = s.strip_prefix('p') {
+        return match pct.parse::<f64>() {
+            Ok(p) if p > 0.0 && p <= 100.0 => Ok(Threshold::Percentile(p)),
+            _ => Err(format!(
+                "bad percentile {s:?}: expected 0 < N <= 100, e.g. p90"
+            )),
+        };
+    }
+    let (digits, scale) = s
+        .strip_suffix(['k', 'K'])
+        .map_or((s, 1), |digits| (digits, 1_000));
+    digits
+        .replace('_', "")
+        .parse::<usize>()
+        .map(|n| Threshold::Tokens(n.saturating_mul(scale)))
+        .map_err(|e| format!("bad threshold {s:?}: {e}"))
+}
+
+/// Parse a `--concurrency` value: a positive integer.
+fn parse_concurrency(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(0) => Err("concurrency must be at least 1".to_string()),
+        Ok(n) => Ok(n),
+        Err(e) => Err(format!("bad concurrency {s:?}: {e}")),
+    }
+}
+
+/// Parse a `--max-size` value: bytes with an optional `K`/`M`/`G` suffix
+/// (binary multiples, optionally followed by `B` or `iB`), or `none`.
+fn parse_max_size(s: &str) -> Result<u64, String> {
+    if s.eq_ignore_ascii_case("none") {
+        return Ok(u64::MAX);
+    }
+    let upper = s.trim().to_ascii_uppercase();
+    let number = upper.trim_end_matches("IB").trim_end_matches('B');
+    let (digits, shift) = match number.char_indices().last() {
+        Some((i, 'K')) => (&number[..i], 10),
+        Some((i, 'M')) => (&number[..i], 20),
+        Some((i, 'G')) => (&number[..i], 30),
+        _ => (number, 0),
+    };
+    let n: u64 = digits
+        .trim()
+        .parse()
+        .map_err(|_| format!("bad size {s:?}: expected e.g. 500K, 3M, 1G, or none"))?;
+    n.checked_mul(1 << shift)
+        .ok_or_else(|| format!("size {s:?} is too large"))
+}
+
+/// Validate an HTTP header name for `--auth-header` / `--api-header`.
+fn parse_header_name(s: &str) -> Result<String, String> {
+    reqwest::header::HeaderName::from_bytes(s.as_bytes())
+        .map(|_| s.to_string())
+        .map_err(|_| format!("invalid header name {s:?}"))
+}
+
+/// Parse an `--api-header` value.  Splits on the first `:`, so values may
+/// contain colons (URLs, timestamps).
+fn parse_api_header(s: &str) -> Result<(String, String), String> {
+    let (name, value) = s
+        .split_once(':')
+        .ok_or_else(|| format!("expected NAME:VALUE, got {s:?}"))?;
+    let value = value.trim();
+    reqwest::header::HeaderValue::from_str(value)
+        .map_err(|_| format!("invalid value for header {name:?}"))?;
+    Ok((parse_header_name(name.trim())?, value.to_string()))
+}
+
+/// Run `treetok cache <action>` and exit.
+fn run_cache_command(action: &CacheAction) -> ! {
+    let Some(dir) = tokenize::CountCache::default_dir() else {
+        eprintln!("error: cannot locate the cache directory (set XDG_CACHE_HOME or HOME)");
+        std::process::exit(exitcode::CONFIG);
+    };
+    let result = match action {
+        CacheAction::Clear => tokenize::CountCache::clear(&dir).map(|removed| {
+            if removed {
+                println!("cleared {}", dir.display());
+            } else {
+                println!("cache already empty");
+            }
+        }),
+        CacheAction::Stats => tokenize::CountCache::stats(&dir).map(|stats| {
+            println!("path:    {}", stats.path.display());
+            println!("entries: {}", output::format_number(stats.entries()));
+            println!(
+                "size:    {} bytes",
+                output::format_number(usize::try_from(stats.bytes).unwrap_or(usize::MAX))
+            );
+            let width = stats.models.keys().map(String::len).max().unwrap_or(0);
+            for (model, n) in &stats.models {
+                println!("  {model:<width$}  {}", output::format_number(*n));
+            }
+        }),
+    };
+    if let Err(e) = result {
+        eprintln!("error: {}: {e}", dir.display());
+        std::process::exit(exitcode::IOERR);
+    }
+    std::process::exit(exitcode::OK);
+}
+
+fn read_stdin_or_exit(max_size: u64) -> walk::WalkResult {
+    match walk::read_stdin(max_size) {
         Ok(r) => r,
         Err(e) => {
             eprintln!("error reading stdin: {e}");
@@ -77,6 +349,25 @@ fn read_stdin_or_exit() -> walk::WalkResult {
 fn main() {
     let mut cli = Cli::parse();
 
+    if let Some(Command::Cache { action }) = &cli.command {
+        run_cache_command(action);
+    }
+
+    let filter = match walk::Filter::new(&cli.include, &cli.exclude, &cli.types, &cli.types_not) {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("{:?}", miette::Report::new(e));
+            std::process::exit(exitcode::USAGE);
+        }
+    };
+
+    if let (Some(warn), Some(crit)) = (cli.warn, cli.crit)
+        && warn.is_above(crit)
+    {
+        eprintln!("error: --warn must not be above --crit");
+        std::process::exit(exitcode::USAGE);
+    }
+
     let mut stdin_result: Option<walk::WalkResult> = None;
 
     // Handle explicit `-` path.
@@ -87,12 +378,12 @@ fn main() {
     }
     if dash_count == 1 {
         cli.paths.retain(|p| p.as_os_str() != "-");
-        stdin_result = Some(read_stdin_or_exit());
+        stdin_result = Some(read_stdin_or_exit(cli.max_size));
     }
 
     // Auto-detect piped stdin when no paths given.
     if cli.paths.is_empty() && stdin_result.is_none() && !std::io::stdin().is_terminal() {
-        stdin_result = Some(read_stdin_or_exit());
+        stdin_result = Some(read_stdin_or_exit(cli.max_size));
     }
 
     // Default to "." if no paths and no stdin.
@@ -100,17 +391,51 @@ fn main() {
         cli.paths.push(".".into());
     }
 
-    let api_key = if cli.offline {
-        None
+    let (api_key, gemini_api_key) = if cli.offline {
+        (None, None)
     } else {
-        tokenize::load_api_key()
+        (
+            tokenize::load_api_key(),
+            tokenize::load_gemini_api_key().filter(|_| cli.gemini_api),
+        )
+    };
+    let resolve_opts = tokenize::ResolveOptions {
+        offline: cli.offline,
+        api_key,
+        tiktoken_pattern: cli.tiktoken_pattern,
+        tiktoken_specials: cli.tiktoken_special,
+        models: cli.models,
+        cache_dir: if cli.no_cache {
+            None
+        } else {
+            tokenize::CountCache::default_dir()
+        },
+        api_base: cli.api_base,
+        auth_header: cli.auth_header,
+        api_headers: cli.api_header,
+        concurrency: Some(cli.concurrency),
+        max_retries: Some(cli.max_retries),
+        gemini_api: cli.gemini_api,
+        gemini_api_key,
+        gemini_api_base: cli.gemini_api_base,
     };
-    let resolved = match tokenize::resolve_tokenizers(&cli.tokenizers, cli.offline, api_key) {
+    let resolved = match tokenize::resolve_tokenizers(&cli.tokenizers, &resolve_opts) {
         Ok(t) => t,
         Err(tokenize::TokenizeError::NoApiKey) => {
             eprintln!("error: ANTHROPIC_API_KEY is not set (required by -t claude)");
             std::process::exit(exitcode::UNAVAILABLE);
         }
+        Err(tokenize::TokenizeError::NoGeminiApiKey) => {
+            eprintln!("error: GEMINI_API_KEY is not set (required by --gemini-api)");
+            std::process::exit(exitcode::UNAVAILABLE);
+        }
+        Err(
+            e @ (tokenize::TokenizeError::UnknownModel { .. }
+            | tokenize::TokenizeError::ConflictingModels { .. }),
+        ) => {
+            eprintln!("{:?}", miette::Report::new(e));
+            std::process::exit(exitcode::USAGE);
+        }
         Err(e) => {
             eprintln!("error: {e}");
             std::process::exit(exitcode::USAGE);
@@ -124,12 +449,38 @@ fn main() {
         CountFormat::Named
     };
 
+    let budget = match cli
+        .budget
+        .as_deref()
+        .map(tokenize::resolve_budget)
+        .transpose()
+    {
+        Ok(b) => b,
+        Err(e) => {
+            eprintln!("{:?}", miette::Report::new(e));
+            std::process::exit(exitcode::USAGE);
+        }
+    };
+
     // Build output options (encapsulates color detection).
-    let out_opts = OutputOptions::new(cli.flat, cli.json, cli.sort, cli.no_color, count_format);
+    let out_opts = OutputOptions {
+        budget,
+        summarize: cli.summarize,
+        depth: cli.depth,
+        min_tokens: cli.min_tokens,
+        top: cli.top,
+        top_global: cli.top_global,
+        percent: cli.percent,
+        bars: cli.bars,
+        warn: cli.warn,
+        crit: cli.crit,
+        ..OutputOptions::new(cli.flat, cli.json, cli.sort, cli.no_color, count_format)
+    };
 
     let walk_opts = walk::WalkOptions {
         no_ignore: cli.no_ignore,
-        depth: cli.depth,
+        max_size: cli.max_size,
+        filter,
     };
     let mut walk_results = walk::walk_paths(&cli.paths, &walk_opts);
 
@@ -166,13 +166,89 @@ pub(super) fn format_counts(
     }
 }
 
-pub(super) fn format_tokens(entry: &FileResult, format: &CountFormat, color: bool) -> String {
+/// A file row's count block, e.g. `"[1,234] *"` or `"[binary]"`, with the
+/// counts coloured by `heat` (`--warn` / `--crit`) when given.
+pub(super) fn format_tokens(
+    entry: &FileResult,
+    format: &CountFormat,
+    color: bool,
+    heat: Option<Heat>,
+) -> String {
     match &entry.kind {
+        FileKind::Image(_) if entry.tokens.is_empty() => dim("[image]", color),
         FileKind::Binary => dim("[binary]", color),
         FileKind::TooLarge => dim("[too large]", color),
         FileKind::Error(msg) => dim(&format!("[error: {msg}]"), color),
-        FileKind::Text => format!("[{}]", format_counts(&entry.tokens, format)),
+        FileKind::Text(_) | FileKind::Chunked(_) | FileKind::Image(_) | FileKind::Pdf(_) => {
+            let counts = format!("[{}]", format_counts(&entry.tokens, format));
+            let counts = heat.map_or_else(|| counts.clone(), |heat| paint_heat(&counts, heat));
+            format!("{counts}{}", row_marker(entry, color))
+        }
+    }
+}
+
+/// Notes appended to a counted file's row: `(chunked)` for a file counted
+/// in pieces, then its [`fallback_marker`].
+pub(super) fn row_marker(entry: &FileResult, color: bool) -> String {
+    let chunked = if matches!(entry.kind, FileKind::Chunked(_)) {
+        format!(" {}", dim("(chunked)", color))
+    } else {
+        String::new()
+    };
+    chunked + &fallback_marker(!entry.fallback.is_empty(), color)
+}
+
+/// Marker appended to rows whose counts include a ctoc fallback estimate
+/// (see [`FileResult::fallback`]), or `""` when `flagged` is false.
+pub(super) fn fallback_marker(flagged: bool, color: bool) -> String {
+    if !flagged {
+        String::new()
+    } else if color {
+        format!(" {}", "*".yellow())
+    } else {
+        " *".to_string()
+    }
+}
+
+/// A `--summarize` file count, e.g. `"   12 files"`, with the number
+/// right-aligned to `width`.
+pub(super) fn format_file_count(n: usize, width: usize) -> String {
+    let noun = if n == 1 { "file" } else { "files" };
+    format!("  {:>width$} {noun}", format_number(n))
+}
+
+/// The row standing in for a directory's contents below `--depth`:
+/// `"… 24 files, 82,752 tokens"`, with the tokens as a min–max range over
+/// the active tokenizers.  Dim.
+pub(super) fn format_collapsed(
+    files: usize,
+    totals: &BTreeMap<TokenizerId, TokenCount>,
+    color: bool,
+) -> String {
+    let noun = if files == 1 { "file" } else { "files" };
+    let mut label = format!("\u{2026} {} {noun}", format_number(files));
+    if !totals.is_empty() {
+        let tokens = format_counts(totals, &CountFormat::Range);
+        label = format!("{label}, {tokens} tokens");
+    }
+    dim(&label, color)
+}
+
+/// The row standing in for files hidden by `--min-tokens` and `--top`:
+/// `"… 187 more files (12,304 tokens)"`, with the tokens as a min–max
+/// range over the active tokenizers.  Dim.
+pub(super) fn format_pruned(
+    files: usize,
+    totals: &BTreeMap<TokenizerId, TokenCount>,
+    color: bool,
+) -> String {
+    let noun = if files == 1 { "file" } else { "files" };
+    let mut label = format!("\u{2026} {} more {noun}", format_number(files));
+    if !totals.is_empty() {
+        let tokens = format_counts(totals, &CountFormat::Range);
+        label = format!("{label} ({tokens} tokens)");
     }
+    dim(&label, color)
 }
 
 pub(super) fn format_dir_label(name: &str, color: bool) -> String {
@@ -188,6 +264,108 @@ pub(super) fn format_dir_label(name: &str, color: bool) -> String {
     }
 }
 
+/// Share of the `--budget` window at which usage turns yellow.
+const BUDGET_WARN: f64 = 0.5;
+/// Share of the `--budget` window at which usage turns red.
+const BUDGET_CRIT: f64 = 0.9;
+
+/// Fraction of `budget` consumed by `used` tokens.
+pub(super) fn budget_fraction(used: usize, budget: usize) -> f64 {
+    used as f64 / budget.max(1) as f64
+}
+
+/// Format `used` as a percentage of `budget` (e.g. `"12.3%"`), coloured
+/// green / yellow / red by the warn and crit thresholds.
+pub(super) fn format_budget_pct(used: usize, budget: usize, color: bool) -> String {
+    let fraction = budget_fraction(used, budget);
+    let pct = format!("{:.1}%", fraction * 100.0);
+    if !color {
+        pct
+    } else if fraction >= BUDGET_CRIT {
+        paint_heat(&pct, Heat::Hot)
+    } else if fraction >= BUDGET_WARN {
+        paint_heat(&pct, Heat::Warm)
+    } else {
+        paint_heat(&pct, Heat::Cool)
+    }
+}
+
+/// Where a count falls against a pair of warn / crit thresholds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Heat {
+    /// Below warn: green.
+    Cool,
+    /// At or above warn: yellow.
+    Warm,
+    /// At or above crit: red.
+    Hot,
+}
+
+/// Colour `s` green / yellow / red by `heat`.
+pub(super) fn paint_heat(s: &str, heat: Heat) -> String {
+    match heat {
+        Heat::Cool => s.green().to_string(),
+        Heat::Warm => s.yellow().to_string(),
+        Heat::Hot => s.red().to_string(),
+    }
+}
+
+/// Format the TOTAL row's budget annotation: `"12.3% of 200,000"`, followed
+/// by an overflow marker when `used` exceeds the window.
+pub(super) fn format_budget_total(used: usize, budget: usize, color: bool) -> String {
+    let pct = format_budget_pct(used, budget, color);
+    let summary = format!("{pct} of {}", format_number(budget));
+    if used <= budget {
+        return summary;
+    }
+    let marker = format!("OVER BUDGET by {}", format_number(used - budget));
+    if color {
+        format!("{summary}  {}", marker.red().bold())
+    } else {
+        format!("{summary}  {marker}")
+    }
+}
+
+/// Width of the `--bars` column, in characters.
+const BAR_WIDTH: usize = 20;
+/// Partial blocks for the last character of a bar, one to seven eighths.
+const BAR_EIGHTHS: [char; 7] = ['▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+
+/// `used` as a percentage of `total` for `--percent`, right-aligned to the
+/// width of `"100.0%"`.
+#[allow(clippy::cast_precision_loss)]
+pub(super) fn format_share_pct(used: usize, total: usize) -> String {
+    let pct = format!("{:.1}%", used as f64 / total.max(1) as f64 * 100.0);
+    format!("{pct:>6}")
+}
+
+/// A `--bars` bar of Unicode blocks, `BAR_WIDTH` long for `largest` and
+/// scaled down in eighths for smaller `used`.  Any non-zero count gets at
+/// least an eighth.
+pub(super) fn format_bar(used: usize, largest: usize) -> String {
+    let eighths = (used * BAR_WIDTH * 8).div_ceil(largest.max(1));
+    let mut bar = "█".repeat(eighths / 8);
+    if !eighths.is_multiple_of(8) {
+        bar.push(BAR_EIGHTHS[eighths % 8 - 1]);
+    }
+    bar
+}
+
+/// Display width of `s`: its characters, less any ANSI colour codes.
+pub(super) fn display_width(s: &str) -> usize {
+    let mut width = 0;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // `ESC [ … m`: skip through the final byte.
+            chars.by_ref().find(char::is_ascii_alphabetic);
+        } else {
+            width += 1;
+        }
+    }
+    width
+}
+
 fn dim(s: &str, color: bool) -> String {
     if color {
         s.dimmed().to_string()
@@ -198,24 +376,29 @@ fn dim(s: &str, color: bool) -> String {
 
 #[cfg(test)]
 mod tests {
-    use std::collections::BTreeMap;
+    use std::collections::{BTreeMap, BTreeSet};
 
     use rstest::{fixture, rstest};
 
-    use super::super::{CountFormat, FileResult, OutputOptions, TokenCount, write_output};
-    use super::format_number;
-    use crate::tokenize::TokenizerId;
+    use super::super::{
+        CountFormat, FileResult, OutputOptions, Threshold, TokenCount, write_output,
+    };
+    use super::{Heat, dim, display_width, format_bar, format_number, paint_heat};
+    use crate::encoding::Encoding;
+    use crate::tokenize::{ResolveOptions, TokenizerId, resolve_tokenizers, tokenize_entries};
 
     // ── fixtures and helpers ───────────────────────────────────────────────
 
     fn text_result(path: &str, counts: &[(&str, usize)]) -> FileResult {
         FileResult {
             rel_path: path.into(),
-            kind: crate::walk::FileKind::Text,
+            kind: crate::walk::FileKind::Text(Encoding::Utf8),
             tokens: counts
                 .iter()
                 .map(|(k, v)| (k.parse::<TokenizerId>().unwrap(), TokenCount::Exact(*v)))
                 .collect(),
+            wrapped: BTreeMap::new(),
+            fallback: BTreeSet::new(),
         }
     }
 
@@ -224,6 +407,8 @@ mod tests {
             rel_path: path.into(),
             kind: crate::walk::FileKind::Binary,
             tokens: BTreeMap::new(),
+            wrapped: BTreeMap::new(),
+            fallback: BTreeSet::new(),
         }
     }
 
@@ -231,12 +416,14 @@ mod tests {
     fn approx_entry() -> FileResult {
         FileResult {
             rel_path: "f.rs".into(),
-            kind: crate::walk::FileKind::Text,
+            kind: crate::walk::FileKind::Text(Encoding::Utf8),
             tokens: [
                 (TokenizerId::Ctoc, TokenCount::from_approx(6_000)),
                 (TokenizerId::O200k, TokenCount::Exact(4_754)),
             ]
             .into(),
+            wrapped: BTreeMap::new(),
+            fallback: BTreeSet::new(),
         }
     }
 
@@ -247,6 +434,16 @@ mod tests {
             sort,
             color: false,
             count_format,
+            budget: None,
+            summarize: false,
+            depth: None,
+            min_tokens: None,
+            top: None,
+            top_global: false,
+            percent: false,
+            bars: false,
+            warn: None,
+            crit: None,
         }
     }
 
@@ -268,6 +465,17 @@ mod tests {
         opts(false, true, false, CountFormat::Single)
     }
 
+    /// Single-tokenizer files of three sizes, two of them in `d/`, plus a
+    /// binary.
+    fn sized_entries() -> [FileResult; 4] {
+        [
+            text_result("a.rs", &[("o200k", 100)]),
+            text_result("d/b.rs", &[("o200k", 4_000)]),
+            text_result("d/c.rs", &[("o200k", 12_000)]),
+            binary_result("d/logo.png"),
+        ]
+    }
+
     fn run(root: &str, entries: &[FileResult], o: &OutputOptions) -> String {
         let mut out: Vec<u8> = Vec::new();
         write_output(&mut out, root, entries, o).unwrap();
@@ -383,8 +591,10 @@ mod tests {
     fn range_mode_approx_shows_tilde_on_max() {
         let entry = FileResult {
             rel_path: "f.rs".into(),
-            kind: crate::walk::FileKind::Text,
+            kind: crate::walk::FileKind::Text(Encoding::Utf8),
             tokens: [(TokenizerId::Ctoc, TokenCount::from_approx(1000))].into(),
+            wrapped: BTreeMap::new(),
+            fallback: BTreeSet::new(),
         };
         let s = run(".", &[entry], &opts(true, false, false, CountFormat::Range));
         assert!(s.contains("957"), "lo bound missing:\n{s}");
@@ -396,12 +606,14 @@ mod tests {
     fn range_mode_mixed_exact_and_approx_shows_tilde() {
         let entry = FileResult {
             rel_path: "f.rs".into(),
-            kind: crate::walk::FileKind::Text,
+            kind: crate::walk::FileKind::Text(Encoding::Utf8),
             tokens: [
                 (TokenizerId::Ctoc, TokenCount::from_approx(120)),
                 (TokenizerId::O200k, TokenCount::Exact(100)),
             ]
             .into(),
+            wrapped: BTreeMap::new(),
+            fallback: BTreeSet::new(),
         };
         let s = run(".", &[entry], &opts(true, false, false, CountFormat::Range));
         assert!(s.contains("100"), "min missing:\n{s}");
@@ -420,8 +632,10 @@ mod tests {
     fn approx_count_shows_range(flat_opts: OutputOptions) {
         let entry = FileResult {
             rel_path: "f.rs".into(),
-            kind: crate::walk::FileKind::Text,
+            kind: crate::walk::FileKind::Text(Encoding::Utf8),
             tokens: [(TokenizerId::Ctoc, TokenCount::from_approx(1000))].into(),
+            wrapped: BTreeMap::new(),
+            fallback: BTreeSet::new(),
         };
         let s = run(".", &[entry], &flat_opts);
         assert!(s.contains('–'), "en-dash missing for approx range:\n{s}");
@@ -676,6 +890,524 @@ mod tests {
         );
     }
 
+    /// `src/` sums `main.rs` and everything under `src/gen/`.
+    fn nested_entries() -> [FileResult; 4] {
+        [
+            text_result("README.md", &[("o200k", 40), ("claude", 45)]),
+            text_result("src/gen/api.rs", &[("o200k", 2_000), ("claude", 2_100)]),
+            text_result("src/main.rs", &[("o200k", 300), ("claude", 310)]),
+            binary_result("src/logo.bin"),
+        ]
+    }
+
+    #[rstest]
+    #[case::range(CountFormat::Range, "[2,300 – 2,410]", "[2,000 – 2,100]")]
+    #[case::named(CountFormat::Named, "2,410", "2,100")]
+    fn tree_dirs_show_subtotals(
+        #[case] count_format: CountFormat,
+        #[case] src: &str,
+        #[case] generated: &str,
+    ) {
+        let s = run(
+            ".",
+            &nested_entries(),
+            &opts(false, false, false, count_format),
+        );
+        let line = |name: &str| s.lines().find(|l| l.contains(name)).unwrap();
+        assert!(line("src/").contains(src), "src/ subtotal:\n{s}");
+        assert!(line("gen/").contains(generated), "gen/ subtotal:\n{s}");
+    }
+
+    /// Directory subtotals start in the same column as file counts, and
+    /// in Named mode end there too.
+    #[rstest]
+    #[case::single(CountFormat::Single)]
+    #[case::named(CountFormat::Named)]
+    fn tree_dir_subtotals_aligned_with_files(#[case] count_format: CountFormat) {
+        let named = matches!(count_format, CountFormat::Named);
+        let s = run(
+            ".",
+            &nested_entries(),
+            &opts(false, false, false, count_format),
+        );
+        let rows: Vec<&str> = ["src/", "gen/", "api.rs", "main.rs", "README.md"]
+            .iter()
+            .map(|name| s.lines().find(|l| l.contains(name)).unwrap())
+            .collect();
+        let edge = |l: &str| {
+            if named {
+                l.chars().count()
+            } else {
+                l.chars().take_while(|&c| c != '[').count()
+            }
+        };
+        assert!(
+            rows.iter().all(|l| edge(l) == edge(rows[0])),
+            "rows not aligned:\n{s}"
+        );
+    }
+
+    #[test]
+    fn tree_sort_orders_dirs_by_subtotal() {
+        let entries = [
+            text_result("a/small.rs", &[("o200k", 10)]),
+            text_result("b/big.rs", &[("o200k", 900)]),
+        ];
+        let s = run(
+            ".",
+            &entries,
+            &opts(false, false, true, CountFormat::Single),
+        );
+        assert!(s.find("b/").unwrap() < s.find("a/").unwrap(), "{s}");
+    }
+
+    #[rstest]
+    fn json_has_nested_dir_totals(json_opts: OutputOptions) {
+        let s = run(".", &nested_entries(), &json_opts);
+        let v: serde_json::Value = serde_json::from_str(&s).expect("not valid JSON");
+        assert_eq!(
+            v["dirs"],
+            serde_json::json!([{
+                "path": "src",
+                "tokens": { "claude": 2_410, "o200k": 2_300 },
+                "file_count": 3,
+                "dirs": [{
+                    "path": "src/gen",
+                    "tokens": { "claude": 2_100, "o200k": 2_000 },
+                    "file_count": 1,
+                    "dirs": [],
+                }],
+            }])
+        );
+    }
+
+    // ── --depth ────────────────────────────────────────────────────────────
+
+    /// Contents below `--depth` collapse into one row per directory but
+    /// still count toward subtotals and TOTAL.
+    #[test]
+    fn depth_collapses_deeper_rows() {
+        let o = OutputOptions {
+            depth: Some(1),
+            ..opts(false, false, false, CountFormat::Range)
+        };
+        let s = run(".", &nested_entries(), &o);
+        assert!(!s.contains("main.rs") && !s.contains("gen/"), "{s}");
+        assert!(s.contains("README.md"), "shallow file hidden:\n{s}");
+        let src = s.lines().position(|l| l.contains("src/")).unwrap();
+        assert_eq!(
+            s.lines().nth(src + 1).unwrap(),
+            "│   └── … 3 files, 2,300 – 2,410 tokens",
+            "{s}"
+        );
+        let total = s.lines().find(|l| l.starts_with("Total:")).unwrap();
+        assert_eq!(total, "Total: [2,340 – 2,455]");
+    }
+
+    #[test]
+    fn depth_zero_collapses_everything_under_root() {
+        let o = OutputOptions {
+            depth: Some(0),
+            ..opts(false, false, false, CountFormat::Range)
+        };
+        let s = run(".", &nested_entries(), &o);
+        assert_eq!(
+            s.lines().nth(1).unwrap(),
+            "└── … 4 files, 2,340 – 2,455 tokens"
+        );
+    }
+
+    /// `--flat` folds files below `--depth` into one row per directory at
+    /// the limit, in place, or ranked by its total with `--sort`.
+    #[rstest]
+    #[case::unsorted(
+        CountFormat::Range,
+        false,
+        &["README.md  [40 – 45]", "src/       … 3 files, 2,300 – 2,410 tokens"]
+    )]
+    #[case::sorted(
+        CountFormat::Range,
+        true,
+        &["src/       … 3 files, 2,300 – 2,410 tokens", "README.md  [40 – 45]"]
+    )]
+    #[case::named(
+        CountFormat::Named,
+        false,
+        &["README.md      45      40", "src/       … 3 files, 2,300 – 2,410 tokens"]
+    )]
+    fn flat_depth_collapses_deeper_files(
+        #[case] count_format: CountFormat,
+        #[case] sort: bool,
+        #[case] rows: &[&str],
+    ) {
+        let o = OutputOptions {
+            depth: Some(1),
+            ..opts(true, false, sort, count_format)
+        };
+        let s = run(".", &nested_entries(), &o);
+        let lines: Vec<&str> = s.lines().filter(|l| !l.starts_with("PATH")).collect();
+        assert_eq!(&lines[..rows.len()], rows, "{s}");
+        assert!(!s.contains("main.rs"), "{s}");
+    }
+
+    /// Hidden files do not widen the count column.
+    #[test]
+    fn depth_ignores_hidden_names_for_alignment() {
+        let entries = [
+            text_result("a.rs", &[("o200k", 1)]),
+            text_result("d/a_very_long_file_name_that_is_hidden.rs", &[("o200k", 2)]),
+        ];
+        let o = OutputOptions {
+            depth: Some(1),
+            ..opts(false, false, false, CountFormat::Range)
+        };
+        let s = run(".", &entries, &o);
+        let row = s.lines().find(|l| l.contains("a.rs")).unwrap();
+        assert_eq!(row, "└── a.rs  [1]");
+    }
+
+    // ── --min-tokens / --top ───────────────────────────────────────────────
+
+    /// Files under `--min-tokens` (and uncounted ones) fold into one row per
+    /// directory that still adds up to TOTAL.
+    #[test]
+    fn min_tokens_folds_small_files() {
+        let o = OutputOptions {
+            min_tokens: Some(100),
+            ..opts(false, false, false, CountFormat::Range)
+        };
+        let s = run(".", &nested_entries(), &o);
+        assert!(!s.contains("README.md") && !s.contains("logo.bin"), "{s}");
+        assert!(
+            s.contains("│   └── … 1 more file"),
+            "uncounted file kept:\n{s}"
+        );
+        assert!(
+            s.lines().any(|l| l == "└── … 1 more file (40 – 45 tokens)"),
+            "{s}"
+        );
+        let total = s.lines().find(|l| l.starts_with("Total:")).unwrap();
+        assert_eq!(total, "Total: [2,340 – 2,455]");
+    }
+
+    /// `--top` keeps the largest files of each directory in a tree, and of
+    /// the whole list with `--flat` or `--top-global`.
+    #[rstest]
+    #[case::tree(false, false, &["b.rs", "e.rs"], &["… 1 more file (10 tokens)", "… 1 more file (5 tokens)"])]
+    #[case::tree_global(false, true, &["d/e.rs"], &["… 2 more files (30 tokens)", "… 1 more file (5 tokens)"])]
+    #[case::flat(true, false, &["d/e.rs"], &["… 3 more files (35 tokens)"])]
+    fn top_keeps_largest_files(
+        #[case] flat: bool,
+        #[case] global: bool,
+        #[case] shown: &[&str],
+        #[case] rows: &[&str],
+    ) {
+        let entries = [
+            text_result("a.rs", &[("o200k", 10)]),
+            text_result("b.rs", &[("o200k", 20)]),
+            text_result("d/c.rs", &[("o200k", 5)]),
+            text_result("d/e.rs", &[("o200k", 30)]),
+        ];
+        let o = OutputOptions {
+            top: Some(1),
+            top_global: global,
+            ..opts(flat, false, false, CountFormat::Range)
+        };
+        let s = run(".", &entries, &o);
+        for name in ["a.rs", "b.rs", "c.rs", "e.rs"] {
+            assert_eq!(
+                s.contains(name),
+                shown.iter().any(|p| p.ends_with(name)),
+                "{name}:\n{s}"
+            );
+        }
+        for row in rows {
+            assert!(s.lines().any(|l| l.ends_with(row)), "missing {row:?}:\n{s}");
+        }
+        assert!(s.contains("Total: [65]"), "{s}");
+    }
+
+    /// Pruned files do not widen the count column.
+    #[test]
+    fn pruned_names_ignored_for_alignment() {
+        let entries = [
+            text_result("a.rs", &[("o200k", 10)]),
+            text_result("a_very_long_file_name_that_is_pruned.rs", &[("o200k", 2)]),
+        ];
+        let o = OutputOptions {
+            min_tokens: Some(5),
+            ..opts(false, false, false, CountFormat::Range)
+        };
+        let s = run(".", &entries, &o);
+        assert_eq!(s.lines().nth(1).unwrap(), "├── a.rs  [10]");
+    }
+
+    // ── --percent / --bars ─────────────────────────────────────────────────
+
+    #[rstest]
+    #[case::zero(0, "")]
+    #[case::tiny(1, "▏")]
+    #[case::half(500, "██████████")]
+    #[case::eighths(530, "██████████▋")]
+    #[case::full(1_000, "████████████████████")]
+    fn bar_scaled_to_largest(#[case] used: usize, #[case] expected: &str) {
+        assert_eq!(format_bar(used, 1_000), expected);
+    }
+
+    #[test]
+    fn display_width_skips_colour_codes() {
+        assert_eq!(display_width(&dim("[binary]", true)), 8);
+        assert_eq!(display_width("[1,234] *"), 9);
+    }
+
+    /// Percentages are of the root total and line up after the widest
+    /// count; uncounted rows get none.
+    #[rstest]
+    #[case::tree(false, &[
+        "├── d/            [16,000]   99.4%",
+        "│   ├── b.rs      [4,000]    24.8%",
+        "│   ├── c.rs      [12,000]   74.5%",
+        "│   └── logo.png  [binary]",
+        "└── a.rs          [100]       0.6%",
+    ])]
+    #[case::flat(true, &[
+        "a.rs        [100]       0.6%",
+        "d/b.rs      [4,000]    24.8%",
+        "d/c.rs      [12,000]   74.5%",
+        "d/logo.png  [binary]",
+    ])]
+    fn percent_column_aligned(#[case] flat: bool, #[case] rows: &[&str]) {
+        let o = OutputOptions {
+            percent: true,
+            ..opts(flat, false, false, CountFormat::Single)
+        };
+        let s = run(".", &sized_entries(), &o);
+        for row in rows {
+            assert!(s.lines().any(|l| l == *row), "missing {row:?}:\n{s}");
+        }
+        assert!(s.contains("Total: [16,100]\n"), "{s}");
+    }
+
+    /// Bars go on file rows, scaled to the largest file, after the
+    /// percentage.
+    #[test]
+    fn bars_follow_percent_on_file_rows() {
+        let o = OutputOptions {
+            percent: true,
+            bars: true,
+            ..tree_opts()
+        };
+        let s = run(".", &sized_entries(), &o);
+        let line = |name: &str| s.lines().find(|l| l.contains(name)).unwrap();
+        assert!(
+            line("c.rs").ends_with(" 74.5%  ████████████████████"),
+            "{s}"
+        );
+        assert!(line("b.rs").ends_with(" 24.8%  ██████▊"), "{s}");
+        assert!(line("a.rs").ends_with("  0.6%  ▎"), "{s}");
+        assert!(line("d/").ends_with(" 99.4%"), "{s}");
+    }
+
+    // ── --warn / --crit ────────────────────────────────────────────────────
+
+    /// Counts are green below `--warn`, yellow from it, red from `--crit`;
+    /// percentiles pick thresholds from the file counts.
+    #[rstest]
+    #[case::tree_absolute(false, Threshold::Tokens(4_000), Threshold::Tokens(10_000))]
+    #[case::flat_absolute(true, Threshold::Tokens(4_000), Threshold::Tokens(10_000))]
+    #[case::percentile(false, Threshold::Percentile(50.0), Threshold::Percentile(90.0))]
+    fn heatmap_colours_file_counts(
+        #[case] flat: bool,
+        #[case] warn: Threshold,
+        #[case] crit: Threshold,
+    ) {
+        let o = OutputOptions {
+            color: true,
+            warn: Some(warn),
+            crit: Some(crit),
+            ..opts(flat, false, false, CountFormat::Single)
+        };
+        let s = run(".", &sized_entries(), &o);
+        let line = |name: &str| s.lines().find(|l| l.contains(name)).unwrap();
+        assert!(
+            line("a.rs").contains(&paint_heat("[100]", Heat::Cool)),
+            "{s}"
+        );
+        assert!(
+            line("b.rs").contains(&paint_heat("[4,000]", Heat::Warm)),
+            "{s}"
+        );
+        assert!(
+            line("c.rs").contains(&paint_heat("[12,000]", Heat::Hot)),
+            "{s}"
+        );
+        assert!(!line("logo.png").contains("\x1b[3"), "{s}");
+    }
+
+    /// Without colour (`--no-color`, `NO_COLOR`, or no TTY) thresholds
+    /// change nothing.
+    #[test]
+    fn heatmap_needs_colour() {
+        let heat = OutputOptions {
+            warn: Some(Threshold::Tokens(1)),
+            crit: Some(Threshold::Tokens(2)),
+            ..flat_opts()
+        };
+        assert_eq!(
+            run(".", &sized_entries(), &heat),
+            run(".", &sized_entries(), &flat_opts())
+        );
+    }
+
+    // ── --summarize ────────────────────────────────────────────────────────
+
+    /// Only directory rows are shown; `gen/` is folded into `src/` at depth 1.
+    #[rstest]
+    #[case::tree(false)]
+    #[case::flat(true)]
+    fn summarize_shows_dirs_with_file_counts(#[case] flat: bool) {
+        let o = OutputOptions {
+            summarize: true,
+            depth: Some(1),
+            ..opts(flat, false, false, CountFormat::Range)
+        };
+        let s = run(".", &nested_entries(), &o);
+        assert!(
+            !s.contains(".rs") && !s.contains("README"),
+            "file rows:\n{s}"
+        );
+        assert!(!s.contains("gen/"), "gen/ not folded:\n{s}");
+        let src = s.lines().find(|l| l.contains("src/")).unwrap();
+        assert!(src.ends_with("[2,300 – 2,410]  3 files"), "src/ row:\n{s}");
+        let total = s.lines().find(|l| l.starts_with("Total")).unwrap();
+        assert!(total.ends_with("[2,340 – 2,455]  4 files"), "total:\n{s}");
+    }
+
+    #[test]
+    fn summarize_without_depth_shows_every_dir() {
+        let o = OutputOptions {
+            summarize: true,
+            depth: None,
+            ..opts(false, false, false, CountFormat::Range)
+        };
+        let s = run(".", &nested_entries(), &o);
+        let gen_row = s.lines().find(|l| l.contains("gen/")).unwrap();
+        assert!(gen_row.ends_with("[2,000 – 2,100]  1 file"), "{s}");
+    }
+
+    /// File counts line up even when the subtotals before them differ in
+    /// width.
+    #[test]
+    fn summarize_file_counts_aligned() {
+        let entries = [
+            text_result("a/big.rs", &[("o200k", 12_345)]),
+            text_result("b/small.rs", &[("o200k", 7)]),
+        ];
+        let o = OutputOptions {
+            summarize: true,
+            depth: None,
+            ..opts(false, false, false, CountFormat::Range)
+        };
+        let s = run(".", &entries, &o);
+        let ends: Vec<usize> = s
+            .lines()
+            .filter(|l| l.contains("a/") || l.contains("b/"))
+            .map(|l| l.chars().count())
+            .collect();
+        assert_eq!(ends.len(), 2, "{s}");
+        assert_eq!(ends[0], ends[1], "file counts not aligned:\n{s}");
+    }
+
+    #[test]
+    fn json_summarize_has_dirs_only() {
+        let o = OutputOptions {
+            summarize: true,
+            depth: Some(1),
+            ..opts(false, true, false, CountFormat::Range)
+        };
+        let s = run(".", &nested_entries(), &o);
+        let v: serde_json::Value = serde_json::from_str(&s).expect("not valid JSON");
+        assert!(v.get("files").is_none(), "files listed:\n{s}");
+        assert_eq!(v["file_count"], 4);
+        assert_eq!(v["dirs"][0]["path"], "src");
+        assert_eq!(v["dirs"][0]["file_count"], 3);
+        assert_eq!(v["dirs"][0]["dirs"], serde_json::json!([]));
+    }
+
+    // ── budget ─────────────────────────────────────────────────────────────
+
+    #[test]
+    fn budget_annotates_dir_and_total_rows() {
+        let entries = [
+            text_result("top.rs", &[("o200k", 250)]),
+            text_result("sub/deep.rs", &[("o200k", 500)]),
+        ];
+        let o = OutputOptions {
+            budget: Some(1_000),
+            ..tree_opts()
+        };
+        let s = run(".", &entries, &o);
+        let dir_line = s.lines().find(|l| l.contains("sub/")).unwrap();
+        assert!(dir_line.ends_with("[500]  50.0%"), "dir row:\n{s}");
+        let total = s.lines().find(|l| l.starts_with("Total:")).unwrap();
+        assert_eq!(total, "Total: [750]  75.0% of 1,000", "total row:\n{s}");
+    }
+
+    #[rstest]
+    #[case::tree(false)]
+    #[case::flat(true)]
+    fn budget_overflow_marks_total_row(#[case] flat: bool) {
+        let entries = [text_result("big.rs", &[("o200k", 1_500)])];
+        let o = OutputOptions {
+            budget: Some(1_000),
+            ..opts(flat, false, false, CountFormat::Single)
+        };
+        let s = run(".", &entries, &o);
+        let total = s.lines().find(|l| l.starts_with("Total")).unwrap();
+        assert!(
+            total.ends_with("150.0% of 1,000  OVER BUDGET by 500"),
+            "total row:\n{s}"
+        );
+    }
+
+    #[test]
+    fn budget_named_total_row_has_annotation() {
+        let o = OutputOptions {
+            budget: Some(100),
+            ..opts(false, false, false, CountFormat::Named)
+        };
+        let s = run(".", &[approx_entry()], &o);
+        let total = s.lines().find(|l| l.starts_with("Total")).unwrap();
+        // Ctoc's hi bound is the conservative figure.
+        assert!(total.contains("of 100  OVER BUDGET"), "total row:\n{s}");
+    }
+
+    #[test]
+    fn json_budget_has_budget_and_fractions() {
+        let entries = [
+            text_result("a.rs", &[("o200k", 100)]),
+            text_result("b.rs", &[("o200k", 300)]),
+            binary_result("c.bin"),
+        ];
+        let o = OutputOptions {
+            budget: Some(800),
+            ..json_opts()
+        };
+        let s = run(".", &entries, &o);
+        let v: serde_json::Value = serde_json::from_str(&s).expect("not valid JSON");
+        assert_eq!(v["budget"], 800);
+        assert_eq!(v["fraction"], 0.5);
+        assert_eq!(v["files"][0]["fraction"], 0.125);
+        assert!(v["files"][2].get("fraction").is_none());
+    }
+
+    #[rstest]
+    fn json_without_budget_has_no_budget_fields(json_opts: OutputOptions) {
+        let entries = [text_result("a.rs", &[("o200k", 1)])];
+        let v: serde_json::Value = serde_json::from_str(&run(".", &entries, &json_opts)).unwrap();
+        assert!(v.get("budget").is_none() && v.get("fraction").is_none());
+    }
+
     // ── JSON mode ──────────────────────────────────────────────────────────
 
     #[rstest]
@@ -696,12 +1428,263 @@ mod tests {
         assert_eq!(v["total"]["o200k"], 42);
     }
 
+    #[test]
+    fn custom_tokenizer_gets_own_column_and_json_key() {
+        let entry = FileResult {
+            rel_path: "f.rs".into(),
+            kind: crate::walk::FileKind::Text(Encoding::Utf8),
+            tokens: [
+                (TokenizerId::O200k, TokenCount::Exact(10)),
+                (TokenizerId::custom("qwen"), TokenCount::Exact(12)),
+            ]
+            .into(),
+            wrapped: BTreeMap::new(),
+            fallback: BTreeSet::new(),
+        };
+        let tree = run(
+            ".",
+            std::slice::from_ref(&entry),
+            &opts(false, false, false, CountFormat::Named),
+        );
+        let header = tree.lines().next().unwrap();
+        assert!(
+            header.contains("OpenAI") && header.contains("qwen"),
+            "header:\n{tree}"
+        );
+
+        let json = run(".", &[entry], &opts(false, true, false, CountFormat::Named));
+        let v: serde_json::Value = serde_json::from_str(&json).expect("not valid JSON");
+        assert_eq!(v["files"][0]["tokens"]["qwen"], 12);
+        assert_eq!(v["total"]["qwen"], 12);
+    }
+
+    #[rstest]
+    fn json_reports_wrapped_counts(json_opts: OutputOptions) {
+        let mut a = text_result("a.rs", &[("claude", 10)]);
+        a.wrapped.insert(TokenizerId::Claude, 17);
+        let mut b = text_result("b.rs", &[("claude", 20)]);
+        b.wrapped.insert(TokenizerId::Claude, 27);
+        let s = run(
+            ".",
+            &[a, b, text_result("c.rs", &[("o200k", 1)])],
+            &json_opts,
+        );
+        let v: serde_json::Value = serde_json::from_str(&s).expect("not valid JSON");
+        assert_eq!(v["files"][0]["tokens"]["claude"], 10);
+        assert_eq!(v["files"][0]["wrapped"]["claude"], 17);
+        assert!(v["files"][2].get("wrapped").is_none());
+        assert_eq!(v["total"]["claude"], 30);
+        assert_eq!(v["wrapped_total"]["claude"], 44);
+    }
+
+    /// `b.rs`'s Claude count is a ctoc estimate standing in for a failed call.
+    fn fallback_entries() -> [FileResult; 2] {
+        let mut b = text_result("b.rs", &[]);
+        b.tokens
+            .insert(TokenizerId::Claude, TokenCount::from_approx(600));
+        b.fallback.insert(TokenizerId::Claude);
+        [text_result("a.rs", &[("claude", 10)]), b]
+    }
+
+    #[rstest]
+    #[case::flat(true, CountFormat::Single)]
+    #[case::tree(false, CountFormat::Single)]
+    #[case::flat_named(true, CountFormat::Named)]
+    #[case::tree_named(false, CountFormat::Named)]
+    fn fallback_rows_are_marked(#[case] flat: bool, #[case] count_format: CountFormat) {
+        let s = run(
+            ".",
+            &fallback_entries(),
+            &opts(flat, false, false, count_format),
+        );
+        let line = |name: &str| s.lines().find(|l| l.contains(name)).unwrap();
+        assert!(!line("a.rs").ends_with(" *"), "a.rs marked:\n{s}");
+        assert!(line("b.rs").ends_with(" *"), "b.rs unmarked:\n{s}");
+        assert!(line("Total").ends_with(" *"), "total unmarked:\n{s}");
+        assert!(
+            s.contains("* Claude: API call failed for 1 file; offline estimate shown"),
+            "footnote missing:\n{s}"
+        );
+    }
+
+    #[rstest]
+    fn no_fallback_note_without_fallbacks(flat_opts: OutputOptions) {
+        let s = run(".", &[text_result("a.rs", &[("claude", 10)])], &flat_opts);
+        assert!(!s.contains('*'), "unexpected marker:\n{s}");
+    }
+
+    #[rstest]
+    fn json_lists_fallback_tokenizers(json_opts: OutputOptions) {
+        let s = run(".", &fallback_entries(), &json_opts);
+        let v: serde_json::Value = serde_json::from_str(&s).expect("not valid JSON");
+        assert!(v["files"][0].get("fallback").is_none());
+        assert_eq!(v["files"][1]["fallback"], serde_json::json!(["claude"]));
+        assert_eq!(v["files"][1]["tokens"]["claude"]["lo"], 573);
+        // An exact count plus an estimate totals to a range.
+        assert_eq!(v["total"]["claude"]["lo"], 583);
+    }
+
+    fn image_result(path: &str, counts: &[(&str, usize)]) -> FileResult {
+        FileResult {
+            kind: crate::walk::FileKind::Image(crate::image::ImageInfo {
+                format: crate::image::ImageFormat::Png,
+                width: 1280,
+                height: 720,
+            }),
+            ..text_result(path, counts)
+        }
+    }
+
+    /// Priced images show counts like text; unpriced ones a dim label.
+    #[rstest]
+    #[case::flat(true)]
+    #[case::tree(false)]
+    fn image_rows_show_counts_or_label(#[case] flat: bool) {
+        let entries = [
+            image_result("shot.png", &[("o200k", 1_105)]),
+            image_result("logo.png", &[]),
+        ];
+        let s = run(
+            ".",
+            &entries,
+            &opts(flat, false, false, CountFormat::Single),
+        );
+        assert!(s.contains("[1,105]"), "{s}");
+        assert!(s.contains("[image]"), "{s}");
+    }
+
+    /// Image counts are formula estimates, so even exact tokenizers' counts
+    /// carry the `~` marker, and JSON reports them as ranges.
+    #[rstest]
+    #[case::flat(true, false)]
+    #[case::tree(false, false)]
+    #[case::json(false, true)]
+    fn image_estimates_are_marked_approximate(#[case] flat: bool, #[case] json: bool) {
+        let entry = crate::walk::FileEntry {
+            path: "shot.png".into(),
+            rel_path: "shot.png".into(),
+            kind: crate::walk::FileKind::Image(crate::image::ImageInfo {
+                format: crate::image::ImageFormat::Png,
+                width: 1280,
+                height: 720,
+            }),
+            content: None,
+            document: None,
+        };
+        let tokenizers = resolve_tokenizers(
+            &["o200k".to_string(), "cl100k".to_string()],
+            &ResolveOptions::default(),
+        )
+        .unwrap();
+        let results = tokenize_entries(&[entry], &tokenizers);
+
+        let s = run(".", &results, &opts(flat, json, false, CountFormat::Range));
+        if json {
+            let v: serde_json::Value = serde_json::from_str(&s).expect("not valid JSON");
+            for name in ["o200k", "cl100k"] {
+                assert!(v["files"][0]["tokens"][name]["lo"].is_u64(), "{s}");
+            }
+        } else {
+            assert!(s.contains("shot.png  [1,057 – ~1,153]"), "{s}");
+        }
+    }
+
+    #[rstest]
+    fn json_image_has_dimensions(json_opts: OutputOptions) {
+        let s = run(
+            ".",
+            &[image_result("shot.png", &[("o200k", 1_105)])],
+            &json_opts,
+        );
+        let v: serde_json::Value = serde_json::from_str(&s).expect("not valid JSON");
+        assert_eq!(v["files"][0]["type"], "image");
+        assert_eq!(
+            v["files"][0]["image"],
+            serde_json::json!({ "format": "png", "width": 1280, "height": 720 })
+        );
+        assert_eq!(v["total"]["o200k"], 1_105);
+    }
+
+    #[rstest]
+    fn json_pdf_has_pages(json_opts: OutputOptions) {
+        let entries = [FileResult {
+            kind: crate::walk::FileKind::Pdf(crate::pdf::PdfInfo {
+                pages: 12,
+                width_pt: 612,
+                height_pt: 792,
+            }),
+            ..text_result("spec.pdf", &[("claude", 30_412)])
+        }];
+        let s = run(".", &entries, &json_opts);
+        let v: serde_json::Value = serde_json::from_str(&s).expect("not valid JSON");
+        assert_eq!(v["files"][0]["type"], "pdf");
+        assert_eq!(
+            v["files"][0]["pdf"],
+            serde_json::json!({ "pages": 12, "width_pt": 612, "height_pt": 792 })
+        );
+        assert_eq!(v["total"]["claude"], 30_412);
+    }
+
+    #[rstest]
+    fn json_text_has_encoding(json_opts: OutputOptions) {
+        let entries = [FileResult {
+            kind: crate::walk::FileKind::Text(Encoding::Utf16Le),
+            ..text_result("App.sln", &[("claude", 812)])
+        }];
+        let s = run(".", &entries, &json_opts);
+        let v: serde_json::Value = serde_json::from_str(&s).expect("not valid JSON");
+        assert_eq!(v["files"][0]["type"], "text");
+        assert_eq!(v["files"][0]["encoding"], "utf-16le");
+    }
+
+    fn chunked_result(path: &str, counts: &[(&str, usize)]) -> FileResult {
+        FileResult {
+            kind: crate::walk::FileKind::Chunked(Encoding::Utf8),
+            ..text_result(path, counts)
+        }
+    }
+
+    /// A file counted in pieces is marked after its counts; others are not.
+    #[rstest]
+    #[case::flat(true)]
+    #[case::tree(false)]
+    fn chunked_rows_are_marked(#[case] flat: bool) {
+        let entries = [
+            chunked_result("big.log", &[("o200k", 900_000)]),
+            text_result("small.log", &[("o200k", 12)]),
+        ];
+        let s = run(
+            ".",
+            &entries,
+            &opts(flat, false, false, CountFormat::Single),
+        );
+        let row = |name: &str| s.lines().find(|l| l.contains(name)).unwrap();
+        assert!(row("big.log").ends_with("[900,000] (chunked)"), "{s}");
+        assert!(!row("small.log").contains("(chunked)"), "{s}");
+    }
+
+    #[rstest]
+    fn json_chunked_has_flag(json_opts: OutputOptions) {
+        let entries = [
+            chunked_result("big.log", &[("o200k", 900_000)]),
+            text_result("small.log", &[("o200k", 12)]),
+        ];
+        let s = run(".", &entries, &json_opts);
+        let v: serde_json::Value = serde_json::from_str(&s).expect("not valid JSON");
+        assert_eq!(v["files"][0]["type"], "text");
+        assert_eq!(v["files"][0]["encoding"], "utf-8");
+        assert_eq!(v["files"][0]["chunked"], true);
+        assert!(v["files"][1].get("chunked").is_none());
+    }
+
     #[rstest]
     fn json_too_large_has_skipped_field(json_opts: OutputOptions) {
         let entries = [FileResult {
             rel_path: "huge.dat".into(),
             kind: crate::walk::FileKind::TooLarge,
             tokens: BTreeMap::new(),
+            wrapped: BTreeMap::new(),
+            fallback: BTreeSet::new(),
         }];
         let s = run(".", &entries, &json_opts);
         let v: serde_json::Value = serde_json::from_str(&s).expect("not valid JSON");
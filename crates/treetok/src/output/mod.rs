@@ -4,11 +4,13 @@ mod format;
 
 pub use format::format_number;
 use format::{
-    ColLayout, format_counts, format_dir_label, format_named_columns, format_named_header,
-    format_tokens,
+    ColLayout, Heat, budget_fraction, display_width, fallback_marker, format_bar,
+    format_budget_pct, format_budget_total, format_collapsed, format_counts, format_dir_label,
+    format_file_count, format_named_columns, format_named_header, format_pruned, format_share_pct,
+    format_tokens, paint_heat, row_marker,
 };
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::io::Write;
 use std::path::Path;
 
@@ -39,6 +41,74 @@ pub struct OutputOptions {
     pub color: bool,
     /// How to format token counts.
     pub count_format: CountFormat,
+    /// Context window from `--budget`.  When set, directory and TOTAL rows
+    /// show the share of the window they consume.
+    pub budget: Option<usize>,
+    /// Show one row per directory, with subtree totals and file counts,
+    /// instead of one per file (`--summarize`).
+    pub summarize: bool,
+    /// Deepest level of the tree shown (`--depth`, `None` = all).  Deeper
+    /// files still count: tree output collapses them into one row per
+    /// directory, and `--summarize` folds them into their shown ancestor.
+    pub depth: Option<usize>,
+    /// Hide files with fewer tokens than this (`--min-tokens`).  JSON and
+    /// `--summarize` output list everything and ignore it.
+    pub min_tokens: Option<usize>,
+    /// Show only this many of the largest files per directory, or in the
+    /// whole list with `--flat` (`--top`).  Ignored like `min_tokens`.
+    pub top: Option<usize>,
+    /// Rank `top` across all files in tree output too (`--top-global`).
+    pub top_global: bool,
+    /// Append each row's share of the root total (`--percent`).  JSON
+    /// output ignores it.
+    pub percent: bool,
+    /// Append a bar scaled to the largest row (`--bars`).  Ignored like
+    /// `percent`.
+    pub bars: bool,
+    /// File count at which counts turn yellow (`--warn`).  Needs `color`;
+    /// JSON output ignores it.
+    pub warn: Option<Threshold>,
+    /// File count at which counts turn red (`--crit`).  Needs `color`.
+    pub crit: Option<Threshold>,
+}
+
+/// A `--warn` / `--crit` threshold for colouring file counts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Threshold {
+    /// A fixed token count.
+    Tokens(usize),
+    /// A percentile, in `(0, 100]`, of the counted files' counts.
+    Percentile(f64),
+}
+
+impl Threshold {
+    /// Whether `self`, as `--warn`, lies above `crit`.  A token count and a
+    /// percentile only compare once files are counted, so they never clash.
+    #[must_use]
+    pub fn is_above(self, crit: Self) -> bool {
+        match (self, crit) {
+            (Self::Tokens(warn), Self::Tokens(crit)) => warn > crit,
+            (Self::Percentile(warn), Self::Percentile(crit)) => warn > crit,
+            _ => false,
+        }
+    }
+
+    /// The token count this threshold stands for, given every counted
+    /// file's count in ascending order.  Percentiles use the nearest rank.
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    fn resolve(self, sorted: &[usize]) -> Option<usize> {
+        match self {
+            Self::Tokens(n) => Some(n),
+            Self::Percentile(p) => {
+                let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+                sorted.get(rank.max(1) - 1).copied()
+            }
+        }
+    }
 }
 
 impl OutputOptions {
@@ -62,7 +132,61 @@ impl OutputOptions {
             sort,
             color,
             count_format,
+            budget: None,
+            summarize: false,
+            depth: None,
+            min_tokens: None,
+            top: None,
+            top_global: false,
+            percent: false,
+            bars: false,
+            warn: None,
+            crit: None,
+        }
+    }
+
+    /// Whether the tree shows a row for `path` (a file or directory).
+    fn shows_path(&self, path: &Path) -> bool {
+        self.depth
+            .is_none_or(|depth| path.components().count() <= depth)
+    }
+
+    /// Whether the contents of directory `dir` lie below `--depth`.
+    fn hides_contents(&self, dir: &Path) -> bool {
+        self.depth
+            .is_some_and(|depth| dir.components().count() >= depth)
+    }
+
+    /// The files `--min-tokens` and `--top` hide.  `--top` ranks the files
+    /// of each directory separately in a tree, and all files together with
+    /// `--flat` or `--top-global`.
+    fn pruned_files<'a>(&self, entries: &'a [FileResult]) -> BTreeSet<&'a Path> {
+        if self.min_tokens.is_none() && self.top.is_none() {
+            return BTreeSet::new();
+        }
+        let per_dir = !self.flat && !self.top_global;
+        let mut groups: BTreeMap<&Path, Vec<&FileResult>> = BTreeMap::new();
+        for entry in entries {
+            let key = if per_dir {
+                entry.rel_path.parent().unwrap_or_else(|| Path::new(""))
+            } else {
+                Path::new("")
+            };
+            groups.entry(key).or_default().push(entry);
         }
+        let mut pruned = BTreeSet::new();
+        for files in groups.values_mut() {
+            sort_by_tokens(files);
+            for (rank, file) in files.iter().enumerate() {
+                let small = self
+                    .min_tokens
+                    .is_some_and(|min| max_total(&file.tokens) < min);
+                if small || self.top.is_some_and(|top| rank >= top) {
+                    pruned.insert(file.rel_path.as_path());
+                }
+            }
+        }
+        pruned
     }
 }
 
@@ -74,8 +198,27 @@ pub struct FileResult {
     pub rel_path: std::path::PathBuf,
     /// Content kind (reuses `walk::FileKind`).
     pub kind: FileKind,
-    /// Token counts keyed by tokenizer id.  Empty for non-text files.
+    /// Token counts keyed by tokenizer id.  Empty for files that are neither
+    /// text nor images.
     pub tokens: BTreeMap<TokenizerId, TokenCount>,
+    /// Counts as the API reported them, including the per-request message
+    /// envelope, for tokenizers whose `tokens` entry has that overhead removed.
+    pub wrapped: BTreeMap<TokenizerId, usize>,
+    /// Tokenizers whose call failed for this file; their `tokens` entry is a
+    /// ctoc estimate standing in for the real count.
+    pub fallback: BTreeSet<TokenizerId>,
+}
+
+impl FileResult {
+    /// Whether the row shows token counts rather than a `[binary]`-style
+    /// label.  An image no active tokenizer can price shows `[image]`.
+    fn shows_counts(&self) -> bool {
+        match self.kind {
+            FileKind::Text(_) | FileKind::Chunked(_) | FileKind::Pdf(_) => true,
+            FileKind::Image(_) => !self.tokens.is_empty(),
+            _ => false,
+        }
+    }
 }
 
 // ─── Public entry points ──────────────────────────────────────────────────────
@@ -88,7 +231,9 @@ pub fn write_output(
     opts: &OutputOptions,
 ) -> std::io::Result<()> {
     if opts.json {
-        write_json(out, root_label, entries)
+        write_json(out, root_label, entries, opts)
+    } else if opts.flat && opts.summarize {
+        write_flat_summary(out, entries, opts)
     } else if opts.flat {
         write_flat(out, entries, opts)
     } else if matches!(opts.count_format, CountFormat::Named) {
@@ -114,7 +259,7 @@ fn sort_by_tokens(entries: &mut [&FileResult]) {
 fn all_tokenizer_ids(entries: &[FileResult]) -> Vec<TokenizerId> {
     let mut ids: std::collections::BTreeSet<TokenizerId> = std::collections::BTreeSet::new();
     for e in entries {
-        ids.extend(e.tokens.keys().copied());
+        ids.extend(e.tokens.keys().cloned());
     }
     ids.into_iter().collect()
 }
@@ -168,21 +313,113 @@ fn compute_col_layouts(
 
 /// The label stored in every `Tree` node.
 enum TreeNode {
-    /// A directory label (e.g. `"src/"`).
-    Dir(String),
+    /// A directory row: label (e.g. `"src/"`, possibly coloured) and the
+    /// subtotal of every file beneath it.
+    Dir {
+        label: String,
+        /// Display width of `label` without colour codes.
+        width: usize,
+        /// Formatted subtotal, or `""` for the root (whose total is the
+        /// TOTAL row) and for directories with nothing counted.
+        counts: String,
+        /// `--percent` / `--bars` columns, or `""`.
+        share: String,
+    },
     /// A file leaf: display name + pre-formatted count string.
     File {
         name: String,
         /// Formatted count block, e.g. `"[1,234]"` or `"[binary]"`.
         counts: String,
+        /// `--percent` / `--bars` columns, or `""`.
+        share: String,
     },
+    /// Everything in a directory below `--depth`, e.g.
+    /// `"… 24 files, 82,752 tokens"`, or the files `--min-tokens` and
+    /// `--top` hide, e.g. `"… 187 more files (12,304 tokens)"`.
+    Collapsed(String),
+}
+
+impl TreeNode {
+    /// The root row: the walk root's label, without counts.
+    fn root(root_label: &str, color: bool) -> Self {
+        Self::Dir {
+            label: format_dir_label(root_label, color),
+            width: format_dir_label(root_label, false).chars().count(),
+            counts: String::new(),
+            share: String::new(),
+        }
+    }
+
+    /// Write the node after a connector of `prefix_width` columns, padding
+    /// counts out to `name_col` and the share columns out to `counts_col`
+    /// past it.
+    fn render(
+        &self,
+        out: &mut dyn Write,
+        prefix_width: usize,
+        name_col: usize,
+        counts_col: usize,
+    ) -> std::io::Result<()> {
+        let (label, width, counts, share) = match self {
+            Self::Dir {
+                label,
+                width,
+                counts,
+                share,
+            } => (label, *width, counts, share),
+            Self::File {
+                name,
+                counts,
+                share,
+            } => (name, name.chars().count(), counts, share),
+            Self::Collapsed(label) => return write!(out, "{label}"),
+        };
+        if counts.is_empty() {
+            return write!(out, "{label}");
+        }
+        let pad = name_col.saturating_sub(prefix_width + width);
+        write!(out, "{label}{:pad$}{counts}", "", pad = pad)?;
+        if share.is_empty() {
+            return Ok(());
+        }
+        let pad = counts_col.saturating_sub(display_width(counts));
+        write!(out, "{:pad$}{share}", "", pad = pad)
+    }
+}
+
+/// The width count blocks are padded to so the `--percent` / `--bars`
+/// columns line up: the widest block of any row that has them.
+fn counts_col_width(tree: &Tree<TreeNode>) -> usize {
+    let own = match &tree.root {
+        TreeNode::Dir { counts, share, .. } | TreeNode::File { counts, share, .. }
+            if !share.is_empty() =>
+        {
+            display_width(counts)
+        }
+        _ => 0,
+    };
+    tree.leaves
+        .iter()
+        .map(counts_col_width)
+        .fold(own, usize::max)
 }
 
 impl std::fmt::Display for TreeNode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::Dir(s) => write!(f, "{s}"),
-            Self::File { name, counts } => write!(f, "{name:<30}  {counts}"),
+            Self::Dir { label, counts, .. } if counts.is_empty() => write!(f, "{label}"),
+            Self::Dir {
+                label,
+                counts,
+                share,
+                ..
+            } => write!(f, "{label:<30}  {counts}{share}"),
+            Self::File {
+                name,
+                counts,
+                share,
+            } => write!(f, "{name:<30}  {counts}{share}"),
+            Self::Collapsed(label) => write!(f, "{label}"),
         }
     }
 }
@@ -191,20 +428,55 @@ impl std::fmt::Display for TreeNode {
 
 /// The column at which the count block should start in tree output.
 ///
-/// Equals `max(prefix_width + filename_width) + 2` across all entries,
-/// where `prefix_width = 4 × path-component-count` (each depth level adds
-/// the 4-char connector `"├── "` / `"│   "`).
-fn name_col_width(entries: &[FileResult]) -> usize {
+/// Equals `max(prefix_width + name_width) + 2` across all file and
+/// directory rows, where `prefix_width = 4 × depth` (each depth level adds
+/// the 4-char connector `"├── "` / `"│   "`) and a directory's name
+/// includes its trailing `/`.
+fn name_col_width(
+    entries: &[FileResult],
+    dirs: &DirIndex<'_>,
+    opts: &OutputOptions,
+    pruned: &BTreeSet<&Path>,
+) -> usize {
+    if opts.summarize {
+        return dirs
+            .shown(opts)
+            .map(|dir| 4 * dir.components().count() + dir_name(dir).chars().count() + 1)
+            .max()
+            .unwrap_or(28)
+            + 2;
+    }
     entries
         .iter()
         .map(|e| {
+            if !opts.shows_path(&e.rel_path) || pruned.contains(e.rel_path.as_path()) {
+                // Hidden below `--depth` or pruned; only its shown ancestors
+                // count.
+                return e
+                    .rel_path
+                    .ancestors()
+                    .skip(1)
+                    .filter(|dir| !dir.as_os_str().is_empty() && opts.shows_path(dir))
+                    .map(|dir| 4 * dir.components().count() + dir_name(dir).chars().count() + 1)
+                    .max()
+                    .unwrap_or(0);
+            }
             let depth = e.rel_path.components().count();
             let prefix_w = 4 * depth;
             let name_w = e.rel_path.file_name().map_or_else(
                 || e.rel_path.display().to_string().chars().count(),
                 |n| n.to_string_lossy().chars().count(),
             );
-            prefix_w + name_w
+            let dirs_w = e
+                .rel_path
+                .parent()
+                .into_iter()
+                .flat_map(Path::components)
+                .enumerate()
+                .map(|(i, dir)| 4 * (i + 1) + dir.as_os_str().to_string_lossy().chars().count() + 1)
+                .max()
+                .unwrap_or(0);
+            (prefix_w + name_w).max(dirs_w)
         })
         .max()
         .unwrap_or(28)
@@ -217,22 +489,46 @@ fn write_tree(
     entries: &[FileResult],
     opts: &OutputOptions,
 ) -> std::io::Result<()> {
-    let name_col = name_col_width(entries);
-    let root_display = format_dir_label(root_label, opts.color);
+    let pruned = opts.pruned_files(entries);
+    let dirs = DirIndex::new(entries);
+    let name_col = name_col_width(entries, &dirs, opts, &pruned);
+    let mut totals: BTreeMap<TokenizerId, TokenCount> = BTreeMap::new();
+    accumulate_totals(entries, &mut totals);
+    let shares = ShareColumns::new(
+        opts,
+        &totals,
+        largest_row(entries, Some(&dirs), opts, &pruned),
+    );
+    let heatmap = Heatmap::new(entries, opts);
+    let fmt_dir = |totals: &BTreeMap<TokenizerId, TokenCount>| {
+        if totals.is_empty() {
+            String::new()
+        } else {
+            format!("[{}]", format_counts(totals, &opts.count_format))
+        }
+    };
     let tree = build_tree_node(
-        TreeNode::Dir(root_display),
-        entries,
+        TreeNode::root(root_label, opts.color),
+        &dirs,
         Path::new(""),
         opts,
-        &|file| format_tokens(file, &opts.count_format, opts.color),
+        &RowFormat {
+            file: &|file| {
+                let heat = heatmap.heat(&file.tokens);
+                format_tokens(file, &opts.count_format, opts.color, heat)
+            },
+            dir: &fmt_dir,
+            // Every subtotal fits in the width of the grand total.
+            count_width: fmt_dir(&totals).chars().count(),
+            files_width: format_number(entries.len()).len(),
+            pruned: &pruned,
+            shares: &shares,
+        },
     );
 
-    tree.render(out, &|out, prefix_width, node| match node {
-        TreeNode::Dir(name) => write!(out, "{name}"),
-        TreeNode::File { name, counts } => {
-            let pad = name_col.saturating_sub(prefix_width + name.chars().count());
-            write!(out, "{name}{:pad$}{counts}", "", pad = pad)
-        }
+    let counts_col = counts_col_width(&tree);
+    tree.render(out, &|out, prefix_width, node| {
+        node.render(out, prefix_width, name_col, counts_col)
     })?;
 
     write_totals(out, entries, opts)?;
@@ -249,102 +545,351 @@ fn write_tree_named(
     let mut totals: BTreeMap<TokenizerId, TokenCount> = BTreeMap::new();
     accumulate_totals(entries, &mut totals);
     let layouts = compute_col_layouts(entries, &ids, &totals);
-    let name_col = name_col_width(entries);
+    let pruned = opts.pruned_files(entries);
+    let dirs = DirIndex::new(entries);
+    let name_col = name_col_width(entries, &dirs, opts, &pruned);
+    let shares = ShareColumns::new(
+        opts,
+        &totals,
+        largest_row(entries, Some(&dirs), opts, &pruned),
+    );
+    let heatmap = Heatmap::new(entries, opts);
 
     // Header row — blank padding to name_col, then right-aligned column labels.
     let header_cols = format_named_header(&ids, &layouts);
     writeln!(out, "{:<name_col$}{header_cols}", "", name_col = name_col)?;
 
     // Build and render the tree.
-    let root_display = format_dir_label(root_label, opts.color);
     let tree = build_tree_node(
-        TreeNode::Dir(root_display),
-        entries,
+        TreeNode::root(root_label, opts.color),
+        &dirs,
         Path::new(""),
         opts,
-        &|file| match &file.kind {
-            FileKind::Text => format_named_columns(&file.tokens, &ids, &layouts),
-            _ => format_tokens(file, &CountFormat::Named, opts.color),
+        &RowFormat {
+            file: &|file| {
+                if file.shows_counts() {
+                    let cols = format_named_columns(&file.tokens, &ids, &layouts);
+                    format!(
+                        "{}{}",
+                        heatmap.paint(&cols, &file.tokens),
+                        row_marker(file, opts.color)
+                    )
+                } else {
+                    format_tokens(file, &CountFormat::Named, opts.color, None)
+                }
+            },
+            dir: &|totals| format_named_columns(totals, &ids, &layouts),
+            count_width: 0,
+            files_width: format_number(entries.len()).len(),
+            pruned: &pruned,
+            shares: &shares,
         },
     );
 
-    tree.render(out, &|out, prefix_width, node| match node {
-        TreeNode::Dir(name) => write!(out, "{name}"),
-        TreeNode::File { name, counts } => {
-            let pad = name_col.saturating_sub(prefix_width + name.chars().count());
-            write!(out, "{name}{:pad$}{counts}", "", pad = pad)
-        }
+    let counts_col = counts_col_width(&tree);
+    tree.render(out, &|out, prefix_width, node| {
+        node.render(out, prefix_width, name_col, counts_col)
     })?;
 
     // Totals row.
     if !totals.is_empty() {
         let total_cols = format_named_columns(&totals, &ids, &layouts);
-        writeln!(out, "\n{TOTAL_LABEL:<name_col$}{total_cols}")?;
+        let files = summary_file_count(entries, opts);
+        let budget = budget_suffix(&totals, opts);
+        let marker = fallback_marker(has_fallback(entries), opts.color);
+        writeln!(
+            out,
+            "\n{TOTAL_LABEL:<name_col$}{total_cols}{files}{marker}{budget}"
+        )?;
+        write_fallback_note(out, entries)?;
+    }
+
+    Ok(())
+}
+
+/// Count formatters, and the files left out, for the rows of one tree.
+struct RowFormat<'a> {
+    /// Formats a file leaf's `TreeNode::File::counts`.
+    file: &'a dyn Fn(&FileResult) -> String,
+    /// Formats a directory's subtotal (`""` for an empty one in
+    /// Single/Range mode).
+    dir: &'a dyn Fn(&BTreeMap<TokenizerId, TokenCount>) -> String,
+    /// With `--summarize`: width subtotals are padded to so the file
+    /// counts after them line up.
+    count_width: usize,
+    /// With `--summarize`: width of the widest file count.
+    files_width: usize,
+    /// Files hidden by `--min-tokens` and `--top`.
+    pruned: &'a BTreeSet<&'a Path>,
+    /// The `--percent` / `--bars` columns.
+    shares: &'a ShareColumns,
+}
+
+/// The `--percent` and `--bars` columns appended to counted rows.
+struct ShareColumns {
+    percent: bool,
+    bars: bool,
+    /// Largest count in the root total, which `--percent` divides by.
+    total: usize,
+    /// Largest count among the rows that get a bar, which fills it.
+    largest: usize,
+}
+
+impl ShareColumns {
+    fn new(
+        opts: &OutputOptions,
+        totals: &BTreeMap<TokenizerId, TokenCount>,
+        largest: usize,
+    ) -> Self {
+        Self {
+            percent: opts.percent,
+            bars: opts.bars,
+            total: max_total(totals),
+            largest,
+        }
+    }
+
+    /// The columns for a row of `counts`, or `""` if it has no counts or
+    /// neither column is on.  Only rows with `bar` set get a bar.
+    fn format(&self, counts: &BTreeMap<TokenizerId, TokenCount>, bar: bool) -> String {
+        let mut columns = String::new();
+        if counts.is_empty() {
+            return columns;
+        }
+        let used = max_total(counts);
+        if self.percent {
+            columns = format!("  {}", format_share_pct(used, self.total));
+        }
+        if self.bars && bar {
+            columns = format!("{columns}  {}", format_bar(used, self.largest));
+        }
+        columns
+    }
+}
+
+/// `--warn` / `--crit` resolved to token counts for one listing.  Both are
+/// `None` when colour is off.
+struct Heatmap {
+    warn: Option<usize>,
+    crit: Option<usize>,
+}
+
+impl Heatmap {
+    fn new(entries: &[FileResult], opts: &OutputOptions) -> Self {
+        if !opts.color {
+            return Self {
+                warn: None,
+                crit: None,
+            };
+        }
+        let mut counts: Vec<usize> = entries
+            .iter()
+            .filter(|e| e.shows_counts())
+            .map(|e| max_total(&e.tokens))
+            .collect();
+        counts.sort_unstable();
+        Self {
+            warn: opts.warn.and_then(|t| t.resolve(&counts)),
+            crit: opts.crit.and_then(|t| t.resolve(&counts)),
+        }
+    }
+
+    /// How a file of `tokens` compares with the thresholds, or `None` when
+    /// there are none.
+    fn heat(&self, tokens: &BTreeMap<TokenizerId, TokenCount>) -> Option<Heat> {
+        if self.warn.is_none() && self.crit.is_none() {
+            return None;
+        }
+        let used = max_total(tokens);
+        Some(if self.crit.is_some_and(|crit| used >= crit) {
+            Heat::Hot
+        } else if self.warn.is_some_and(|warn| used >= warn) {
+            Heat::Warm
+        } else {
+            Heat::Cool
+        })
+    }
+
+    /// `s` coloured for a file of `tokens`.
+    fn paint(&self, s: &str, tokens: &BTreeMap<TokenizerId, TokenCount>) -> String {
+        self.heat(tokens)
+            .map_or_else(|| s.to_string(), |heat| paint_heat(s, heat))
+    }
+}
+
+/// The largest count among the rows that get a `--bars` bar: the files
+/// shown, or with `--summarize` the directories.
+fn largest_row(
+    entries: &[FileResult],
+    dirs: Option<&DirIndex<'_>>,
+    opts: &OutputOptions,
+    pruned: &BTreeSet<&Path>,
+) -> usize {
+    if let Some(dirs) = dirs.filter(|_| opts.summarize) {
+        return dirs
+            .shown(opts)
+            .map(|dir| max_total(&dirs.get(dir).totals))
+            .max()
+            .unwrap_or(0);
     }
+    entries
+        .iter()
+        .filter(|e| opts.flat || opts.shows_path(&e.rel_path))
+        .filter(|e| !pruned.contains(e.rel_path.as_path()))
+        .map(|e| max_total(&e.tokens))
+        .max()
+        .unwrap_or(0)
+}
 
+/// Write `(row, share)` lines, padding rows so the `--percent` / `--bars`
+/// columns after them line up.
+fn write_rows(out: &mut dyn Write, rows: &[(String, String)]) -> std::io::Result<()> {
+    let width = rows
+        .iter()
+        .filter(|(_, share)| !share.is_empty())
+        .map(|(row, _)| display_width(row))
+        .max()
+        .unwrap_or(0);
+    for (row, share) in rows {
+        if share.is_empty() {
+            writeln!(out, "{row}")?;
+        } else {
+            let pad = width - display_width(row);
+            writeln!(out, "{row}{:pad$}{share}", "", pad = pad)?;
+        }
+    }
     Ok(())
 }
 
 /// Recursively build a `Tree<TreeNode>` for `prefix`.
 ///
-/// `fmt_counts` is called for every file leaf to produce the pre-formatted
-/// count string stored in `TreeNode::File::counts`.
+/// Each subdirectory row carries the subtotal of every file beneath it,
+/// formatted by `fmt.dir` into the same columns as the file rows.  With
+/// `--summarize`, file rows are left out, directory rows add a file count,
+/// and directories below `--depth` are folded into their ancestor.
+/// Otherwise a directory at `--depth` gets a single collapsed row in place
+/// of its contents, and the files in `fmt.pruned` are folded into one row
+/// after the directory's other files.
 fn build_tree_node(
     label: TreeNode,
-    entries: &[FileResult],
+    dirs: &DirIndex<'_>,
     prefix: &Path,
     opts: &OutputOptions,
-    fmt_counts: &dyn Fn(&FileResult) -> String,
+    fmt: &RowFormat,
 ) -> Tree<TreeNode> {
-    let mut files: Vec<&FileResult> = entries
-        .iter()
-        .filter(|e| e.rel_path.parent() == Some(prefix))
-        .collect();
+    let mut node = Tree::new(label);
+    let here = dirs.get(prefix);
+
+    if !opts.summarize && opts.hides_contents(prefix) {
+        if here.file_count > 0 {
+            node.push(Tree::new(TreeNode::Collapsed(format_collapsed(
+                here.file_count,
+                &here.totals,
+                opts.color,
+            ))));
+        }
+        return node;
+    }
 
-    let mut subdirs: Vec<String> = entries
+    let mut files = here.files.clone();
+    let mut subdirs: Vec<(&str, &DirStats<'_>)> = here
+        .subdirs
         .iter()
-        .filter_map(|e| {
-            let rel = e.rel_path.strip_prefix(prefix).ok()?;
-            let mut comps = rel.components();
-            let first = comps.next()?.as_os_str().to_string_lossy().into_owned();
-            comps.next()?;
-            Some(first)
-        })
-        .collect::<std::collections::BTreeSet<_>>()
-        .into_iter()
+        .map(|name| (name.as_str(), dirs.get(&prefix.join(name))))
         .collect();
 
     if opts.sort {
         sort_by_tokens(&mut files);
-        subdirs.sort();
+        subdirs.sort_by_key(|(_, stats)| std::cmp::Reverse(max_total(&stats.totals)));
     }
 
-    let mut node = Tree::new(label);
-
-    for dir_name in &subdirs {
+    for (dir_name, stats) in subdirs {
         let dir_prefix = prefix.join(dir_name);
-        let dir_label = TreeNode::Dir(format_dir_label(dir_name, opts.color));
-        node.push(build_tree_node(
-            dir_label,
-            entries,
-            &dir_prefix,
-            opts,
-            fmt_counts,
-        ));
+        let totals = &stats.totals;
+        let mut counts = String::new();
+        if opts.summarize {
+            counts = format!(
+                "{:<w$}{}",
+                (fmt.dir)(totals),
+                format_file_count(stats.file_count, fmt.files_width),
+                w = fmt.count_width
+            );
+        } else if !totals.is_empty() {
+            counts = (fmt.dir)(totals);
+        }
+        if !totals.is_empty() {
+            counts += &fallback_marker(stats.fallback, opts.color);
+            if let Some(budget) = opts.budget {
+                let used = max_total(totals);
+                counts = format!("{counts}  {}", format_budget_pct(used, budget, opts.color));
+            }
+        }
+        let label = TreeNode::Dir {
+            label: format_dir_label(dir_name, opts.color),
+            width: dir_name.chars().count() + 1,
+            counts,
+            share: fmt.shares.format(totals, opts.summarize),
+        };
+        if opts.summarize && opts.hides_contents(&dir_prefix) {
+            node.push(Tree::new(label));
+        } else {
+            node.push(build_tree_node(label, dirs, &dir_prefix, opts, fmt));
+        }
     }
 
+    if opts.summarize {
+        return node;
+    }
+
+    let (hidden, files): (Vec<&FileResult>, Vec<&FileResult>) = files
+        .into_iter()
+        .partition(|file| fmt.pruned.contains(file.rel_path.as_path()));
     for file in &files {
         let name = file.rel_path.file_name().map_or_else(
             || file.rel_path.display().to_string(),
             |n| n.to_string_lossy().into_owned(),
         );
-        let counts = fmt_counts(file);
-        node.push(Tree::new(TreeNode::File { name, counts }));
+        let counts = (fmt.file)(file);
+        let share = fmt.shares.format(&file.tokens, true);
+        node.push(Tree::new(TreeNode::File {
+            name,
+            counts,
+            share,
+        }));
+    }
+    if !hidden.is_empty() {
+        node.push(Tree::new(TreeNode::Collapsed(pruned_row(&hidden, opts))));
     }
 
     node
 }
 
+/// The row standing in for files hidden by `--min-tokens` and `--top`.
+fn pruned_row(hidden: &[&FileResult], opts: &OutputOptions) -> String {
+    let mut totals = BTreeMap::new();
+    for entry in hidden {
+        add_counts(&mut totals, entry);
+    }
+    format_pruned(hidden.len(), &totals, opts.color)
+}
+
+/// A directory's own name, for display.
+fn dir_name(dir: &Path) -> std::borrow::Cow<'_, str> {
+    dir.file_name().map_or_else(
+        || dir.as_os_str().to_string_lossy(),
+        |n| n.to_string_lossy(),
+    )
+}
+
+/// The TOTAL row's file count with `--summarize`, or `""` without.
+fn summary_file_count(entries: &[FileResult], opts: &OutputOptions) -> String {
+    if opts.summarize {
+        format_file_count(entries.len(), 0)
+    } else {
+        String::new()
+    }
+}
+
 // ─── Flat mode ────────────────────────────────────────────────────────────────
 
 fn write_flat(
@@ -352,22 +897,26 @@ fn write_flat(
     entries: &[FileResult],
     opts: &OutputOptions,
 ) -> std::io::Result<()> {
-    let mut sorted: Vec<&FileResult> = entries.iter().collect();
-    if opts.sort {
-        sort_by_tokens(&mut sorted);
-    }
+    let pruned = opts.pruned_files(entries);
+    let (hidden, shown): (Vec<&FileResult>, Vec<&FileResult>) = entries
+        .iter()
+        .partition(|e| pruned.contains(e.rel_path.as_path()));
+    let flat_rows = flat_rows(&shown, opts);
+    let hidden_row = (!hidden.is_empty()).then(|| pruned_row(&hidden, opts));
+    let mut totals: BTreeMap<TokenizerId, TokenCount> = BTreeMap::new();
+    accumulate_totals(entries, &mut totals);
+    let shares = ShareColumns::new(opts, &totals, largest_row(entries, None, opts, &pruned));
+    let heatmap = Heatmap::new(entries, opts);
 
-    let path_w = sorted
+    let path_w = flat_rows
         .iter()
-        .map(|e| e.rel_path.display().to_string().chars().count())
+        .map(|row| row.label().chars().count())
         .max()
         .unwrap_or(0)
         .max(TOTAL_LABEL.len()); // at least wide enough for "PATH" header and total label
 
     if matches!(&opts.count_format, CountFormat::Named) {
         let ids = all_tokenizer_ids(entries);
-        let mut totals: BTreeMap<TokenizerId, TokenCount> = BTreeMap::new();
-        accumulate_totals(entries, &mut totals);
         let layouts = compute_col_layouts(entries, &ids, &totals);
 
         // Header.
@@ -375,50 +924,234 @@ fn write_flat(
         writeln!(out, "{:<path_w$}{header_cols}", "PATH", path_w = path_w)?;
 
         // Rows.
-        for entry in &sorted {
-            let path_str = entry.rel_path.display().to_string();
-            if matches!(&entry.kind, FileKind::Text) {
+        let mut rows: Vec<(String, String)> = Vec::new();
+        for row in &flat_rows {
+            let path_str = row.label();
+            let entry = match row {
+                FlatRow::File(entry) => entry,
+                FlatRow::Collapsed { files, totals, .. } => {
+                    let collapsed = format_collapsed(*files, totals, opts.color);
+                    rows.push((format!("{path_str:<path_w$}  {collapsed}"), String::new()));
+                    continue;
+                }
+            };
+            let row = if entry.shows_counts() {
                 let cols = format_named_columns(&entry.tokens, &ids, &layouts);
-                writeln!(out, "{path_str:<path_w$}{cols}")?;
+                let cols = heatmap.paint(&cols, &entry.tokens);
+                let marker = row_marker(entry, opts.color);
+                format!("{path_str:<path_w$}{cols}{marker}")
             } else {
-                let label = format_tokens(entry, &opts.count_format, opts.color);
-                writeln!(out, "{path_str:<path_w$}  {label}")?;
-            }
+                let label = format_tokens(entry, &opts.count_format, opts.color, None);
+                format!("{path_str:<path_w$}  {label}")
+            };
+            rows.push((row, shares.format(&entry.tokens, true)));
         }
+        rows.extend(hidden_row.map(|row| (row, String::new())));
+        write_rows(out, &rows)?;
 
         // Totals row.
         if !totals.is_empty() {
             let total_cols = format_named_columns(&totals, &ids, &layouts);
-            writeln!(out, "\n{TOTAL_LABEL:<path_w$}{total_cols}")?;
+            let budget = budget_suffix(&totals, opts);
+            let marker = fallback_marker(has_fallback(entries), opts.color);
+            writeln!(out, "\n{TOTAL_LABEL:<path_w$}{total_cols}{marker}{budget}")?;
+            write_fallback_note(out, entries)?;
         }
     } else {
         // Single / Range: align the count block start to a fixed column.
-        for entry in &sorted {
-            let path_str = entry.rel_path.display().to_string();
-            let count_str = format_tokens(entry, &opts.count_format, opts.color);
-            writeln!(out, "{path_str:<path_w$}  {count_str}")?;
-        }
+        let mut rows: Vec<(String, String)> = flat_rows
+            .iter()
+            .map(|row| {
+                let path_str = row.label();
+                let entry = match row {
+                    FlatRow::File(entry) => entry,
+                    FlatRow::Collapsed { files, totals, .. } => {
+                        let collapsed = format_collapsed(*files, totals, opts.color);
+                        return (format!("{path_str:<path_w$}  {collapsed}"), String::new());
+                    }
+                };
+                let heat = heatmap.heat(&entry.tokens);
+                let count_str = format_tokens(entry, &opts.count_format, opts.color, heat);
+                let share = shares.format(&entry.tokens, true);
+                (format!("{path_str:<path_w$}  {count_str}"), share)
+            })
+            .collect();
+        rows.extend(hidden_row.map(|row| (row, String::new())));
+        write_rows(out, &rows)?;
         write_totals(out, entries, opts)?;
     }
 
     Ok(())
 }
 
+/// A `--flat` row: a file, or the files below `--depth` in one directory.
+enum FlatRow<'a> {
+    File(&'a FileResult),
+    Collapsed {
+        dir: std::path::PathBuf,
+        files: usize,
+        totals: BTreeMap<TokenizerId, TokenCount>,
+    },
+}
+
+impl FlatRow<'_> {
+    /// The path column: the file's path, or the directory's with a `/`.
+    fn label(&self) -> String {
+        match self {
+            Self::File(entry) => entry.rel_path.display().to_string(),
+            Self::Collapsed { dir, .. } if dir.as_os_str().is_empty() => "./".to_string(),
+            Self::Collapsed { dir, .. } => format!("{}/", dir.display()),
+        }
+    }
+
+    fn max_tokens(&self) -> usize {
+        let counts = match self {
+            Self::File(entry) => &entry.tokens,
+            Self::Collapsed { totals, .. } => totals,
+        };
+        counts.values().map(TokenCount::hi).max().unwrap_or(0)
+    }
+}
+
+/// The `--flat` rows for `shown`.  Files below `--depth` are folded into one
+/// row per directory at the limit, in place of the first of them, like the
+/// tree's collapsed rows.
+fn flat_rows<'a>(shown: &[&'a FileResult], opts: &OutputOptions) -> Vec<FlatRow<'a>> {
+    let mut rows: Vec<FlatRow<'a>> = Vec::with_capacity(shown.len());
+    let mut collapsed: BTreeMap<std::path::PathBuf, usize> = BTreeMap::new();
+    for &entry in shown {
+        if opts.shows_path(&entry.rel_path) {
+            rows.push(FlatRow::File(entry));
+            continue;
+        }
+        let depth = opts.depth.unwrap_or(0);
+        let dir: std::path::PathBuf = entry.rel_path.components().take(depth).collect();
+        let idx = *collapsed.entry(dir.clone()).or_insert_with(|| {
+            rows.push(FlatRow::Collapsed {
+                dir,
+                files: 0,
+                totals: BTreeMap::new(),
+            });
+            rows.len() - 1
+        });
+        if let FlatRow::Collapsed { files, totals, .. } = &mut rows[idx] {
+            *files += 1;
+            add_counts(totals, entry);
+        }
+    }
+    if opts.sort {
+        rows.sort_by(|a, b| {
+            b.max_tokens()
+                .cmp(&a.max_tokens())
+                .then_with(|| a.label().cmp(&b.label()))
+        });
+    }
+    rows
+}
+
+/// `--flat --summarize`: one row per directory, with its full path.
+fn write_flat_summary(
+    out: &mut dyn Write,
+    entries: &[FileResult],
+    opts: &OutputOptions,
+) -> std::io::Result<()> {
+    let index = DirIndex::new(entries);
+    let mut dirs: Vec<(String, &DirStats<'_>)> = index
+        .shown(opts)
+        .map(|dir| (format!("{}/", dir.display()), index.get(dir)))
+        .collect();
+    if opts.sort {
+        dirs.sort_by_key(|(_, stats)| std::cmp::Reverse(max_total(&stats.totals)));
+    }
+
+    let mut totals: BTreeMap<TokenizerId, TokenCount> = BTreeMap::new();
+    accumulate_totals(entries, &mut totals);
+    let named = matches!(opts.count_format, CountFormat::Named);
+    let ids = all_tokenizer_ids(entries);
+    let layouts = compute_col_layouts(entries, &ids, &totals);
+    let fmt_counts = |totals: &BTreeMap<TokenizerId, TokenCount>| {
+        if named {
+            format_named_columns(totals, &ids, &layouts)
+        } else if totals.is_empty() {
+            String::new()
+        } else {
+            format!("  [{}]", format_counts(totals, &opts.count_format))
+        }
+    };
+    let count_w = fmt_counts(&totals).chars().count();
+    let files_w = format_number(entries.len()).len();
+    let path_w = dirs
+        .iter()
+        .map(|(path, ..)| path.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(TOTAL_LABEL.len());
+
+    if named {
+        let header_cols = format_named_header(&ids, &layouts);
+        writeln!(out, "{:<path_w$}{header_cols}", "PATH", path_w = path_w)?;
+    }
+    let shares = ShareColumns::new(
+        opts,
+        &totals,
+        largest_row(entries, Some(&index), opts, &BTreeSet::new()),
+    );
+    let mut rows: Vec<(String, String)> = Vec::new();
+    for (path, stats) in &dirs {
+        let dir_totals = &stats.totals;
+        let counts = fmt_counts(dir_totals);
+        let files = format_file_count(stats.file_count, files_w);
+        let marker = fallback_marker(stats.fallback, opts.color);
+        let budget =
+            opts.budget
+                .filter(|_| !dir_totals.is_empty())
+                .map_or_else(String::new, |budget| {
+                    format!(
+                        "  {}",
+                        format_budget_pct(max_total(dir_totals), budget, opts.color)
+                    )
+                });
+        rows.push((
+            format!("{path:<path_w$}{counts:<count_w$}{files}{marker}{budget}"),
+            shares.format(dir_totals, true),
+        ));
+    }
+    write_rows(out, &rows)?;
+
+    if !totals.is_empty() {
+        let total_counts = fmt_counts(&totals);
+        let files = format_file_count(entries.len(), files_w);
+        let budget = budget_suffix(&totals, opts);
+        let marker = fallback_marker(has_fallback(entries), opts.color);
+        writeln!(
+            out,
+            "\n{TOTAL_LABEL:<path_w$}{total_counts}{files}{marker}{budget}"
+        )?;
+        write_fallback_note(out, entries)?;
+    }
+    Ok(())
+}
+
 // ─── JSON mode ────────────────────────────────────────────────────────────────
 
 fn write_json(
     out: &mut dyn Write,
     root_label: &str,
     entries: &[FileResult],
+    opts: &OutputOptions,
 ) -> std::io::Result<()> {
     use serde_json::{Map, Value};
 
+    let budget = opts.budget;
+
     let files: Vec<Value> = entries
         .iter()
         .map(|e| {
             let path_str = e.rel_path.display().to_string();
             let type_str = match &e.kind {
-                FileKind::Text => "text",
+                FileKind::Text(_) | FileKind::Chunked(_) => "text",
+                FileKind::Image(_) => "image",
+                FileKind::Pdf(_) => "pdf",
                 FileKind::Binary => "binary",
                 FileKind::TooLarge => "too_large",
                 FileKind::Error(_) => "error",
@@ -427,35 +1160,63 @@ fn write_json(
             let tokens_val: Value = if e.tokens.is_empty() {
                 Value::Null
             } else {
-                let map: Map<String, Value> = e
-                    .tokens
-                    .iter()
-                    .map(|(k, v)| {
-                        let json_val = match v {
-                            TokenCount::Exact(n) => Value::from(*n),
-                            TokenCount::Approx { lo, hi } => {
-                                serde_json::json!({ "lo": lo, "hi": hi })
-                            }
-                        };
-                        (k.as_str().to_string(), json_val)
-                    })
-                    .collect();
-                Value::Object(map)
+                tokens_json(&e.tokens)
             };
 
             let mut obj = Map::new();
             obj.insert("path".to_string(), Value::from(path_str));
             obj.insert("type".to_string(), Value::from(type_str));
             obj.insert("tokens".to_string(), tokens_val);
+            if !e.wrapped.is_empty() {
+                obj.insert("wrapped".to_string(), counts_json(&e.wrapped));
+            }
+            if !e.fallback.is_empty() {
+                let ids: Vec<&str> = e.fallback.iter().map(TokenizerId::as_str).collect();
+                obj.insert("fallback".to_string(), Value::from(ids));
+            }
+            if let Some(budget) = budget.filter(|_| !e.tokens.is_empty()) {
+                let used = max_total(&e.tokens);
+                obj.insert(
+                    "fraction".to_string(),
+                    Value::from(budget_fraction(used, budget)),
+                );
+            }
 
             match &e.kind {
+                FileKind::Text(encoding) => {
+                    obj.insert("encoding".to_string(), Value::from(encoding.as_str()));
+                }
+                FileKind::Chunked(encoding) => {
+                    obj.insert("encoding".to_string(), Value::from(encoding.as_str()));
+                    obj.insert("chunked".to_string(), Value::from(true));
+                }
+                FileKind::Image(image) => {
+                    obj.insert(
+                        "image".to_string(),
+                        serde_json::json!({
+                            "format": image.format.as_str(),
+                            "width": image.width,
+                            "height": image.height,
+                        }),
+                    );
+                }
+                FileKind::Pdf(pdf) => {
+                    obj.insert(
+                        "pdf".to_string(),
+                        serde_json::json!({
+                            "pages": pdf.pages,
+                            "width_pt": pdf.width_pt,
+                            "height_pt": pdf.height_pt,
+                        }),
+                    );
+                }
                 FileKind::TooLarge => {
                     obj.insert("skipped".to_string(), Value::from("too large"));
                 }
                 FileKind::Error(msg) => {
                     obj.insert("skipped".to_string(), Value::from(msg.as_str()));
                 }
-                _ => {}
+                FileKind::Binary => {}
             }
 
             Value::Object(obj)
@@ -464,24 +1225,30 @@ fn write_json(
 
     let mut totals: BTreeMap<TokenizerId, TokenCount> = BTreeMap::new();
     accumulate_totals(entries, &mut totals);
-    let totals_val: Map<String, Value> = totals
-        .iter()
-        .map(|(k, v)| {
-            let json_val = match v {
-                TokenCount::Exact(n) => Value::from(*n),
-                TokenCount::Approx { lo, hi } => {
-                    serde_json::json!({ "lo": lo, "hi": hi })
-                }
-            };
-            (k.as_str().to_string(), json_val)
-        })
-        .collect();
 
-    let output = serde_json::json!({
+    let mut output = serde_json::json!({
         "root": root_label,
-        "files": files,
-        "total": Value::Object(totals_val),
+        "dirs": dirs_json(&DirIndex::new(entries), Path::new(""), opts),
+        "total": tokens_json(&totals),
     });
+    if opts.summarize {
+        output["file_count"] = Value::from(entries.len());
+    } else {
+        output["files"] = Value::from(files);
+    }
+    let mut wrapped_totals: BTreeMap<TokenizerId, usize> = BTreeMap::new();
+    for e in entries {
+        for (id, n) in &e.wrapped {
+            *wrapped_totals.entry(id.clone()).or_default() += n;
+        }
+    }
+    if !wrapped_totals.is_empty() {
+        output["wrapped_total"] = counts_json(&wrapped_totals);
+    }
+    if let Some(budget) = budget {
+        output["budget"] = Value::from(budget);
+        output["fraction"] = Value::from(budget_fraction(max_total(&totals), budget));
+    }
 
     let json_str =
         serde_json::to_string_pretty(&output).map_err(|e| std::io::Error::other(e.to_string()))?;
@@ -489,6 +1256,62 @@ fn write_json(
     writeln!(out, "{json_str}")
 }
 
+/// A `{"tokenizer": count}` JSON object, with approximate counts as
+/// `{"lo", "hi"}` ranges.
+fn tokens_json(tokens: &BTreeMap<TokenizerId, TokenCount>) -> serde_json::Value {
+    tokens
+        .iter()
+        .map(|(id, count)| {
+            let value = match count {
+                TokenCount::Exact(n) => serde_json::Value::from(*n),
+                TokenCount::Approx { lo, hi } => serde_json::json!({ "lo": lo, "hi": hi }),
+            };
+            (id.as_str().to_string(), value)
+        })
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+/// The directories under `prefix`, each with its subtotal, file count, and
+/// its own subdirectories nested under `"dirs"`.  With `--summarize`,
+/// nesting stops at `--depth`.
+fn dirs_json(dirs: &DirIndex<'_>, prefix: &Path, opts: &OutputOptions) -> serde_json::Value {
+    dirs.get(prefix)
+        .subdirs
+        .iter()
+        .map(|name| prefix.join(name))
+        .filter(|path| !opts.summarize || opts.shows_path(path))
+        .map(|path| {
+            let stats = dirs.get(&path);
+            let totals = &stats.totals;
+            let mut obj = serde_json::Map::new();
+            obj.insert("path".to_string(), path.display().to_string().into());
+            let tokens = if totals.is_empty() {
+                serde_json::Value::Null
+            } else {
+                tokens_json(totals)
+            };
+            obj.insert("tokens".to_string(), tokens);
+            obj.insert("file_count".to_string(), stats.file_count.into());
+            if let Some(budget) = opts.budget.filter(|_| !totals.is_empty()) {
+                let fraction = budget_fraction(max_total(totals), budget);
+                obj.insert("fraction".to_string(), fraction.into());
+            }
+            obj.insert("dirs".to_string(), dirs_json(dirs, &path, opts));
+            serde_json::Value::Object(obj)
+        })
+        .collect()
+}
+
+/// A `{"tokenizer": count}` JSON object.
+fn counts_json(counts: &BTreeMap<TokenizerId, usize>) -> serde_json::Value {
+    counts
+        .iter()
+        .map(|(id, n)| (id.as_str().to_string(), serde_json::Value::from(*n)))
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
 // ─── Totals ───────────────────────────────────────────────────────────────────
 
 /// Label used for the totals row in tabular output.
@@ -497,14 +1320,26 @@ const TOTAL_LABEL: &str = "Total";
 /// Sum token counts from `entries` into `totals`, merging by tokenizer id.
 pub fn accumulate_totals(entries: &[FileResult], totals: &mut BTreeMap<TokenizerId, TokenCount>) {
     for entry in entries {
-        for (name, count) in &entry.tokens {
-            match totals.entry(*name) {
-                std::collections::btree_map::Entry::Occupied(mut e) => {
-                    e.get_mut().add(count);
-                }
-                std::collections::btree_map::Entry::Vacant(e) => {
-                    e.insert(count.clone());
-                }
+        add_counts(totals, entry);
+    }
+}
+
+fn add_counts(totals: &mut BTreeMap<TokenizerId, TokenCount>, entry: &FileResult) {
+    merge_counts(totals, &entry.tokens);
+}
+
+/// Add every count in `counts` into `totals`, merging by tokenizer id.
+fn merge_counts(
+    totals: &mut BTreeMap<TokenizerId, TokenCount>,
+    counts: &BTreeMap<TokenizerId, TokenCount>,
+) {
+    for (name, count) in counts {
+        match totals.entry(name.clone()) {
+            std::collections::btree_map::Entry::Occupied(mut e) => {
+                e.get_mut().add(count);
+            }
+            std::collections::btree_map::Entry::Vacant(e) => {
+                e.insert(count.clone());
             }
         }
     }
@@ -530,24 +1365,143 @@ fn write_totals(
     }
 
     let total_str = format_counts(&totals, &opts.count_format);
-    writeln!(out, "\n{TOTAL_LABEL}: [{total_str}]")
+    let files = summary_file_count(entries, opts);
+    let budget = budget_suffix(&totals, opts);
+    let marker = fallback_marker(has_fallback(entries), opts.color);
+    writeln!(out, "\n{TOTAL_LABEL}: [{total_str}]{files}{marker}{budget}")?;
+    write_fallback_note(out, entries)
+}
+
+/// Whether any entry carries a fallback estimate.
+fn has_fallback(entries: &[FileResult]) -> bool {
+    entries.iter().any(|e| !e.fallback.is_empty())
+}
+
+/// Explain the fallback marker below the TOTAL row: one line per tokenizer
+/// that fell back, with the number of files affected.
+fn write_fallback_note(out: &mut dyn Write, entries: &[FileResult]) -> std::io::Result<()> {
+    let mut failed: BTreeMap<&TokenizerId, usize> = BTreeMap::new();
+    for id in entries.iter().flat_map(|e| &e.fallback) {
+        *failed.entry(id).or_default() += 1;
+    }
+    for (id, n) in failed {
+        let files = if n == 1 { "file" } else { "files" };
+        writeln!(
+            out,
+            "* {id}: API call failed for {n} {files}; offline estimate shown"
+        )?;
+    }
+    Ok(())
+}
+
+/// The `--budget` annotation appended to a TOTAL row, or `""` without a budget.
+fn budget_suffix(totals: &BTreeMap<TokenizerId, TokenCount>, opts: &OutputOptions) -> String {
+    opts.budget.map_or_else(String::new, |budget| {
+        format!(
+            "  {}",
+            format_budget_total(max_total(totals), budget, opts.color)
+        )
+    })
+}
+
+/// One directory's files and the aggregates its row shows.
+#[derive(Default)]
+struct DirStats<'a> {
+    /// Files directly in the directory, in entry order.
+    files: Vec<&'a FileResult>,
+    /// Names of its subdirectories that hold files, sorted.
+    subdirs: BTreeSet<String>,
+    /// Subtotal of every file beneath it.
+    totals: BTreeMap<TokenizerId, TokenCount>,
+    /// Number of files beneath it.
+    file_count: usize,
+    /// Whether any file beneath it carries a fallback estimate.
+    fallback: bool,
+}
+
+/// Every directory holding files, directly or below, keyed by its path
+/// relative to the root (`""` for the root).
+///
+/// Built once per output: each file is added to its own directory, then
+/// every directory's aggregates are folded into its parent's, deepest
+/// first, so rows never rescan the entries beneath them.
+struct DirIndex<'a>(BTreeMap<&'a Path, DirStats<'a>>);
+
+impl<'a> DirIndex<'a> {
+    fn new(entries: &'a [FileResult]) -> Self {
+        let root = Path::new("");
+        let mut dirs: BTreeMap<&Path, DirStats<'_>> = BTreeMap::new();
+        dirs.insert(root, DirStats::default());
+        for entry in entries {
+            let parent = entry.rel_path.parent().unwrap_or(root);
+            let stats = dirs.entry(parent).or_default();
+            stats.files.push(entry);
+            add_counts(&mut stats.totals, entry);
+            stats.file_count += 1;
+            stats.fallback |= !entry.fallback.is_empty();
+            // Link each new directory to its parent, up to the first one
+            // already linked.
+            for dir in parent.ancestors().filter(|d| *d != root) {
+                let up = dir.parent().unwrap_or(root);
+                let name = dir_name(dir).into_owned();
+                if !dirs.entry(up).or_default().subdirs.insert(name) {
+                    break;
+                }
+            }
+        }
+
+        // A directory sorts after its parent, so in reverse every
+        // directory is complete before it is folded upwards.
+        let paths: Vec<&Path> = dirs.keys().rev().copied().collect();
+        for dir in paths {
+            let Some(up) = dir.parent() else { continue };
+            let (totals, file_count, fallback) = {
+                let stats = &dirs[dir];
+                (stats.totals.clone(), stats.file_count, stats.fallback)
+            };
+            let parent = dirs.entry(up).or_default();
+            merge_counts(&mut parent.totals, &totals);
+            parent.file_count += file_count;
+            parent.fallback |= fallback;
+        }
+        Self(dirs)
+    }
+
+    /// The stats for `dir`, which must be the root or a directory named by
+    /// [`shown`](Self::shown) or some directory's `subdirs`.
+    fn get(&self, dir: &Path) -> &DirStats<'a> {
+        &self.0[dir]
+    }
+
+    /// Every directory `--summarize` shows a row for, in tree order.
+    fn shown<'s>(&'s self, opts: &'s OutputOptions) -> impl Iterator<Item = &'a Path> + 's {
+        self.0
+            .keys()
+            .copied()
+            .filter(|dir| !dir.as_os_str().is_empty() && opts.shows_path(dir))
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::collections::BTreeMap;
+    use std::collections::{BTreeMap, BTreeSet};
 
     use rstest::rstest;
 
-    use super::{accumulate_totals, max_total, FileResult, TokenCount};
+    use std::path::Path;
+
+    use super::{DirIndex, FileResult, Threshold, TokenCount, accumulate_totals, max_total};
+    use crate::encoding::Encoding;
     use crate::tokenize::TokenizerId;
     use crate::walk::FileKind;
 
     fn text_result(counts: &[(TokenizerId, TokenCount)]) -> FileResult {
         FileResult {
             rel_path: "f.rs".into(),
-            kind: FileKind::Text,
+            kind: FileKind::Text(Encoding::Utf8),
             tokens: counts.iter().cloned().collect(),
+            wrapped: BTreeMap::new(),
+            fallback: BTreeSet::new(),
         }
     }
 
@@ -562,6 +1516,20 @@ mod tests {
         assert_eq!(totals[&TokenizerId::O200k].hi(), 30);
     }
 
+    #[test]
+    fn accumulate_totals_widens_exact_plus_approx_to_range() {
+        let entries = vec![
+            text_result(&[(TokenizerId::Claude, TokenCount::Exact(10))]),
+            text_result(&[(TokenizerId::Claude, TokenCount::Approx { lo: 90, hi: 110 })]),
+        ];
+        let mut totals = BTreeMap::new();
+        accumulate_totals(&entries, &mut totals);
+        assert!(matches!(
+            totals[&TokenizerId::Claude],
+            TokenCount::Approx { lo: 100, hi: 120 }
+        ));
+    }
+
     #[test]
     fn accumulate_totals_merges_across_calls() {
         let batch_a = vec![text_result(&[(TokenizerId::O200k, TokenCount::Exact(5))])];
@@ -572,12 +1540,58 @@ mod tests {
         assert_eq!(totals[&TokenizerId::O200k].hi(), 12);
     }
 
+    #[rstest]
+    #[case::tokens_inverted(Threshold::Tokens(10_000), Threshold::Tokens(4_000), true)]
+    #[case::tokens_equal(Threshold::Tokens(4_000), Threshold::Tokens(4_000), false)]
+    #[case::percentiles_inverted(Threshold::Percentile(95.0), Threshold::Percentile(75.0), true)]
+    #[case::percentiles_ordered(Threshold::Percentile(75.0), Threshold::Percentile(95.0), false)]
+    #[case::mixed(Threshold::Tokens(1_000_000), Threshold::Percentile(50.0), false)]
+    fn warn_above_crit(#[case] warn: Threshold, #[case] crit: Threshold, #[case] above: bool) {
+        assert_eq!(warn.is_above(crit), above);
+    }
+
+    #[test]
+    fn dir_index_folds_files_into_every_ancestor() {
+        let at = |path: &str, n: usize| FileResult {
+            rel_path: path.into(),
+            ..text_result(&[(TokenizerId::O200k, TokenCount::Exact(n))])
+        };
+        let mut fell_back = at("src/out/b.rs", 4);
+        fell_back.fallback.insert(TokenizerId::Claude);
+        let entries = vec![
+            at("README.md", 1),
+            at("src/lib.rs", 2),
+            at("src/out/a.rs", 3),
+            fell_back,
+            at("tests/t.rs", 5),
+        ];
+
+        let dirs = DirIndex::new(&entries);
+
+        let stats = |dir: &str| dirs.get(Path::new(dir));
+        assert_eq!(stats("").totals[&TokenizerId::O200k].hi(), 15);
+        assert_eq!(stats("").file_count, 5);
+        assert_eq!(stats("").files.len(), 1);
+        assert_eq!(
+            stats("").subdirs.iter().collect::<Vec<_>>(),
+            ["src", "tests"]
+        );
+        assert_eq!(stats("src").totals[&TokenizerId::O200k].hi(), 9);
+        assert_eq!(stats("src").file_count, 3);
+        assert!(stats("src").fallback);
+        assert!(!stats("tests").fallback);
+        assert_eq!(stats("src/out").files.len(), 2);
+        assert!(stats("src/out").subdirs.is_empty());
+    }
+
     #[test]
     fn accumulate_totals_skips_non_text() {
         let entries = vec![FileResult {
             rel_path: "img.bin".into(),
             kind: FileKind::Binary,
             tokens: BTreeMap::new(),
+            wrapped: BTreeMap::new(),
+            fallback: BTreeSet::new(),
         }];
         let mut totals = BTreeMap::new();
         accumulate_totals(&entries, &mut totals);
@@ -0,0 +1,553 @@
+//! Best-effort PDF reading: page count, page size, and the text drawn by
+//! content streams.
+//!
+//! This is not a PDF parser.  It scans for stream objects, inflates the
+//! `FlateDecode` ones, counts `/Type /Page` dictionaries, and pulls string
+//! operands out of text-showing operators.  That is enough to estimate what
+//! a model is charged for a document; fonts with custom encodings and no
+//! standard byte mapping come out as noise, and the estimate is marked
+//! approximate accordingly.
+
+/// Page size assumed when no `/MediaBox` is found: US Letter, in points.
+const DEFAULT_PAGE: (u32, u32) = (612, 792);
+/// Resolution pages are assumed to be rendered at for per-page image
+/// estimates.
+const RENDER_DPI: u32 = 150;
+/// Largest inflated stream kept; bigger ones are skipped rather than
+/// exhausting memory on a decompression bomb.
+const MAX_INFLATED: usize = 64 * 1024 * 1024;
+
+/// What a PDF's cost depends on besides its text.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PdfInfo {
+    /// Number of pages.
+    pub pages: usize,
+    /// Width of the first `/MediaBox`, in points.
+    pub width_pt: u32,
+    /// Height of the first `/MediaBox`, in points.
+    pub height_pt: u32,
+}
+
+impl PdfInfo {
+    /// Pixel size of one page rendered at [`RENDER_DPI`].
+    #[must_use]
+    pub const fn page_pixels(&self) -> (u32, u32) {
+        (
+            self.width_pt.saturating_mul(RENDER_DPI) / 72,
+            self.height_pt.saturating_mul(RENDER_DPI) / 72,
+        )
+    }
+}
+
+/// Returns `true` if `bytes` starts with the PDF header.
+#[must_use]
+pub fn is_pdf(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"%PDF-")
+}
+
+/// Read page information and the extracted text from a whole PDF file.
+/// Returns `None` if `data` is not a PDF or no page can be found in it.
+#[must_use]
+pub fn read(data: &[u8]) -> Option<(PdfInfo, String)> {
+    if !is_pdf(data) {
+        return None;
+    }
+    let streams = streams(data);
+
+    // Page dictionaries live either in the file body or, since PDF 1.5, in
+    // compressed object streams.
+    let pages = std::iter::once(data)
+        .chain(streams.iter().map(Vec::as_slice))
+        .map(count_pages)
+        .sum::<usize>();
+    if pages == 0 {
+        return None;
+    }
+    let (width_pt, height_pt) = std::iter::once(data)
+        .chain(streams.iter().map(Vec::as_slice))
+        .find_map(media_box)
+        .unwrap_or(DEFAULT_PAGE);
+
+    let mut text = String::new();
+    for stream in &streams {
+        extract_text(stream, &mut text);
+    }
+    Some((
+        PdfInfo {
+            pages,
+            width_pt,
+            height_pt,
+        },
+        text,
+    ))
+}
+
+/// Find `needle` in `haystack` at or after `from`.
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack
+        .get(from..)?
+        .windows(needle.len())
+        .position(|w| w == needle)
+        .map(|i| i + from)
+}
+
+/// The decoded contents of every unfiltered or `FlateDecode` stream that
+/// is not an image.
+fn streams(data: &[u8]) -> Vec<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut at = 0;
+    while let Some(kw) = find(data, b"stream", at) {
+        // Skip the `stream` inside `endstream`.
+        if data[..kw].ends_with(b"end") {
+            at = kw + 6;
+            continue;
+        }
+        let mut start = kw + 6;
+        if data.get(start) == Some(&b'\r') {
+            start += 1;
+        }
+        if data.get(start) == Some(&b'\n') {
+            start += 1;
+        }
+        let Some(end) = find(data, b"endstream", start) else {
+            break;
+        };
+        at = end + 9;
+
+        // The stream dictionary sits between the object header and `stream`.
+        let dict_start = data[..kw]
+            .windows(3)
+            .rposition(|w| w == b"obj")
+            .unwrap_or(0);
+        let dict = &data[dict_start..kw];
+        if contains(dict, b"/Image") {
+            continue;
+        }
+        let raw = &data[start..end];
+        if contains(dict, b"/FlateDecode") {
+            if let Ok(inflated) =
+                miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(raw, MAX_INFLATED)
+            {
+                out.push(inflated);
+            }
+        } else if !contains(dict, b"/Filter") {
+            out.push(raw.to_vec());
+        }
+    }
+    out
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    find(haystack, needle, 0).is_some()
+}
+
+/// Count `/Type /Page` dictionaries (but not `/Type /Pages`).
+fn count_pages(data: &[u8]) -> usize {
+    let mut n = 0;
+    let mut at = 0;
+    while let Some(i) = find(data, b"/Type", at) {
+        at = i + 5;
+        let rest = skip_whitespace(data, at);
+        if data[rest..].starts_with(b"/Page")
+            && !data.get(rest + 5).is_some_and(u8::is_ascii_alphanumeric)
+        {
+            n += 1;
+        }
+    }
+    n
+}
+
+fn skip_whitespace(data: &[u8], mut at: usize) -> usize {
+    while data.get(at).is_some_and(u8::is_ascii_whitespace) {
+        at += 1;
+    }
+    at
+}
+
+/// The width and height of the first `/MediaBox [x0 y0 x1 y1]`.
+fn media_box(data: &[u8]) -> Option<(u32, u32)> {
+    let at = find(data, b"/MediaBox", 0)? + 9;
+    let open = skip_whitespace(data, at);
+    if data.get(open) != Some(&b'[') {
+        return None;
+    }
+    let close = find(data, b"]", open)?;
+    let nums: Vec<f64> = std::str::from_utf8(&data[open + 1..close])
+        .ok()?
+        .split_ascii_whitespace()
+        .map(str::parse)
+        .collect::<Result<_, _>>()
+        .ok()?;
+    let [x0, y0, x1, y1] = nums[..] else {
+        return None;
+    };
+    let size = |a: f64, b: f64| {
+        let len = (b - a).abs().round();
+        // Page sizes are a few thousand points at most.
+        (1.0..=f64::from(u16::MAX)).contains(&len).then(|| {
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let len = len as u32;
+            len
+        })
+    };
+    Some((size(x0, x1)?, size(y0, y1)?))
+}
+
+// ─── Content-stream text ─────────────────────────────────────────────────────
+
+/// One lexical item of a content stream.
+enum Operand {
+    Str(Vec<u8>),
+    Array(Vec<Self>),
+    Number(f64),
+    Other,
+}
+
+/// Append the text shown by `stream`'s `Tj`, `TJ`, `'`, and `"` operators
+/// to `out`, breaking lines where the text position moves down.
+fn extract_text(stream: &[u8], out: &mut String) {
+    let mut operands: Vec<Operand> = Vec::new();
+    let mut lexer = Lexer {
+        data: stream,
+        at: 0,
+    };
+    while let Some(token) = lexer.next_token() {
+        let op = match token {
+            Token::Operand(operand) => {
+                operands.push(operand);
+                continue;
+            }
+            Token::Operator(op) => op,
+        };
+        match op.as_slice() {
+            b"Tj" => show(operands.last(), out),
+            b"'" | b"\"" => {
+                newline(out);
+                show(operands.last(), out);
+            }
+            b"TJ" => {
+                if let Some(Operand::Array(items)) = operands.last() {
+                    for item in items {
+                        match item {
+                            // A large negative kern is a word gap.
+                            Operand::Number(n) if *n < -200.0 => push_space(out),
+                            _ => show(Some(item), out),
+                        }
+                    }
+                }
+            }
+            b"Td" | b"TD" => match operands.last() {
+                Some(Operand::Number(ty)) if *ty != 0.0 => newline(out),
+                _ => push_space(out),
+            },
+            b"T*" | b"ET" => newline(out),
+            b"Tm" => push_space(out),
+            b"ID" => lexer.skip_inline_image(),
+            _ => {}
+        }
+        operands.clear();
+    }
+}
+
+fn show(operand: Option<&Operand>, out: &mut String) {
+    if let Some(Operand::Str(bytes)) = operand {
+        decode_into(bytes, out);
+    }
+}
+
+fn push_space(out: &mut String) {
+    if !out.is_empty() && !out.ends_with(char::is_whitespace) {
+        out.push(' ');
+    }
+}
+
+fn newline(out: &mut String) {
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+}
+
+/// Decode a shown string: UTF-16BE with a BOM, else one byte per character
+/// (`PDFDocEncoding` and `WinAnsi` agree with Latin-1 on printable ASCII and
+/// most accented letters).  Control characters are dropped.
+fn decode_into(bytes: &[u8], out: &mut String) {
+    if let Some(utf16) = bytes.strip_prefix(&[0xFE, 0xFF]) {
+        let units = utf16
+            .as_chunks::<2>()
+            .0
+            .iter()
+            .map(|&pair| u16::from_be_bytes(pair));
+        out.extend(
+            char::decode_utf16(units)
+                .filter_map(Result::ok)
+                .filter(|c| !c.is_control()),
+        );
+    } else {
+        out.extend(
+            bytes
+                .iter()
+                .map(|&b| char::from(b))
+                .filter(|c| !c.is_control()),
+        );
+    }
+}
+
+enum Token {
+    Operand(Operand),
+    Operator(Vec<u8>),
+}
+
+struct Lexer<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+impl Lexer<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.data.get(self.at).copied()
+    }
+
+    fn next_token(&mut self) -> Option<Token> {
+        loop {
+            self.at = skip_whitespace(self.data, self.at);
+            match self.peek()? {
+                b'%' => {
+                    while self.peek().is_some_and(|b| b != b'\n' && b != b'\r') {
+                        self.at += 1;
+                    }
+                }
+                b'(' => return Some(Token::Operand(Operand::Str(self.literal()))),
+                b'<' if self.data.get(self.at + 1) == Some(&b'<') => {
+                    self.skip_dict();
+                    return Some(Token::Operand(Operand::Other));
+                }
+                b'<' => return Some(Token::Operand(Operand::Str(self.hex()))),
+                b'[' => return Some(Token::Operand(self.array())),
+                b']' | b'>' | b'{' | b'}' | b')' => self.at += 1,
+                b'/' => {
+                    self.at += 1;
+                    self.word();
+                    return Some(Token::Operand(Operand::Other));
+                }
+                _ => {
+                    let word = self.word();
+                    if word.is_empty() {
+                        self.at += 1;
+                        continue;
+                    }
+                    let number = std::str::from_utf8(&word).ok().and_then(|s| s.parse().ok());
+                    return Some(number.map_or(Token::Operator(word), |n| {
+                        Token::Operand(Operand::Number(n))
+                    }));
+                }
+            }
+        }
+    }
+
+    /// A run of regular (non-delimiter, non-whitespace) characters.
+    fn word(&mut self) -> Vec<u8> {
+        let start = self.at;
+        while self
+            .peek()
+            .is_some_and(|b| !b.is_ascii_whitespace() && !b"()<>[]{}/%".contains(&b))
+        {
+            self.at += 1;
+        }
+        self.data[start..self.at].to_vec()
+    }
+
+    /// A `( … )` string with nested parentheses and backslash escapes.
+    fn literal(&mut self) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut depth = 0usize;
+        self.at += 1;
+        while let Some(b) = self.peek() {
+            self.at += 1;
+            match b {
+                b'(' => {
+                    depth += 1;
+                    out.push(b);
+                }
+                b')' if depth == 0 => break,
+                b')' => {
+                    depth -= 1;
+                    out.push(b);
+                }
+                b'\\' => {
+                    let Some(e) = self.peek() else { break };
+                    self.at += 1;
+                    match e {
+                        b'n' => out.push(b'\n'),
+                        b'r' => out.push(b'\r'),
+                        b't' => out.push(b'\t'),
+                        b'b' => out.push(0x08),
+                        b'f' => out.push(0x0C),
+                        b'0'..=b'7' => {
+                            let mut value = u32::from(e - b'0');
+                            for _ in 0..2 {
+                                match self.peek() {
+                                    Some(d @ b'0'..=b'7') => {
+                                        value = value * 8 + u32::from(d - b'0');
+                                        self.at += 1;
+                                    }
+                                    _ => break,
+                                }
+                            }
+                            out.push(u8::try_from(value & 0xFF).unwrap_or(0));
+                        }
+                        // Line continuation.
+                        b'\r' | b'\n' => {}
+                        other => out.push(other),
+                    }
+                }
+                _ => out.push(b),
+            }
+        }
+        out
+    }
+
+    /// A `< … >` hex string; an odd final digit is padded with `0`.
+    fn hex(&mut self) -> Vec<u8> {
+        self.at += 1;
+        let mut digits = Vec::new();
+        while let Some(b) = self.peek() {
+            self.at += 1;
+            if b == b'>' {
+                break;
+            }
+            if let Some(d) = char::from(b).to_digit(16) {
+                digits.push(u8::try_from(d).unwrap_or(0));
+            }
+        }
+        digits
+            .chunks(2)
+            .map(|pair| (pair[0] << 4) | pair.get(1).copied().unwrap_or(0))
+            .collect()
+    }
+
+    fn array(&mut self) -> Operand {
+        self.at += 1;
+        let mut items = Vec::new();
+        loop {
+            self.at = skip_whitespace(self.data, self.at);
+            match self.peek() {
+                None => break,
+                Some(b']') => {
+                    self.at += 1;
+                    break;
+                }
+                Some(_) => match self.next_token() {
+                    Some(Token::Operand(operand)) => items.push(operand),
+                    // Operators cannot appear in arrays; stop at malformed input.
+                    Some(Token::Operator(_)) | None => break,
+                },
+            }
+        }
+        Operand::Array(items)
+    }
+
+    /// Skip the binary data of an inline image, up to its `EI` operator.
+    fn skip_inline_image(&mut self) {
+        self.at = find(self.data, b"EI", self.at).map_or(self.data.len(), |i| i + 2);
+    }
+
+    /// Skip a `<< … >>` dictionary (e.g. inline-image or marked-content
+    /// properties), including nested ones.
+    fn skip_dict(&mut self) {
+        let mut depth = 0usize;
+        while self.at < self.data.len() {
+            if self.data[self.at..].starts_with(b"<<") {
+                depth += 1;
+                self.at += 2;
+            } else if self.data[self.at..].starts_with(b">>") {
+                depth -= 1;
+                self.at += 2;
+                if depth == 0 {
+                    return;
+                }
+            } else if self.data[self.at] == b'(' {
+                self.literal();
+            } else {
+                self.at += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    /// A one-font PDF with one page per entry of `pages`, each page's
+    /// content stream compressed with `FlateDecode`.
+    pub fn sample_pdf(pages: &[&str]) -> Vec<u8> {
+        let mut pdf = b"%PDF-1.4\n1 0 obj << /Type /Catalog /Pages 2 0 R >> endobj\n\
+                        2 0 obj << /Type /Pages /MediaBox [0 0 595 842] >> endobj\n"
+            .to_vec();
+        for (i, content) in pages.iter().enumerate() {
+            let stream = miniz_oxide::deflate::compress_to_vec_zlib(content.as_bytes(), 6);
+            pdf.extend_from_slice(
+                format!(
+                    "{} 0 obj << /Type /Page /Parent 2 0 R /Contents {} 0 R >> endobj\n\
+                     {} 0 obj << /Length {} /Filter /FlateDecode >>\nstream\n",
+                    10 + 2 * i,
+                    11 + 2 * i,
+                    11 + 2 * i,
+                    stream.len()
+                )
+                .as_bytes(),
+            );
+            pdf.extend_from_slice(&stream);
+            pdf.extend_from_slice(b"\nendstream\nendobj\n");
+        }
+        pdf.extend_from_slice(b"%%EOF\n");
+        pdf
+    }
+
+    #[test]
+    fn pages_size_and_text_are_read() {
+        let pdf = sample_pdf(&[
+            "BT /F1 12 Tf 72 720 Td (Hello, world!) Tj 0 -14 Td (Second line) Tj ET",
+            "BT [(Kern)-30(ed) -500 (words)] TJ ET",
+        ]);
+        let (info, text) = read(&pdf).unwrap();
+        assert_eq!(
+            info,
+            PdfInfo {
+                pages: 2,
+                width_pt: 595,
+                height_pt: 842,
+            }
+        );
+        assert_eq!(text, "Hello, world!\nSecond line\nKerned words\n");
+    }
+
+    #[rstest]
+    #[case::escapes(r"BT (a\(b\)c \101\102) Tj ET", "a(b)c AB\n")]
+    #[case::hex("BT <48656C6C6F> Tj ET", "Hello\n")]
+    #[case::utf16("BT <FEFF00E9> Tj ET", "\u{e9}\n")]
+    #[case::quote_starts_line("BT (one) Tj (two) ' ET", "one\ntwo\n")]
+    #[case::no_text("0 0 m 10 10 l S", "")]
+    #[case::inline_image("BI /W 1 /H 1 ID (x) Tj EI BT (after) Tj ET", "after\n")]
+    fn string_operands_are_decoded(#[case] content: &str, #[case] expected: &str) {
+        let mut text = String::new();
+        extract_text(content.as_bytes(), &mut text);
+        assert_eq!(text, expected);
+    }
+
+    #[rstest]
+    #[case::not_a_pdf(b"hello")]
+    #[case::no_pages(b"%PDF-1.7\n\xff\xfe garbage")]
+    fn unreadable(#[case] data: &[u8]) {
+        assert!(read(data).is_none());
+    }
+
+    #[test]
+    fn missing_media_box_defaults_to_letter() {
+        let (info, _) = read(b"%PDF-1.4\n1 0 obj << /Type /Page >> endobj\n").unwrap();
+        assert_eq!((info.width_pt, info.height_pt), DEFAULT_PAGE);
+        // Letter at 150 dpi.
+        assert_eq!(info.page_pixels(), (1275, 1650));
+    }
+}
@@ -0,0 +1,400 @@
+//! On-disk cache of Claude `count_tokens` and Gemini `countTokens` results.
+//!
+//! Counts are keyed by (SHA-256 of the API base and content, model, API
+//! version) and stored as a single JSON map under `$XDG_CACHE_HOME/treetok` (falling
+//! back to `~/.cache/treetok`).  A cache that cannot be read or written only
+//! costs network calls — it never fails a run.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use super::gemini_api::GEMINI_API_VERSION;
+use super::remote::ANTHROPIC_VERSION;
+
+/// File name of the count map inside the cache directory.
+const CACHE_FILE: &str = "counts.json";
+/// Name the map had while it held only Claude counts; `clear` removes it.
+const LEGACY_CACHE_FILE: &str = "claude-counts.json";
+/// Bumped when the on-disk format changes; other versions are ignored.
+/// Version 1 keys did not include the API base.
+const FORMAT_VERSION: u32 = 2;
+
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+struct CacheFile {
+    version: u32,
+    counts: HashMap<String, usize>,
+}
+
+/// API token counts persisted between runs.
+pub struct CountCache {
+    path: PathBuf,
+    counts: HashMap<String, usize>,
+    /// Entries added this run, merged into the file on [`save`](Self::save).
+    fresh: HashMap<String, usize>,
+}
+
+/// Summary printed by `treetok cache stats`.
+pub struct CacheStats {
+    /// Location of the cache file.
+    pub path: PathBuf,
+    /// Size of the cache file in bytes (0 when absent).
+    pub bytes: u64,
+    /// Number of cached counts per model.
+    pub models: BTreeMap<String, usize>,
+}
+
+impl CacheStats {
+    /// Total number of cached counts.
+    #[must_use]
+    pub fn entries(&self) -> usize {
+        self.models.values().sum()
+    }
+}
+
+/// Pick the cache directory from `XDG_CACHE_HOME` and `HOME`.
+///
+/// Per the XDG spec, a relative or empty `XDG_CACHE_HOME` is ignored.
+fn select_cache_dir(xdg_cache_home: Option<OsString>, home: Option<OsString>) -> Option<PathBuf> {
+    let base = xdg_cache_home
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| home.map(|h| PathBuf::from(h).join(".cache")))?;
+    Some(base.join("treetok"))
+}
+
+impl CountCache {
+    /// The default cache directory, or `None` when neither
+    /// `XDG_CACHE_HOME` nor `HOME` is set.
+    #[must_use]
+    pub fn default_dir() -> Option<PathBuf> {
+        select_cache_dir(std::env::var_os("XDG_CACHE_HOME"), std::env::var_os("HOME"))
+    }
+
+    /// Load the cache in `dir`.  A missing file gives an empty cache; an
+    /// unreadable one is reported and ignored.
+    #[must_use]
+    pub fn open(dir: &Path) -> Self {
+        let path = dir.join(CACHE_FILE);
+        let counts = read_counts(&path).unwrap_or_else(|e| {
+            eprintln!("warning: ignoring token cache {}: {e}", path.display());
+            HashMap::new()
+        });
+        Self {
+            path,
+            counts,
+            fresh: HashMap::new(),
+        }
+    }
+
+    /// Cache key for `content` counted by `model` at the API root
+    /// `api_base`.  Counts from a gateway or stub never stand in for the
+    /// real API's.
+    #[must_use]
+    pub fn key(content: &str, model: &str, api_base: &str) -> String {
+        hashed_key(
+            api_base,
+            b"\0",
+            content.as_bytes(),
+            model,
+            ANTHROPIC_VERSION,
+        )
+    }
+
+    /// Cache key for the PDF `data` counted by `model` as a `document`
+    /// block.  Hashed apart from [`key`](Self::key)'s so a text file with
+    /// the same bytes never shares its count.
+    #[must_use]
+    pub fn document_key(data: &[u8], model: &str, api_base: &str) -> String {
+        hashed_key(api_base, b"\0document\0", data, model, ANTHROPIC_VERSION)
+    }
+
+    /// Cache key for `content` counted by the Gemini `model` at the API
+    /// root `api_base`.
+    #[must_use]
+    pub fn gemini_key(content: &str, model: &str, api_base: &str) -> String {
+        hashed_key(
+            api_base,
+            b"\0",
+            content.as_bytes(),
+            model,
+            GEMINI_API_VERSION,
+        )
+    }
+
+    /// The cached count for `key`, if any.
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<usize> {
+        self.counts.get(key).copied()
+    }
+
+    /// Record a count fetched this run.
+    pub fn insert(&mut self, key: String, count: usize) {
+        self.counts.insert(key.clone(), count);
+        self.fresh.insert(key, count);
+    }
+
+    /// Write counts added since the last save.
+    ///
+    /// The file is re-read first so concurrent runs don't drop each other's
+    /// entries, then replaced atomically.
+    pub fn save(&mut self) -> std::io::Result<()> {
+        if self.fresh.is_empty() {
+            return Ok(());
+        }
+        let mut counts = read_counts(&self.path).unwrap_or_default();
+        counts.extend(self.fresh.drain());
+        write_counts(&self.path, counts)
+    }
+
+    /// Delete the cache file in `dir`, and any left under its old name.
+    /// Returns `false` if there was none.
+    pub fn clear(dir: &Path) -> std::io::Result<bool> {
+        let mut removed = false;
+        for name in [CACHE_FILE, LEGACY_CACHE_FILE] {
+            match std::fs::remove_file(dir.join(name)) {
+                Ok(()) => removed = true,
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Summarise the cache file in `dir`.
+    pub fn stats(dir: &Path) -> std::io::Result<CacheStats> {
+        let path = dir.join(CACHE_FILE);
+        let bytes = match std::fs::metadata(&path) {
+            Ok(m) => m.len(),
+            Err(e) if e.kind() == ErrorKind::NotFound => 0,
+            Err(e) => return Err(e),
+        };
+        let mut models: BTreeMap<String, usize> = BTreeMap::new();
+        for key in read_counts(&path)?.keys() {
+            // Keys are `hash:model:version`; models never contain ':'.
+            let model = key.split(':').nth(1).unwrap_or("?");
+            *models.entry(model.to_string()).or_default() += 1;
+        }
+        Ok(CacheStats {
+            path,
+            bytes,
+            models,
+        })
+    }
+}
+
+/// `hash:model:version`, hashing the normalized `api_base`, then `separator`,
+/// then `data`.
+fn hashed_key(api_base: &str, separator: &[u8], data: &[u8], model: &str, version: &str) -> String {
+    let digest = Sha256::new()
+        .chain_update(normalize_api_base(api_base))
+        .chain_update(separator)
+        .chain_update(data)
+        .finalize();
+    format!("{digest:x}:{model}:{version}")
+}
+
+/// `base` in a canonical form, so spellings of the same root (host case,
+/// default port, trailing slash) share cache entries.
+fn normalize_api_base(base: &str) -> String {
+    reqwest::Url::parse(base).map_or_else(
+        |_| base.trim_end_matches('/').to_string(),
+        |url| url.as_str().trim_end_matches('/').to_string(),
+    )
+}
+
+fn read_counts(path: &Path) -> std::io::Result<HashMap<String, usize>> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(t) => t,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e),
+    };
+    let file: CacheFile = serde_json::from_str(&text).map_err(std::io::Error::other)?;
+    if file.version == FORMAT_VERSION {
+        Ok(file.counts)
+    } else {
+        Ok(HashMap::new())
+    }
+}
+
+fn write_counts(path: &Path, counts: HashMap<String, usize>) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let file = CacheFile {
+        version: FORMAT_VERSION,
+        counts,
+    };
+    let json = serde_json::to_string(&file).map_err(std::io::Error::other)?;
+    let tmp = path.with_extension(format!("json.{}.tmp", std::process::id()));
+    std::fs::write(&tmp, json)?;
+    std::fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::super::remote::{ClaudeTokenizer, DEFAULT_API_BASE};
+    use super::super::resolve::ResolvedTokenizers;
+    use super::super::{TokenizerId, tokenize_entries};
+    use super::*;
+    use crate::encoding::Encoding;
+    use crate::walk::{FileEntry, FileKind};
+
+    #[rstest]
+    #[case::xdg(Some("/xdg"), Some("/home/u"), Some("/xdg/treetok"))]
+    #[case::home_fallback(None, Some("/home/u"), Some("/home/u/.cache/treetok"))]
+    #[case::relative_xdg_ignored(Some("rel"), Some("/home/u"), Some("/home/u/.cache/treetok"))]
+    #[case::empty_xdg_ignored(Some(""), Some("/home/u"), Some("/home/u/.cache/treetok"))]
+    #[case::neither(None, None, None)]
+    fn cache_dir_selection(
+        #[case] xdg: Option<&str>,
+        #[case] home: Option<&str>,
+        #[case] expected: Option<&str>,
+    ) {
+        assert_eq!(
+            select_cache_dir(xdg.map(OsString::from), home.map(OsString::from)),
+            expected.map(PathBuf::from)
+        );
+    }
+
+    const BASE: &str = DEFAULT_API_BASE;
+    const SONNET: &str = "claude-sonnet-4-6";
+
+    #[test]
+    fn key_depends_on_content_and_model() {
+        let key = CountCache::key("hello", SONNET, BASE);
+        assert_eq!(key, CountCache::key("hello", SONNET, BASE));
+        assert_ne!(key, CountCache::key("hello!", SONNET, BASE));
+        assert_ne!(key, CountCache::key("hello", "claude-opus-4-6", BASE));
+        assert!(key.ends_with(&format!(":{SONNET}:{ANTHROPIC_VERSION}")));
+    }
+
+    #[rstest]
+    #[case::trailing_slash("https://api.anthropic.com/", true)]
+    #[case::host_case("https://API.anthropic.com", true)]
+    #[case::default_port("https://api.anthropic.com:443", true)]
+    #[case::gateway("https://gateway.example.com/anthropic", false)]
+    #[case::stub("http://127.0.0.1:8080", false)]
+    fn key_depends_on_api_base(#[case] base: &str, #[case] same: bool) {
+        let key = CountCache::key("hello", SONNET, BASE);
+        assert_eq!(key == CountCache::key("hello", SONNET, base), same);
+        let doc = CountCache::document_key(b"%PDF", SONNET, BASE);
+        assert_eq!(doc == CountCache::document_key(b"%PDF", SONNET, base), same);
+    }
+
+    #[test]
+    fn counts_survive_save_and_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = CountCache::open(dir.path());
+        cache.insert("k".to_string(), 42);
+        cache.save().unwrap();
+        assert_eq!(CountCache::open(dir.path()).get("k"), Some(42));
+    }
+
+    #[test]
+    fn save_merges_with_concurrent_writer() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut a = CountCache::open(dir.path());
+        let mut b = CountCache::open(dir.path());
+        a.insert("a".to_string(), 1);
+        b.insert("b".to_string(), 2);
+        a.save().unwrap();
+        b.save().unwrap();
+        let merged = CountCache::open(dir.path());
+        assert_eq!((merged.get("a"), merged.get("b")), (Some(1), Some(2)));
+    }
+
+    #[test]
+    fn corrupt_file_is_ignored_and_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(CACHE_FILE), "not json").unwrap();
+        let mut cache = CountCache::open(dir.path());
+        assert_eq!(cache.get("k"), None);
+        cache.insert("k".to_string(), 7);
+        cache.save().unwrap();
+        assert_eq!(CountCache::open(dir.path()).get("k"), Some(7));
+    }
+
+    #[test]
+    fn stats_and_clear() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(CountCache::stats(dir.path()).unwrap().entries(), 0);
+        assert!(!CountCache::clear(dir.path()).unwrap());
+
+        let mut cache = CountCache::open(dir.path());
+        cache.insert(CountCache::key("a", SONNET, BASE), 1);
+        cache.insert(CountCache::key("b", SONNET, BASE), 2);
+        cache.insert(CountCache::key("a", "claude-opus-4-6", BASE), 1);
+        cache.save().unwrap();
+
+        let stats = CountCache::stats(dir.path()).unwrap();
+        assert_eq!(stats.entries(), 3);
+        assert_eq!(stats.models[SONNET], 2);
+        assert!(stats.bytes > 0);
+
+        assert!(CountCache::clear(dir.path()).unwrap());
+        assert_eq!(CountCache::stats(dir.path()).unwrap().entries(), 0);
+    }
+
+    /// `clear` also removes the file from when only Claude counts were cached.
+    #[test]
+    fn clear_removes_legacy_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let legacy = dir.path().join(LEGACY_CACHE_FILE);
+        std::fs::write(&legacy, "{}").unwrap();
+        assert!(CountCache::clear(dir.path()).unwrap());
+        assert!(!legacy.exists());
+    }
+
+    /// A count cached through a stub or gateway is not served to a run
+    /// against the real API, and vice versa.
+    #[test]
+    fn count_cached_under_another_base_is_not_used() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = CountCache::open(dir.path());
+        cache.insert(CountCache::key("hello", SONNET, "http://127.0.0.1:9"), 99);
+        cache.save().unwrap();
+
+        let real =
+            ClaudeTokenizer::with_key("k".to_string()).with_cache(CountCache::open(dir.path()));
+        assert_eq!(real.cached_count("hello"), None);
+        let stub = ClaudeTokenizer::with_key("k".to_string())
+            .with_api_base("http://127.0.0.1:9/")
+            .with_cache(CountCache::open(dir.path()));
+        assert_eq!(stub.cached_count("hello"), Some(99));
+    }
+
+    /// A fully cached run must not touch the network: the key is bogus, so
+    /// any request would fail and fall back to a ctoc estimate.  The cached
+    /// overhead probes (`"a"` → 1 + 7, `"a a"` → 2 + 7) agree, so 7 is
+    /// subtracted from the file's count.
+    #[test]
+    fn cached_counts_skip_the_network() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = CountCache::open(dir.path());
+        cache.insert(CountCache::key("a", SONNET, BASE), 8);
+        cache.insert(CountCache::key("a a", SONNET, BASE), 9);
+        cache.insert(CountCache::key("fn main() {}", SONNET, BASE), 12);
+        let claude = ClaudeTokenizer::with_key("invalid".to_string()).with_cache(cache);
+
+        let entries = [FileEntry {
+            path: "main.rs".into(),
+            rel_path: "main.rs".into(),
+            kind: FileKind::Text(Encoding::Utf8),
+            content: Some("fn main() {}".to_string()),
+            document: None,
+        }];
+        let resolved = ResolvedTokenizers {
+            tokenizers: vec![Box::new(claude)],
+        };
+        let results = tokenize_entries(&entries, &resolved);
+        assert_eq!(results[0].tokens[&TokenizerId::Claude].hi(), 5);
+        assert_eq!(results[0].wrapped[&TokenizerId::Claude], 12);
+        assert!(results[0].fallback.is_empty());
+    }
+}
@@ -1,11 +1,68 @@
 /// Error type for tokenization failures.
-#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+#[derive(Clone, Debug, thiserror::Error, miette::Diagnostic)]
 pub enum TokenizeError {
     /// Tokenizer could not be initialised.
     #[error("tokenizer init failed: {0}")]
     #[diagnostic(code(treetok::tokenize::init))]
     Init(String),
 
+    /// A `-t` value could not be turned into a tokenizer.
+    #[error("invalid tokenizer spec: {0}")]
+    #[diagnostic(
+        code(treetok::tokenize::invalid_spec),
+        help(
+            "use a built-in name, hf:PATH, tiktoken:PATH, server:URL, \
+             or NAME=<hf|tiktoken|server>:…"
+        )
+    )]
+    InvalidSpec(String),
+
+    /// `--model` named a model the registry does not know.
+    #[error("unknown model {name:?}")]
+    #[diagnostic(code(treetok::tokenize::unknown_model))]
+    UnknownModel {
+        /// The name as given on the command line.
+        name: String,
+        /// Close matches, or a pointer to `-t`.
+        #[help]
+        help: String,
+    },
+
+    /// `--model` named two different Claude models; a run counts for one.
+    #[error("--model {second}: already counting Claude for {first}")]
+    #[diagnostic(
+        code(treetok::tokenize::conflicting_models),
+        help("pass one Claude model per run")
+    )]
+    ConflictingModels {
+        /// The Claude model selected first.
+        first: String,
+        /// The later model that conflicts with it.
+        second: String,
+    },
+
+    /// `--budget` was a token count of zero.
+    #[error("budget must be positive, got {0:?}")]
+    #[diagnostic(
+        code(treetok::tokenize::zero_budget),
+        help("pass a token count such as 200k, or a model name")
+    )]
+    ZeroBudget(String),
+
+    /// A blocking count was requested from inside an async runtime, whose
+    /// thread it would have to stall.
+    #[error("cannot wait for token counts inside an async runtime")]
+    #[diagnostic(
+        code(treetok::tokenize::nested_runtime),
+        help("await Tokenizer::count_many instead of calling count_tokens")
+    )]
+    NestedRuntime,
+
+    /// A local tokenizer failed to encode its input.
+    #[error("tokenizer encode failed: {0}")]
+    #[diagnostic(code(treetok::tokenize::encode))]
+    Encode(String),
+
     /// Neither `TREETOK_API_KEY` nor `ANTHROPIC_API_KEY` is set.
     #[error("API key not found")]
     #[diagnostic(
@@ -16,16 +73,28 @@ pub enum TokenizeError {
     )]
     NoApiKey,
 
-    /// Claude API rate-limit still hit after all retries.
-    #[error("Claude API rate limit exceeded after retries")]
+    /// `--gemini-api` was given but neither `GEMINI_API_KEY` nor
+    /// `GOOGLE_API_KEY` is set.
+    #[error("Gemini API key not found")]
+    #[diagnostic(
+        code(treetok::tokenize::no_gemini_api_key),
+        help(
+            "set the GEMINI_API_KEY or GOOGLE_API_KEY environment variable, \
+             or drop --gemini-api to count with the embedded vocab"
+        )
+    )]
+    NoGeminiApiKey,
+
+    /// An API (Claude or a `server:` tokenizer) still throttled after all retries.
+    #[error("API rate limit exceeded after retries")]
     #[diagnostic(
         code(treetok::tokenize::rate_limit),
         help("wait a moment and try again, or use --offline")
     )]
     RateLimitExceeded,
 
-    /// Claude API returned an unexpected HTTP status.
-    #[error("Claude API error (HTTP {status}): {body}")]
+    /// An API returned an unexpected HTTP status.
+    #[error("API error (HTTP {status}): {body}")]
     #[diagnostic(code(treetok::tokenize::api_error))]
     ApiError {
         /// HTTP status code from the API.
@@ -34,13 +103,24 @@ pub enum TokenizeError {
         body: String,
     },
 
-    /// Network / transport error reaching the Claude API.
-    #[error("Claude API network error: {0}")]
+    /// Network / transport error reaching an API.
+    #[error("API network error: {0}")]
     #[diagnostic(code(treetok::tokenize::network))]
     Network(String),
 
     /// Response JSON was not parseable.
-    #[error("Claude API response parse error: {0}")]
+    #[error("API response parse error: {0}")]
     #[diagnostic(code(treetok::tokenize::parse))]
     Parse(String),
+
+    /// A `server:` URL answered none of the known tokenize protocols.
+    #[error("{url} does not look like a tokenize endpoint")]
+    #[diagnostic(
+        code(treetok::tokenize::unknown_server),
+        help("server:URL expects the root of a llama.cpp, vLLM, or TGI server")
+    )]
+    UnknownServer {
+        /// The server root as given.
+        url: String,
+    },
 }
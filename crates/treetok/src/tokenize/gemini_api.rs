@@ -0,0 +1,271 @@
+//! Exact Gemini counts from the Generative Language `countTokens` API.
+
+use std::sync::Mutex;
+
+use futures::future::LocalBoxFuture;
+use futures::stream::{self, StreamExt};
+
+use super::TokenizerId;
+use super::cache::CountCache;
+use super::error::TokenizeError;
+use super::local::GeminiTokenizer;
+use super::remote::{DEFAULT_CONCURRENCY, DEFAULT_MAX_RETRIES};
+use super::run::Blocking;
+use super::schedule::Scheduler;
+use super::tokenizer::{Counted, Tokenizer};
+use crate::image;
+use crate::pdf::PdfInfo;
+
+/// API root used unless `--gemini-api-base` / `TREETOK_GEMINI_API_BASE` names another.
+pub const DEFAULT_GEMINI_API_BASE: &str = "https://generativelanguage.googleapis.com";
+/// API version in every request path; part of the cache key.
+pub(super) const GEMINI_API_VERSION: &str = "v1beta";
+/// Model counted for unless `--model` names a Gemini model.
+pub const DEFAULT_GEMINI_MODEL: &str = "gemini-2.5-flash";
+/// Header carrying the API key.
+const API_KEY_HEADER: &str = "x-goog-api-key";
+
+#[derive(serde::Serialize)]
+struct CountTokensRequest<'a> {
+    contents: [Content<'a>; 1],
+}
+
+#[derive(serde::Serialize)]
+struct Content<'a> {
+    parts: [Part<'a>; 1],
+}
+
+#[derive(serde::Serialize)]
+struct Part<'a> {
+    text: &'a str,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CountTokensResponse {
+    total_tokens: usize,
+}
+
+/// Online tokenizer that calls Google's `models/{model}:countTokens`.
+///
+/// Plain-text contents carry no envelope, so the count is the content's own.
+pub struct GeminiApiTokenizer {
+    api_key: String,
+    model: String,
+    base: String,
+    client: reqwest::Client,
+    scheduler: Scheduler,
+    max_retries: u32,
+    cache: Option<Mutex<CountCache>>,
+    /// Drives [`count_tokens`](Tokenizer::count_tokens) calls.
+    runtime: Blocking,
+}
+
+/// Select the API key from two candidates, preferring the first.
+fn select_api_key(preferred: Option<String>, fallback: Option<String>) -> Option<String> {
+    preferred.or(fallback).filter(|key| !key.is_empty())
+}
+
+/// Load the Gemini API key from the environment, preferring `GEMINI_API_KEY`
+/// over `GOOGLE_API_KEY`.
+pub fn load_api_key() -> Option<String> {
+    select_api_key(
+        std::env::var("GEMINI_API_KEY").ok(),
+        std::env::var("GOOGLE_API_KEY").ok(),
+    )
+}
+
+impl GeminiApiTokenizer {
+    /// Create a tokenizer from an already-resolved API key.
+    #[must_use]
+    pub fn with_key(api_key: String) -> Self {
+        Self {
+            api_key,
+            model: DEFAULT_GEMINI_MODEL.to_string(),
+            base: DEFAULT_GEMINI_API_BASE.to_string(),
+            client: reqwest::Client::new(),
+            scheduler: Scheduler::new(DEFAULT_CONCURRENCY),
+            max_retries: DEFAULT_MAX_RETRIES,
+            cache: None,
+            runtime: Blocking::default(),
+        }
+    }
+
+    /// Builder: count for `model` instead of [`DEFAULT_GEMINI_MODEL`].  A
+    /// `models/` prefix, as the API lists them, is accepted.
+    #[must_use]
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.model = model.trim_start_matches("models/").to_string();
+        self
+    }
+
+    /// Builder: send requests to `base` (e.g. a proxy or local stub)
+    /// instead of [`DEFAULT_GEMINI_API_BASE`].
+    #[must_use]
+    pub fn with_api_base(mut self, base: &str) -> Self {
+        self.base = base.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Builder: allow at most `n` requests in flight.
+    #[must_use]
+    pub fn with_concurrency(mut self, n: usize) -> Self {
+        self.scheduler = Scheduler::new(n);
+        self
+    }
+
+    /// Builder: retry a throttled request up to `n` times.
+    #[must_use]
+    pub fn with_max_retries(mut self, n: u32) -> Self {
+        self.max_retries = n;
+        self
+    }
+
+    /// Builder: consult and fill `cache` around API calls.
+    #[must_use]
+    pub fn with_cache(mut self, cache: CountCache) -> Self {
+        self.cache = Some(Mutex::new(cache));
+        self
+    }
+
+    /// The model whose tokenizer is counted.
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// Count tokens via the `countTokens` API.
+    pub async fn count(&self, content: &str) -> Result<usize, TokenizeError> {
+        let url = format!(
+            "{}/{GEMINI_API_VERSION}/models/{}:countTokens",
+            self.base, self.model
+        );
+        let body = CountTokensRequest {
+            contents: [Content {
+                parts: [Part { text: content }],
+            }],
+        };
+        let parsed: CountTokensResponse = self
+            .scheduler
+            .send(self.max_retries, || {
+                self.client
+                    .post(&url)
+                    .header(API_KEY_HEADER, &self.api_key)
+                    .json(&body)
+            })
+            .await?;
+        Ok(parsed.total_tokens)
+    }
+
+    /// The cached count for `content`, if a cache is attached and has one.
+    pub fn cached_count(&self, content: &str) -> Option<usize> {
+        let cache = self.cache.as_ref()?.lock().ok()?;
+        cache.get(&CountCache::gemini_key(content, &self.model, &self.base))
+    }
+
+    fn remember(&self, content: &str, count: usize) {
+        if let Some(Ok(mut cache)) = self.cache.as_ref().map(Mutex::lock) {
+            cache.insert(
+                CountCache::gemini_key(content, &self.model, &self.base),
+                count,
+            );
+        }
+    }
+
+    /// The count for `content` from the cache, or from the API (recording
+    /// it in the cache) on a miss.
+    async fn cached_or_fetched(&self, content: &str) -> Result<usize, TokenizeError> {
+        if let Some(n) = self.cached_count(content) {
+            return Ok(n);
+        }
+        let n = self.count(content).await?;
+        self.remember(content, n);
+        Ok(n)
+    }
+
+    /// Persist counts fetched this run.
+    fn save_cache(&self) {
+        if let Some(Ok(mut cache)) = self.cache.as_ref().map(Mutex::lock)
+            && let Err(e) = cache.save()
+        {
+            eprintln!("warning: could not write token cache: {e}");
+        }
+    }
+
+    async fn count_all(&self, contents: &[&str]) -> Vec<Result<Counted, TokenizeError>> {
+        let counts = stream::iter(contents)
+            .map(|content| async move { self.cached_or_fetched(content).await.map(Counted::raw) })
+            // The scheduler decides how many actually run.
+            .buffered(self.scheduler.max())
+            .collect()
+            .await;
+        self.save_cache();
+        counts
+    }
+}
+
+impl Tokenizer for GeminiApiTokenizer {
+    fn id(&self) -> TokenizerId {
+        TokenizerId::Gemini
+    }
+
+    fn count_tokens(&self, content: &str) -> Result<usize, TokenizeError> {
+        let count = self.runtime.block_on(self.cached_or_fetched(content))?;
+        self.save_cache();
+        count
+    }
+
+    fn count_many<'a>(
+        &'a self,
+        contents: &'a [&'a str],
+    ) -> LocalBoxFuture<'a, Vec<Result<Counted, TokenizeError>>> {
+        Box::pin(self.count_all(contents))
+    }
+
+    fn count_image(&self, width: u32, height: u32) -> Option<usize> {
+        Some(image::gemini_tokens(width, height))
+    }
+
+    /// Gemini sees each page as one image tile; the text is not charged
+    /// separately.
+    fn count_pdf(&self, _text_tokens: usize, pdf: &PdfInfo) -> usize {
+        image::GEMINI_TILE_TOKENS * pdf.pages
+    }
+
+    fn counts_pdf_text(&self) -> bool {
+        false
+    }
+
+    /// The embedded Gemma 3 vocab, when it has been fetched.
+    fn fallback(&self) -> Option<Box<dyn Tokenizer>> {
+        GeminiTokenizer::new()
+            .ok()
+            .map(|t| Box::new(t) as Box<dyn Tokenizer>)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case(Some("gemini"), Some("google"), Some("gemini"))]
+    #[case(None, Some("google"), Some("google"))]
+    #[case(Some(""), None, None)]
+    #[case(None, None, None)]
+    fn select_api_key_prefers_gemini_key(
+        #[case] preferred: Option<&str>,
+        #[case] fallback: Option<&str>,
+        #[case] expected: Option<&str>,
+    ) {
+        let key = select_api_key(preferred.map(str::to_string), fallback.map(str::to_string));
+        assert_eq!(key.as_deref(), expected);
+    }
+
+    #[test]
+    fn model_prefix_is_stripped() {
+        let t = GeminiApiTokenizer::with_key("k".to_string()).with_model("models/gemini-2.5-pro");
+        assert_eq!(t.model(), "gemini-2.5-pro");
+    }
+}
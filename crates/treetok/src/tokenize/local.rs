@@ -1,43 +1,156 @@
+use std::path::Path;
+
 use aho_corasick::{AhoCorasick, MatchKind};
 
 use super::TokenizerId;
 use super::error::TokenizeError;
+use super::tokenizer::Tokenizer;
+use crate::image;
+use crate::pdf::PdfInfo;
 
-/// Common interface for local (offline) token counters.
-pub trait Tokenizer {
-    /// Stable identifier for this tokenizer.
-    fn id(&self) -> TokenizerId;
-    /// Count the tokens in `content`.
-    fn count_tokens(&self, content: &str) -> Result<usize, TokenizeError>;
-    /// Returns `true` if this tokenizer produces approximate counts.
-    fn is_approximate(&self) -> bool {
-        false
-    }
-}
+// ─── tiktoken BPE (OpenAI encodings and custom rank files) ───────────────────
 
-// ─── o200k_harmony (tiktoken) ────────────────────────────────────────────────────
-
-/// Offline tokenizer backed by `OpenAI`'s `o200k_harmony` BPE vocabulary.
-pub struct O200kTokenizer {
+/// Offline tokenizer backed by a tiktoken BPE: either one of the `OpenAI`
+/// encodings bundled with `tiktoken-rs`, or a tiktoken-format rank file
+/// (`tiktoken:PATH`), e.g. Kimi or an in-house fine-tune.
+///
+/// Rank files hold one `base64(token_bytes) rank` pair per line, the format
+/// `OpenAI` ships its `.tiktoken` encodings in.
+pub struct TiktokenTokenizer {
+    id: TokenizerId,
     bpe: tiktoken_rs::CoreBPE,
+    /// Count configured special tokens in content as one token each.  Off
+    /// for the bundled encodings, whose specials are plain text in files.
+    specials: bool,
 }
 
-impl O200kTokenizer {
-    /// Initialise the encoder (loads vocabulary data).
-    pub fn new() -> Result<Self, TokenizeError> {
-        let bpe = tiktoken_rs::o200k_harmony().map_err(|e| TokenizeError::Init(e.to_string()))?;
-        Ok(Self { bpe })
+impl TiktokenTokenizer {
+    /// Initialise the bundled `OpenAI` encoding named by `id` (loads
+    /// vocabulary data).  Errors if `id` is not one of
+    /// [`TokenizerId::OPENAI`].
+    pub fn openai(id: TokenizerId) -> Result<Self, TokenizeError> {
+        let bpe = match id {
+            TokenizerId::Cl100k => tiktoken_rs::cl100k_base(),
+            TokenizerId::O200k => tiktoken_rs::o200k_harmony(),
+            TokenizerId::O200kBase => tiktoken_rs::o200k_base(),
+            TokenizerId::P50k => tiktoken_rs::p50k_base(),
+            TokenizerId::P50kEdit => tiktoken_rs::p50k_edit(),
+            TokenizerId::R50k => tiktoken_rs::r50k_base(),
+            _ => {
+                return Err(TokenizeError::Init(format!(
+                    "{} is not an OpenAI encoding",
+                    id.as_str()
+                )));
+            }
+        }
+        .map_err(|e| TokenizeError::Init(e.to_string()))?;
+        Ok(Self {
+            id,
+            bpe,
+            specials: false,
+        })
+    }
+
+    /// Load ranks from `path` and build a BPE with the given pre-tokenization
+    /// `pattern` and special tokens, each counted as one token where it
+    /// appears in content.
+    pub fn from_file(
+        id: TokenizerId,
+        path: &Path,
+        pattern: &str,
+        specials: &[(String, tiktoken_rs::Rank)],
+    ) -> Result<Self, TokenizeError> {
+        let init_err = |msg: String| TokenizeError::Init(format!("{}: {msg}", path.display()));
+
+        let data = std::fs::read_to_string(path).map_err(|e| init_err(e.to_string()))?;
+        let encoder = parse_tiktoken_ranks(&data).map_err(init_err)?;
+
+        // `CoreBPE::new` asserts that ranks are unique; report it instead.
+        let mut seen: rustc_hash::FxHashSet<tiktoken_rs::Rank> =
+            encoder.values().copied().collect();
+        let mut special_map = rustc_hash::FxHashMap::default();
+        for (token, rank) in specials {
+            if !seen.insert(*rank) {
+                return Err(init_err(format!(
+                    "special token {token:?} reuses rank {rank}"
+                )));
+            }
+            special_map.insert(token.clone(), *rank);
+        }
+
+        let bpe = tiktoken_rs::CoreBPE::new(encoder, special_map, pattern)
+            .map_err(|e| init_err(e.to_string()))?;
+        Ok(Self {
+            id,
+            bpe,
+            specials: true,
+        })
     }
 }
 
-impl Tokenizer for O200kTokenizer {
+impl Tokenizer for TiktokenTokenizer {
     fn id(&self) -> TokenizerId {
-        TokenizerId::O200k
+        self.id.clone()
     }
 
     fn count_tokens(&self, content: &str) -> Result<usize, TokenizeError> {
-        Ok(self.bpe.encode_ordinary(content).len())
+        let ids = if self.specials {
+            self.bpe.encode_with_special_tokens(content)
+        } else {
+            self.bpe.encode_ordinary(content)
+        };
+        Ok(ids.len())
     }
+
+    /// The encodings of `OpenAI`'s vision models use the tile formula;
+    /// legacy encodings and rank files take no images.
+    fn count_image(&self, width: u32, height: u32) -> Option<usize> {
+        matches!(
+            self.id,
+            TokenizerId::O200k | TokenizerId::O200kBase | TokenizerId::Cl100k
+        )
+        .then(|| image::openai_tokens(width, height))
+    }
+}
+
+/// Parse `base64 rank` lines into a byte-sequence → rank map that covers
+/// every single byte.
+fn parse_tiktoken_ranks(
+    data: &str,
+) -> Result<rustc_hash::FxHashMap<Vec<u8>, tiktoken_rs::Rank>, String> {
+    use base64::Engine as _;
+
+    let mut encoder = rustc_hash::FxHashMap::default();
+    let mut ranks = rustc_hash::FxHashSet::default();
+    for (i, line) in data
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+    {
+        let lineno = i + 1;
+        let (token, rank) = line
+            .split_once(' ')
+            .ok_or_else(|| format!("line {lineno}: expected `<base64> <rank>`"))?;
+        let token = base64::engine::general_purpose::STANDARD
+            .decode(token)
+            .map_err(|e| format!("line {lineno}: {e}"))?;
+        let rank: tiktoken_rs::Rank = rank
+            .trim()
+            .parse()
+            .map_err(|e| format!("line {lineno}: bad rank: {e}"))?;
+        if !ranks.insert(rank) {
+            return Err(format!("line {lineno}: duplicate rank {rank}"));
+        }
+        encoder.insert(token, rank);
+    }
+    if encoder.is_empty() {
+        return Err("no ranks found".to_string());
+    }
+    // `CoreBPE` falls back to single bytes and panics on one with no rank.
+    if let Some(byte) = (0..=u8::MAX).find(|b| !encoder.contains_key([*b].as_slice())) {
+        return Err(format!("no rank for byte 0x{byte:02x}"));
+    }
+    Ok(encoder)
 }
 
 // ─── ctoc (Rohan Gupta, Feb 2026 reverse-engineering) ────────────────────────
@@ -113,4 +226,101 @@ impl Tokenizer for CtocTokenizer {
         tokens += bytes.len() - prev_end;
         Ok(tokens)
     }
+
+    fn count_image(&self, width: u32, height: u32) -> Option<usize> {
+        Some(image::claude_tokens(width, height))
+    }
+}
+
+// ─── Gemini (Gemma 3 tokenizer.json) ─────────────────────────────────────────
+
+/// Embedded Gemma 3 `tokenizer.json`.  Updated by `cargo xtask update-gemini` /
+/// `just update-gemini`.
+///
+/// An empty file means the vocab has not been fetched yet; [`GeminiTokenizer::new`]
+/// reports that instead of failing to parse.
+static GEMMA3_TOKENIZER: &[u8] = include_bytes!("gemma3_tokenizer.json");
+
+/// Offline tokenizer for Gemini models, backed by the Gemma 3 vocabulary.
+///
+/// All current Gemini models share this 262 144-token vocabulary, so the
+/// counts match the `countTokens` API for plain text.  The vocab is embedded
+/// at compile time; use `cargo xtask update-gemini` (or `just update-gemini`)
+/// to refresh it from `HuggingFace`.
+pub struct GeminiTokenizer {
+    inner: tokenizers::Tokenizer,
+}
+
+impl GeminiTokenizer {
+    /// Parse the embedded `tokenizer.json`.
+    pub fn new() -> Result<Self, TokenizeError> {
+        if GEMMA3_TOKENIZER.is_empty() {
+            return Err(TokenizeError::Init(
+                "embedded Gemma 3 vocab is missing (run `cargo xtask update-gemini`)".to_string(),
+            ));
+        }
+        let inner = tokenizers::Tokenizer::from_bytes(GEMMA3_TOKENIZER)
+            .map_err(|e| TokenizeError::Init(e.to_string()))?;
+        Ok(Self { inner })
+    }
+}
+
+impl Tokenizer for GeminiTokenizer {
+    fn id(&self) -> TokenizerId {
+        TokenizerId::Gemini
+    }
+
+    fn count_tokens(&self, content: &str) -> Result<usize, TokenizeError> {
+        hf_count(&self.inner, content)
+    }
+
+    fn count_image(&self, width: u32, height: u32) -> Option<usize> {
+        Some(image::gemini_tokens(width, height))
+    }
+
+    /// Gemini sees each page as one image tile; the text is not charged
+    /// separately.
+    fn count_pdf(&self, _text_tokens: usize, pdf: &PdfInfo) -> usize {
+        image::GEMINI_TILE_TOKENS * pdf.pages
+    }
+
+    fn counts_pdf_text(&self) -> bool {
+        false
+    }
+}
+
+// ─── User-supplied HuggingFace tokenizer.json ────────────────────────────────
+
+/// Offline tokenizer loaded at runtime from a `HuggingFace` `tokenizer.json`
+/// (e.g. Qwen, GLM, Llama), selected with `-t hf:PATH` or `-t NAME=hf:PATH`.
+pub struct HfTokenizer {
+    id: TokenizerId,
+    inner: tokenizers::Tokenizer,
+}
+
+impl HfTokenizer {
+    /// Load `path`, reporting its counts under `id`.
+    pub fn from_file(id: TokenizerId, path: &Path) -> Result<Self, TokenizeError> {
+        let inner = tokenizers::Tokenizer::from_file(path)
+            .map_err(|e| TokenizeError::Init(format!("{}: {e}", path.display())))?;
+        Ok(Self { id, inner })
+    }
+}
+
+impl Tokenizer for HfTokenizer {
+    fn id(&self) -> TokenizerId {
+        self.id.clone()
+    }
+
+    fn count_tokens(&self, content: &str) -> Result<usize, TokenizeError> {
+        hf_count(&self.inner, content)
+    }
+}
+
+/// Number of ids `tok` produces for `content`, without special tokens.
+fn hf_count(tok: &tokenizers::Tokenizer, content: &str) -> Result<usize, TokenizeError> {
+    let encoding = tok
+        .encode(content, false)
+        .map_err(|e| TokenizeError::Encode(e.to_string()))?;
+    Ok(encoding.get_ids().len())
 }
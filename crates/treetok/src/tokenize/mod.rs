@@ -1,16 +1,29 @@
 //! Tokenizer trait and concrete implementations.
 
+mod cache;
 mod error;
+mod gemini_api;
 mod local;
+mod model;
 mod remote;
 mod resolve;
 mod run;
+mod schedule;
+mod server;
+mod spec;
+mod tokenizer;
 
+pub use cache::{CacheStats, CountCache};
 pub use error::TokenizeError;
-pub use local::{CtocTokenizer, Tokenizer};
-pub use resolve::{load_api_key, resolve_tokenizers};
+pub use gemini_api::{DEFAULT_GEMINI_API_BASE, DEFAULT_GEMINI_MODEL, GeminiApiTokenizer};
+pub use local::{CtocTokenizer, GeminiTokenizer, HfTokenizer, TiktokenTokenizer};
+pub use model::{ModelInfo, lookup_model, resolve_budget};
+pub use remote::{ClaudeTokenizer, DEFAULT_API_BASE, DEFAULT_CONCURRENCY, DEFAULT_MAX_RETRIES};
+pub use resolve::{ResolveOptions, load_api_key, load_gemini_api_key, resolve_tokenizers};
 pub use run::tokenize_entries;
+pub use server::ServerTokenizer;
 pub use token_count::TokenCount;
+pub use tokenizer::{Counted, Tokenizer};
 pub use tokenizer_id::TokenizerId;
 
 mod token_count {
@@ -52,47 +65,95 @@ mod token_count {
             Self::Approx { lo, hi }
         }
 
-        /// Accumulate another count into `self` (same variant assumed).
+        /// Widen `self` into a range `slack` tokens wider on each side.
+        pub(crate) fn widen(&mut self, slack: usize) {
+            *self = Self::Approx {
+                lo: self.lo().saturating_sub(slack),
+                hi: self.hi() + slack,
+            };
+        }
+
+        /// Accumulate another count into `self`.  Adding an approximate
+        /// count to an exact one yields an approximate range.
         pub(crate) fn add(&mut self, other: &Self) {
-            match (self, other) {
-                (Self::Exact(a), Self::Exact(b)) => *a += b,
-                (Self::Approx { lo: alo, hi: ahi }, Self::Approx { lo: blo, hi: bhi }) => {
-                    *alo += blo;
-                    *ahi += bhi;
-                }
-                _ => {}
-            }
+            *self = match (&*self, other) {
+                (Self::Exact(a), Self::Exact(b)) => Self::Exact(a + b),
+                _ => Self::Approx {
+                    lo: self.lo() + other.lo(),
+                    hi: self.hi() + other.hi(),
+                },
+            };
         }
     }
 }
 
 mod tokenizer_id {
     use std::fmt;
+    use std::sync::Arc;
 
     /// Stable identifier for each supported tokenizer.
     ///
-    /// Variant declaration order (Claude < Ctoc < O200k) mirrors the current
-    /// alphabetical string order so `BTreeMap` column sequences are unchanged.
-    #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    /// Built-in variant declaration order (Cl100k < Claude < … < R50k)
+    /// mirrors the alphabetical string order so `BTreeMap` column sequences are
+    /// unchanged; user-defined tokenizers sort after every built-in.
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
     pub enum TokenizerId {
+        /// The `OpenAI` `cl100k_base` tokenizer (`"cl100k"`): GPT-4, GPT-3.5,
+        /// `text-embedding-3-*`.
+        Cl100k,
         /// The Claude API tokenizer (`"claude"`).
         Claude,
         /// The ctoc approximate tokenizer (`"ctoc"`).
         Ctoc,
+        /// The embedded Gemma 3 tokenizer used by Gemini models (`"gemini"`).
+        Gemini,
         /// The `OpenAI` `o200k_harmony` tokenizer (`"o200k"`).
         O200k,
+        /// The `OpenAI` `o200k_base` tokenizer (`"o200k_base"`): GPT-4o, o1, o3.
+        O200kBase,
+        /// The `OpenAI` `p50k_base` tokenizer (`"p50k"`): Codex, `text-davinci-00[23]`.
+        P50k,
+        /// The `OpenAI` `p50k_edit` tokenizer (`"p50k_edit"`): edit models.
+        P50kEdit,
+        /// The `OpenAI` `r50k_base` tokenizer (`"r50k"`): GPT-3 (`davinci`).
+        R50k,
+        /// A user-defined tokenizer (e.g. `-t qwen=hf:…`), keyed by its label.
+        Custom(Arc<str>),
     }
 
     impl TokenizerId {
+        /// Every `OpenAI` encoding bundled with `tiktoken-rs`.
+        pub const OPENAI: [Self; 6] = [
+            Self::Cl100k,
+            Self::O200k,
+            Self::O200kBase,
+            Self::P50k,
+            Self::P50kEdit,
+            Self::R50k,
+        ];
+
         /// Short CLI key used in `-t` flags and JSON output.
         #[must_use]
-        pub fn as_str(self) -> &'static str {
+        pub fn as_str(&self) -> &str {
             match self {
+                Self::Cl100k => "cl100k",
                 Self::Claude => "claude",
                 Self::Ctoc => "ctoc",
+                Self::Gemini => "gemini",
                 Self::O200k => "o200k",
+                Self::O200kBase => "o200k_base",
+                Self::P50k => "p50k",
+                Self::P50kEdit => "p50k_edit",
+                Self::R50k => "r50k",
+                Self::Custom(name) => name,
             }
         }
+
+        /// Identifier for a user-defined tokenizer labelled `name`.
+        #[must_use]
+        pub fn custom(name: &str) -> Self {
+            Self::Custom(Arc::from(name))
+        }
     }
 
     impl fmt::Display for TokenizerId {
@@ -100,46 +161,79 @@ mod tokenizer_id {
             let s = match self {
                 Self::Claude => "Claude",
                 Self::Ctoc => "Claude~",
+                Self::Gemini => "Gemini",
                 Self::O200k => "OpenAI",
+                Self::Custom(name) => name,
+                other => other.as_str(),
             };
             f.pad(s)
         }
     }
 
+    /// Parses built-in names only; user-defined tokenizers come from
+    /// `-t` specs (see `resolve_tokenizers`).  `OpenAI` encodings also accept
+    /// their full tiktoken names (`cl100k_base`, `o200k_harmony`, …).
     impl std::str::FromStr for TokenizerId {
         type Err = ();
 
         fn from_str(s: &str) -> Result<Self, Self::Err> {
             match s {
+                "cl100k" | "cl100k_base" => Ok(Self::Cl100k),
                 "claude" => Ok(Self::Claude),
                 "ctoc" => Ok(Self::Ctoc),
-                "o200k" => Ok(Self::O200k),
+                "gemini" => Ok(Self::Gemini),
+                "o200k" | "o200k_harmony" => Ok(Self::O200k),
+                "o200k_base" => Ok(Self::O200kBase),
+                "p50k" | "p50k_base" => Ok(Self::P50k),
+                "p50k_edit" => Ok(Self::P50kEdit),
+                "r50k" | "r50k_base" | "gpt2" => Ok(Self::R50k),
                 _ => Err(()),
             }
         }
     }
 }
 
-#[cfg(test)]
-use local::O200kTokenizer;
-
 #[cfg(test)]
 mod tests {
     use super::*;
     use rstest::{fixture, rstest};
 
+    /// Write a minimal whitespace/word-level `tokenizer.json` into `dir`.
+    ///
+    /// Known words map to one token each; anything else becomes `[UNK]`.
+    pub(super) fn write_word_level_tokenizer(dir: &std::path::Path) -> std::path::PathBuf {
+        let json = serde_json::json!({
+            "version": "1.0",
+            "truncation": null,
+            "padding": null,
+            "added_tokens": [],
+            "normalizer": null,
+            "pre_tokenizer": { "type": "Whitespace" },
+            "post_processor": null,
+            "decoder": null,
+            "model": {
+                "type": "WordLevel",
+                "vocab": { "[UNK]": 0, "hello": 1, "world": 2 },
+                "unk_token": "[UNK]"
+            }
+        });
+        let path = dir.join("tokenizer.json");
+        std::fs::write(&path, json.to_string()).unwrap();
+        path
+    }
+
     // ── fixtures ───────────────────────────────────────────────────────────
 
     #[fixture]
-    fn o200k() -> O200kTokenizer {
-        O200kTokenizer::new().expect("o200k init should succeed")
+    fn o200k() -> TiktokenTokenizer {
+        TiktokenTokenizer::openai(TokenizerId::O200k).expect("o200k init should succeed")
     }
 
-    // ── O200kTokenizer ─────────────────────────────────────────────────────
+    // ── o200k (TiktokenTokenizer) ──────────────────────────────────────────
 
     /// Empty string produces zero tokens.
     #[rstest]
-    fn empty_string_is_zero_tokens(o200k: O200kTokenizer) {
+    fn empty_string_is_zero_tokens(o200k: TiktokenTokenizer) {
         assert_eq!(o200k.count_tokens("").unwrap(), 0);
     }
 
@@ -149,13 +243,13 @@ mod tests {
     #[case("hello")]
     #[case("Hello, world!")]
     #[case("fn main() { println!(\"hi\"); }")]
-    fn nonempty_input_has_positive_count(o200k: O200kTokenizer, #[case] input: &str) {
+    fn nonempty_input_has_positive_count(o200k: TiktokenTokenizer, #[case] input: &str) {
         assert!(o200k.count_tokens(input).unwrap() > 0);
     }
 
     /// A longer text produces more tokens than a shorter one.
     #[rstest]
-    fn longer_text_has_more_tokens(o200k: O200kTokenizer) {
+    fn longer_text_has_more_tokens(o200k: TiktokenTokenizer) {
         let short = o200k.count_tokens("hi").unwrap();
         let long = o200k
             .count_tokens(
@@ -233,4 +327,140 @@ mod tests {
     fn ctoc_is_approximate(ctoc: CtocTokenizer) {
         assert!(ctoc.is_approximate());
     }
+
+    // ── HfTokenizer ───────────────────────────────────────────────────────
+
+    #[test]
+    fn hf_tokenizer_counts_with_custom_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_word_level_tokenizer(dir.path());
+        let hf = HfTokenizer::from_file(TokenizerId::custom("words"), &path).unwrap();
+        assert_eq!(hf.id().as_str(), "words");
+        assert_eq!(hf.count_tokens("").unwrap(), 0);
+        assert_eq!(hf.count_tokens("hello world and more").unwrap(), 4);
+    }
+
+    #[test]
+    fn hf_tokenizer_missing_file_is_init_error() {
+        let err =
+            HfTokenizer::from_file(TokenizerId::custom("x"), "/no/such/tokenizer.json".as_ref());
+        assert!(matches!(err, Err(TokenizeError::Init(_))));
+    }
+
+    // ── OpenAI encodings ──────────────────────────────────────────────────
+
+    /// Every bundled encoding initialises and reports its own id.
+    #[rstest]
+    fn every_openai_encoding_counts(#[values(0, 1, 2, 3, 4, 5)] i: usize) {
+        let id = TokenizerId::OPENAI[i].clone();
+        let tok = TiktokenTokenizer::openai(id.clone()).unwrap();
+        assert_eq!(tok.id(), id);
+        assert!(tok.count_tokens("Hello, world!").unwrap() > 0);
+    }
+
+    /// cl100k and o200k split non-English text differently — the reason
+    /// o200k is a poor proxy for legacy GPT-4 deployments.
+    #[test]
+    fn cl100k_differs_from_o200k() {
+        let text = "Привет, как дела? これはテストです。".repeat(20);
+        let cl100k = TiktokenTokenizer::openai(TokenizerId::Cl100k).unwrap();
+        let o200k = TiktokenTokenizer::openai(TokenizerId::O200k).unwrap();
+        assert_ne!(
+            cl100k.count_tokens(&text).unwrap(),
+            o200k.count_tokens(&text).unwrap()
+        );
+    }
+
+    #[test]
+    fn openai_rejects_non_openai_id() {
+        assert!(matches!(
+            TiktokenTokenizer::openai(TokenizerId::Gemini),
+            Err(TokenizeError::Init(_))
+        ));
+    }
+
+    #[rstest]
+    #[case("cl100k_base", TokenizerId::Cl100k)]
+    #[case("o200k_harmony", TokenizerId::O200k)]
+    #[case("o200k_base", TokenizerId::O200kBase)]
+    #[case("p50k_base", TokenizerId::P50k)]
+    #[case("r50k_base", TokenizerId::R50k)]
+    #[case("gpt2", TokenizerId::R50k)]
+    fn full_encoding_names_parse(#[case] name: &str, #[case] id: TokenizerId) {
+        assert_eq!(name.parse::<TokenizerId>(), Ok(id));
+    }
+
+    // ── TiktokenTokenizer ─────────────────────────────────────────────────
+
+    /// A checked-in rank file with every single byte plus the merge `"ab"`
+    /// at rank 256.
+    pub const BYTE_RANKS: &str =
+        concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/bytes.tiktoken");
+
+    #[test]
+    fn tiktoken_file_applies_merges() {
+        let path = std::path::Path::new(BYTE_RANKS);
+        let tok = TiktokenTokenizer::from_file(TokenizerId::custom("kimi"), path, r"\S+|\s+", &[])
+            .unwrap();
+        assert_eq!(tok.id().as_str(), "kimi");
+        assert_eq!(tok.count_tokens("").unwrap(), 0);
+        // "abab" → ab·ab, " " → 1, "c" → 1.
+        assert_eq!(tok.count_tokens("abab c").unwrap(), 4);
+    }
+
+    #[test]
+    fn tiktoken_special_rank_collision_is_init_error() {
+        let path = std::path::Path::new(BYTE_RANKS);
+        let specials = [("<|end|>".to_string(), 256)];
+        let err = TiktokenTokenizer::from_file(TokenizerId::custom("x"), path, r"\S+", &specials);
+        assert!(matches!(err, Err(TokenizeError::Init(_))));
+    }
+
+    #[rstest]
+    #[case::missing_rank("YQ==\n")]
+    #[case::bad_base64("!!! 0\n")]
+    #[case::duplicate_rank("YQ== 0\nYg== 0\n")]
+    #[case::single_byte("YQ== 0\n")]
+    #[case::empty("")]
+    fn tiktoken_malformed_rank_file_is_init_error(#[case] data: &str) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bad.tiktoken");
+        std::fs::write(&path, data).unwrap();
+        let err = TiktokenTokenizer::from_file(TokenizerId::custom("x"), &path, r"\S+", &[]);
+        assert!(matches!(err, Err(TokenizeError::Init(_))));
+    }
+
+    /// A rank file missing one single byte is rejected at init rather than
+    /// panicking inside the BPE on the first file that contains it.
+    #[test]
+    fn tiktoken_truncated_rank_file_names_missing_byte() {
+        let ranks = std::fs::read_to_string(BYTE_RANKS).unwrap();
+        let truncated = ranks
+            .lines()
+            .filter(|l| !l.starts_with("Yg== "))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("truncated.tiktoken");
+        std::fs::write(&path, truncated).unwrap();
+        let Err(TokenizeError::Init(msg)) =
+            TiktokenTokenizer::from_file(TokenizerId::custom("x"), &path, r"\S+", &[])
+        else {
+            panic!("expected an init error");
+        };
+        assert!(msg.ends_with("no rank for byte 0x62"), "{msg}");
+    }
+
+    // ── GeminiTokenizer ────────────────────────────────────────────────────
+
+    /// The embedded Gemma 3 vocab is present and counts like the
+    /// `countTokens` API.
+    #[rstest]
+    #[case::empty("", 0)]
+    #[case::greeting("Hello, world!", 4)]
+    fn gemini_counts_with_embedded_vocab(#[case] text: &str, #[case] expected: usize) {
+        let gemini = GeminiTokenizer::new().expect("embedded Gemma 3 vocab");
+        assert_eq!(gemini.count_tokens(text).unwrap(), expected);
+        assert!(!gemini.is_approximate());
+    }
 }
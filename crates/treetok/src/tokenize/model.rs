@@ -0,0 +1,229 @@
+use super::TokenizerId;
+use super::error::TokenizeError;
+
+/// A known model and the tokenizer that counts for it.
+pub struct ModelInfo {
+    /// Canonical model identifier (e.g. `"gpt-4o"`).
+    pub name: &'static str,
+    /// Tokenizer used by the model.
+    pub tokenizer: TokenizerId,
+    /// Context window in tokens (input + output), used by `--budget MODEL`.
+    pub context_window: usize,
+}
+
+const fn model(name: &'static str, tokenizer: TokenizerId, context_window: usize) -> ModelInfo {
+    ModelInfo {
+        name,
+        tokenizer,
+        context_window,
+    }
+}
+
+/// Every model `--model` understands.
+///
+/// Dated snapshots (`gpt-4o-2024-08-06`, `claude-sonnet-4-5-20250929`) resolve
+/// through their undated family name; see [`lookup_model`].
+static MODELS: &[ModelInfo] = &[
+    // ── Anthropic (count_tokens API; ctoc offline) ──
+    model("claude-opus-4-6", TokenizerId::Claude, 200_000),
+    model("claude-sonnet-4-6", TokenizerId::Claude, 200_000),
+    model("claude-opus-4-5", TokenizerId::Claude, 200_000),
+    model("claude-sonnet-4-5", TokenizerId::Claude, 200_000),
+    model("claude-haiku-4-5", TokenizerId::Claude, 200_000),
+    model("claude-opus-4-1", TokenizerId::Claude, 200_000),
+    model("claude-opus-4-0", TokenizerId::Claude, 200_000),
+    model("claude-sonnet-4-0", TokenizerId::Claude, 200_000),
+    model("claude-3-7-sonnet-latest", TokenizerId::Claude, 200_000),
+    model("claude-3-5-haiku-latest", TokenizerId::Claude, 200_000),
+    // ── Google (Gemma 3 vocab) ──
+    model("gemini-2.5-pro", TokenizerId::Gemini, 1_048_576),
+    model("gemini-2.5-flash", TokenizerId::Gemini, 1_048_576),
+    model("gemini-2.5-flash-lite", TokenizerId::Gemini, 1_048_576),
+    model("gemini-2.0-flash", TokenizerId::Gemini, 1_048_576),
+    model("gemini-2.0-flash-lite", TokenizerId::Gemini, 1_048_576),
+    // ── OpenAI ──
+    model("gpt-5", TokenizerId::O200kBase, 400_000),
+    model("gpt-5-mini", TokenizerId::O200kBase, 400_000),
+    model("gpt-5-nano", TokenizerId::O200kBase, 400_000),
+    model("gpt-4.1", TokenizerId::O200kBase, 1_047_576),
+    model("gpt-4.1-mini", TokenizerId::O200kBase, 1_047_576),
+    model("gpt-4.1-nano", TokenizerId::O200kBase, 1_047_576),
+    model("gpt-4o", TokenizerId::O200kBase, 128_000),
+    model("gpt-4o-mini", TokenizerId::O200kBase, 128_000),
+    model("o1", TokenizerId::O200kBase, 200_000),
+    model("o1-mini", TokenizerId::O200kBase, 128_000),
+    model("o3", TokenizerId::O200kBase, 200_000),
+    model("o3-mini", TokenizerId::O200kBase, 200_000),
+    model("o4-mini", TokenizerId::O200kBase, 200_000),
+    model("gpt-oss-120b", TokenizerId::O200k, 131_072),
+    model("gpt-oss-20b", TokenizerId::O200k, 131_072),
+    model("gpt-4", TokenizerId::Cl100k, 8_192),
+    model("gpt-4-turbo", TokenizerId::Cl100k, 128_000),
+    model("gpt-3.5-turbo", TokenizerId::Cl100k, 16_385),
+    model("text-embedding-3-small", TokenizerId::Cl100k, 8_191),
+    model("text-embedding-3-large", TokenizerId::Cl100k, 8_191),
+    model("text-embedding-ada-002", TokenizerId::Cl100k, 8_191),
+    model("text-davinci-003", TokenizerId::P50k, 4_097),
+    model("text-davinci-002", TokenizerId::P50k, 4_097),
+    model("code-davinci-002", TokenizerId::P50k, 8_001),
+    model("text-davinci-edit-001", TokenizerId::P50kEdit, 2_049),
+    model("code-davinci-edit-001", TokenizerId::P50kEdit, 2_049),
+    model("davinci", TokenizerId::R50k, 2_049),
+    model("curie", TokenizerId::R50k, 2_049),
+    model("babbage", TokenizerId::R50k, 2_049),
+    model("ada", TokenizerId::R50k, 2_049),
+];
+
+/// Look up `name` in the registry.
+///
+/// An exact match wins; otherwise the longest registered name that `name`
+/// extends with a `-` suffix (a dated snapshot such as `gpt-4o-2024-08-06`).
+/// Unknown names produce [`TokenizeError::UnknownModel`] listing close
+/// matches.
+pub fn lookup_model(name: &str) -> Result<&'static ModelInfo, TokenizeError> {
+    if let Some(info) = MODELS.iter().find(|m| m.name == name) {
+        return Ok(info);
+    }
+    let snapshot = MODELS
+        .iter()
+        .filter(|m| {
+            name.strip_prefix(m.name)
+                .is_some_and(|rest| rest.starts_with('-'))
+        })
+        .max_by_key(|m| m.name.len());
+    snapshot.ok_or_else(|| TokenizeError::UnknownModel {
+        name: name.to_string(),
+        help: suggestion_help(name),
+    })
+}
+
+/// Resolve a `--budget` value: a token count (`200000`, `200k`, `1m`) or a
+/// model name whose context window is used.
+pub fn resolve_budget(arg: &str) -> Result<usize, TokenizeError> {
+    let (digits, scale) = match arg.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&arg[..i], 1_000),
+        Some((i, 'm' | 'M')) => (&arg[..i], 1_000_000),
+        _ => (arg, 1),
+    };
+    match digits.replace('_', "").parse::<usize>() {
+        Ok(0) => return Err(TokenizeError::ZeroBudget(arg.to_string())),
+        Ok(n) => return Ok(n.saturating_mul(scale)),
+        Err(_) => {}
+    }
+    lookup_model(arg).map(|m| m.context_window)
+}
+
+/// Maximum number of close matches listed for an unknown model.
+const MAX_SUGGESTIONS: usize = 5;
+
+/// Help text naming the registered models closest to `name`.
+fn suggestion_help(name: &str) -> String {
+    let mut scored: Vec<(f64, &str)> = MODELS
+        .iter()
+        .map(|m| (strsim::jaro_winkler(name, m.name), m.name))
+        .filter(|(score, _)| *score >= 0.8)
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+
+    if scored.is_empty() {
+        return "run with -t to pick a tokenizer directly, or see DESIGN.md for known models"
+            .to_string();
+    }
+    let names: Vec<&str> = scored
+        .iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, n)| *n)
+        .collect();
+    format!("did you mean: {}?", names.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::exact("gpt-4o", TokenizerId::O200kBase)]
+    #[case::legacy("gpt-4", TokenizerId::Cl100k)]
+    #[case::dated_snapshot("gpt-4o-2024-08-06", TokenizerId::O200kBase)]
+    #[case::longest_prefix_wins("gpt-4o-mini-2024-07-18", TokenizerId::O200kBase)]
+    #[case::gpt4_turbo_snapshot("gpt-4-turbo-2024-04-09", TokenizerId::Cl100k)]
+    #[case::claude("claude-sonnet-4-6", TokenizerId::Claude)]
+    #[case::claude_snapshot("claude-sonnet-4-5-20250929", TokenizerId::Claude)]
+    #[case::gemini("gemini-2.5-pro", TokenizerId::Gemini)]
+    fn known_models_resolve(#[case] name: &str, #[case] tokenizer: TokenizerId) {
+        assert_eq!(lookup_model(name).unwrap().tokenizer, tokenizer);
+    }
+
+    #[test]
+    fn snapshot_resolves_to_family_name() {
+        assert_eq!(
+            lookup_model("gpt-4o-mini-2024-07-18").unwrap().name,
+            "gpt-4o-mini"
+        );
+    }
+
+    /// A bare prefix without the `-` separator is not a snapshot.
+    #[test]
+    fn prefix_without_separator_is_unknown() {
+        assert!(lookup_model("gpt-4oo").is_err());
+    }
+
+    #[test]
+    fn unknown_model_suggests_close_matches() {
+        let Err(TokenizeError::UnknownModel { name, help }) = lookup_model("gpt4o") else {
+            panic!("expected UnknownModel");
+        };
+        assert_eq!(name, "gpt4o");
+        assert!(help.contains("gpt-4o"), "{help}");
+    }
+
+    #[test]
+    fn unrelated_name_has_generic_help() {
+        let Err(TokenizeError::UnknownModel { help, .. }) = lookup_model("zzzzzz") else {
+            panic!("expected UnknownModel");
+        };
+        assert!(help.contains("-t"), "{help}");
+    }
+
+    #[rstest]
+    #[case::plain("200000", 200_000)]
+    #[case::underscores("128_000", 128_000)]
+    #[case::kilo("200k", 200_000)]
+    #[case::mega("1M", 1_000_000)]
+    #[case::model("gpt-4o", 128_000)]
+    #[case::model_snapshot("claude-sonnet-4-5-20250929", 200_000)]
+    fn budget_values_resolve(#[case] arg: &str, #[case] expected: usize) {
+        assert_eq!(resolve_budget(arg).unwrap(), expected);
+    }
+
+    #[test]
+    fn unknown_budget_model_is_an_error() {
+        assert!(matches!(
+            resolve_budget("gpt-9000x"),
+            Err(TokenizeError::UnknownModel { .. })
+        ));
+    }
+
+    #[rstest]
+    #[case::zero("0")]
+    #[case::kilo("0k")]
+    #[case::underscores("0_000")]
+    fn zero_budget_must_be_positive(#[case] arg: &str) {
+        let err = resolve_budget(arg).unwrap_err();
+        assert!(matches!(err, TokenizeError::ZeroBudget(_)));
+        assert_eq!(
+            err.to_string(),
+            format!("budget must be positive, got {arg:?}")
+        );
+    }
+
+    /// Registry names are unique so lookups are unambiguous.
+    #[test]
+    fn model_names_are_unique() {
+        let mut names: Vec<&str> = MODELS.iter().map(|m| m.name).collect();
+        names.sort_unstable();
+        names.dedup();
+        assert_eq!(names.len(), MODELS.len());
+    }
+}
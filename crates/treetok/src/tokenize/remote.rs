@@ -1,8 +1,34 @@
+use std::sync::{Mutex, OnceLock};
+
+use futures::future::LocalBoxFuture;
+use futures::stream::{self, StreamExt};
+
+use super::TokenizerId;
+use super::cache::CountCache;
 use super::error::TokenizeError;
+use super::local::CtocTokenizer;
+use super::run::Blocking;
+use super::schedule::Scheduler;
+use super::tokenizer::{Counted, Tokenizer};
+use crate::image;
 
-const COUNT_TOKENS_URL: &str = "https://api.anthropic.com/v1/messages/count_tokens";
-const CLAUDE_MODEL: &str = "claude-sonnet-4-6";
-const MAX_RETRIES: u32 = 3;
+/// API root used unless `--api-base` / `TREETOK_API_BASE` names another.
+pub const DEFAULT_API_BASE: &str = "https://api.anthropic.com";
+const COUNT_TOKENS_PATH: &str = "/v1/messages/count_tokens";
+/// Header carrying the API key unless `--auth-header` names another.
+const DEFAULT_AUTH_HEADER: &str = "x-api-key";
+/// Model sent in `count_tokens` requests unless `--model` names another.
+pub const DEFAULT_CLAUDE_MODEL: &str = "claude-sonnet-4-6";
+/// Concurrent requests allowed unless `--concurrency` says otherwise.
+pub const DEFAULT_CONCURRENCY: usize = 20;
+/// Retries of a throttled request unless `--max-retries` says otherwise.
+pub const DEFAULT_MAX_RETRIES: u32 = 8;
+/// `anthropic-version` header sent with every request; part of the cache key.
+pub(super) const ANTHROPIC_VERSION: &str = "2023-06-01";
+/// Calibration texts and their token counts in Claude's vocabulary.  Each
+/// wrapped count minus its tokens is the message-envelope overhead; the two
+/// must agree before the overhead is trusted.
+const OVERHEAD_PROBES: [(&str, usize); 2] = [("a", 1), ("a a", 2)];
 
 #[derive(serde::Serialize)]
 struct CountTokensRequest<'a> {
@@ -13,7 +39,60 @@ struct CountTokensRequest<'a> {
 #[derive(serde::Serialize)]
 struct Message<'a> {
     role: &'a str,
-    content: &'a str,
+    content: Content<'a>,
+}
+
+/// A message's content: plain text, or one base64 PDF `document` block.
+#[derive(serde::Serialize)]
+#[serde(untagged)]
+enum Content<'a> {
+    Text(&'a str),
+    Blocks([DocumentBlock; 1]),
+}
+
+#[derive(serde::Serialize)]
+struct DocumentBlock {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    source: DocumentSource,
+}
+
+#[derive(serde::Serialize)]
+struct DocumentSource {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    media_type: &'static str,
+    data: String,
+}
+
+impl Content<'_> {
+    fn document(data: &[u8]) -> Self {
+        use base64::Engine as _;
+        Self::Blocks([DocumentBlock {
+            kind: "document",
+            source: DocumentSource {
+                kind: "base64",
+                media_type: "application/pdf",
+                data: base64::engine::general_purpose::STANDARD.encode(data),
+            },
+        }])
+    }
+}
+
+/// One item to count: a text file's content or a PDF's raw bytes.
+#[derive(Clone, Copy)]
+enum Input<'a> {
+    Text(&'a str),
+    Document(&'a [u8]),
+}
+
+impl Input<'_> {
+    fn cache_key(self, model: &str, api_base: &str) -> String {
+        match self {
+            Self::Text(content) => CountCache::key(content, model, api_base),
+            Self::Document(data) => CountCache::document_key(data, model, api_base),
+        }
+    }
 }
 
 #[derive(serde::Deserialize)]
@@ -24,7 +103,20 @@ struct CountTokensResponse {
 /// Online tokenizer that calls Anthropic's `count_tokens` endpoint.
 pub struct ClaudeTokenizer {
     api_key: String,
+    model: String,
+    /// API root, part of every cache key.
+    api_base: String,
+    /// Full `count_tokens` endpoint URL.
+    url: String,
+    auth_header: String,
+    extra_headers: Vec<(String, String)>,
     client: reqwest::Client,
+    cache: Option<Mutex<CountCache>>,
+    overhead: OnceLock<usize>,
+    scheduler: Scheduler,
+    max_retries: u32,
+    /// Drives [`count_tokens`](Tokenizer::count_tokens) calls.
+    runtime: Blocking,
 }
 
 /// Select the API key from two candidates, preferring the first.
@@ -45,55 +137,327 @@ pub fn load_api_key() -> Result<String, TokenizeError> {
 
 impl ClaudeTokenizer {
     /// Create a tokenizer from an already-resolved API key.
+    #[must_use]
     pub fn with_key(api_key: String) -> Self {
         Self {
             api_key,
+            model: DEFAULT_CLAUDE_MODEL.to_string(),
+            api_base: DEFAULT_API_BASE.to_string(),
+            url: format!("{DEFAULT_API_BASE}{COUNT_TOKENS_PATH}"),
+            auth_header: DEFAULT_AUTH_HEADER.to_string(),
+            extra_headers: Vec::new(),
             client: reqwest::Client::new(),
+            cache: None,
+            overhead: OnceLock::new(),
+            scheduler: Scheduler::new(DEFAULT_CONCURRENCY),
+            max_retries: DEFAULT_MAX_RETRIES,
+            runtime: Blocking::default(),
         }
     }
 
-    /// Count tokens via the Anthropic API (async, with retry on 429).
-    pub async fn count_tokens(&self, content: &str) -> Result<usize, TokenizeError> {
+    /// Builder: count for `model` instead of [`DEFAULT_CLAUDE_MODEL`].
+    #[must_use]
+    pub fn with_model(mut self, model: String) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// Builder: send requests to `base` (e.g. a gateway or local stub)
+    /// instead of [`DEFAULT_API_BASE`].
+    #[must_use]
+    pub fn with_api_base(mut self, base: &str) -> Self {
+        self.api_base = base.trim_end_matches('/').to_string();
+        self.url = format!("{}{COUNT_TOKENS_PATH}", self.api_base);
+        self
+    }
+
+    /// Builder: send the API key in header `name` instead of `x-api-key`.
+    /// For `Authorization` the key is sent as a bearer token.
+    #[must_use]
+    pub fn with_auth_header(mut self, name: String) -> Self {
+        self.auth_header = name;
+        self
+    }
+
+    /// Builder: add `(name, value)` headers to every request.
+    #[must_use]
+    pub fn with_headers(mut self, headers: Vec<(String, String)>) -> Self {
+        self.extra_headers = headers;
+        self
+    }
+
+    /// Builder: allow at most `n` requests in flight.  The scheduler starts
+    /// lower and grows toward `n` while the API keeps accepting requests.
+    #[must_use]
+    pub fn with_concurrency(mut self, n: usize) -> Self {
+        self.scheduler = Scheduler::new(n);
+        self
+    }
+
+    /// Builder: retry a throttled (`429`/`529`) request up to `n` times.
+    #[must_use]
+    pub fn with_max_retries(mut self, n: u32) -> Self {
+        self.max_retries = n;
+        self
+    }
+
+    /// The most requests that may be in flight at once.
+    pub fn concurrency(&self) -> usize {
+        self.scheduler.max()
+    }
+
+    /// Builder: consult and fill `cache` around API calls.
+    #[must_use]
+    pub fn with_cache(mut self, cache: CountCache) -> Self {
+        self.cache = Some(Mutex::new(cache));
+        self
+    }
+
+    /// The model sent in `count_tokens` requests.
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// The cached count for `content`, if a cache is attached and has one.
+    pub fn cached_count(&self, content: &str) -> Option<usize> {
+        self.cached(Input::Text(content))
+    }
+
+    /// Record a count fetched from the API in the attached cache.
+    pub fn remember(&self, content: &str, count: usize) {
+        self.record(Input::Text(content), count);
+    }
+
+    fn cached(&self, input: Input<'_>) -> Option<usize> {
+        let cache = self.cache.as_ref()?.lock().ok()?;
+        cache.get(&input.cache_key(&self.model, &self.api_base))
+    }
+
+    fn record(&self, input: Input<'_>, count: usize) {
+        if let Some(Ok(mut cache)) = self.cache.as_ref().map(Mutex::lock) {
+            cache.insert(input.cache_key(&self.model, &self.api_base), count);
+        }
+    }
+
+    /// Persist counts recorded with [`remember`](Self::remember).
+    pub fn save_cache(&self) -> std::io::Result<()> {
+        match self.cache.as_ref().map(Mutex::lock) {
+            Some(Ok(mut cache)) => cache.save(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Tokens the one-message request envelope adds to every
+    /// [`count_wrapped`](Self::count_wrapped) result.
+    ///
+    /// Calibrated once per tokenizer from both [`OVERHEAD_PROBES`], whose
+    /// counts go through the on-disk cache like any other content.  If the
+    /// probes imply different overheads, the envelope is not constant (or
+    /// the probes are not the token counts assumed), so nothing is
+    /// subtracted and a warning is printed.
+    pub async fn envelope_overhead(&self) -> Result<usize, TokenizeError> {
+        if let Some(&overhead) = self.overhead.get() {
+            return Ok(overhead);
+        }
+        let [(first, first_tokens), (second, second_tokens)] = OVERHEAD_PROBES;
+        let (first_wrapped, second_wrapped) = futures::future::join(
+            self.cached_or_fetched(first),
+            self.cached_or_fetched(second),
+        )
+        .await;
+        let (first_wrapped, second_wrapped) = (first_wrapped?, second_wrapped?);
+        let overhead = match (
+            first_wrapped.checked_sub(first_tokens),
+            second_wrapped.checked_sub(second_tokens),
+        ) {
+            (Some(a), Some(b)) if a == b => a,
+            _ => {
+                eprintln!(
+                    "warning: Claude envelope overhead is inconsistent \
+                     ({first:?} → {first_wrapped}, {second:?} → {second_wrapped}); \
+                     counts are reported unadjusted"
+                );
+                0
+            }
+        };
+        Ok(*self.overhead.get_or_init(|| overhead))
+    }
+
+    /// The wrapped count for `content` from the cache, or from the API
+    /// (recording it in the cache) on a miss.
+    async fn cached_or_fetched(&self, content: &str) -> Result<usize, TokenizeError> {
+        if let Some(n) = self.cached_count(content) {
+            return Ok(n);
+        }
+        let n = self.count_wrapped(content).await?;
+        self.remember(content, n);
+        Ok(n)
+    }
+
+    /// Count tokens via the Anthropic API, envelope included.
+    ///
+    /// Requests go through the shared scheduler; throttled responses
+    /// (`429`, `503`, `529`) are retried after the server's `retry-after` (or
+    /// a jittered back-off) up to the configured retry limit.
+    ///
+    /// The result includes the message-envelope overhead; subtract
+    /// [`envelope_overhead`](Self::envelope_overhead) for the content alone.
+    pub async fn count_wrapped(&self, content: &str) -> Result<usize, TokenizeError> {
+        self.send(Content::Text(content)).await
+    }
+
+    /// Count the PDF `data` as a `document` block via the Anthropic API,
+    /// envelope included, like [`count_wrapped`](Self::count_wrapped).
+    pub async fn count_document_wrapped(&self, data: &[u8]) -> Result<usize, TokenizeError> {
+        self.send(Content::document(data)).await
+    }
+
+    async fn send(&self, content: Content<'_>) -> Result<usize, TokenizeError> {
         let body = CountTokensRequest {
-            model: CLAUDE_MODEL,
+            model: &self.model,
             messages: [Message {
                 role: "user",
                 content,
             }],
         };
 
-        for attempt in 0..MAX_RETRIES {
-            let resp = self
-                .client
-                .post(COUNT_TOKENS_URL)
-                .header("x-api-key", &self.api_key)
-                .header("anthropic-version", "2023-06-01")
-                .json(&body)
-                .send()
-                .await
-                .map_err(|e| TokenizeError::Network(e.to_string()))?;
-
-            let status = resp.status().as_u16();
-
-            if status == 200 {
-                let parsed: CountTokensResponse = resp
-                    .json()
-                    .await
-                    .map_err(|e| TokenizeError::Parse(e.to_string()))?;
-                return Ok(parsed.input_tokens);
-            } else if status == 429 {
-                let wait = std::time::Duration::from_secs(1 << attempt);
-                tokio::time::sleep(wait).await;
+        let auth_value = if self.auth_header.eq_ignore_ascii_case("authorization") {
+            format!("Bearer {}", self.api_key)
+        } else {
+            self.api_key.clone()
+        };
+
+        let parsed: CountTokensResponse = self
+            .scheduler
+            .send(self.max_retries, || {
+                let mut req = self
+                    .client
+                    .post(&self.url)
+                    .header(&self.auth_header, &auth_value)
+                    .header("anthropic-version", ANTHROPIC_VERSION);
+                for (name, value) in &self.extra_headers {
+                    req = req.header(name, value);
+                }
+                req.json(&body)
+            })
+            .await?;
+        Ok(parsed.input_tokens)
+    }
+
+    /// Count every item of `inputs`, serving cache hits locally and
+    /// sending only misses, then subtract the envelope overhead from text
+    /// counts so they are raw content tokens comparable with the local
+    /// tokenizers.  The overhead is calibrated on text, so `document` counts
+    /// keep their envelope.
+    async fn count_all(&self, inputs: &[Input<'_>]) -> Vec<Result<Counted, TokenizeError>> {
+        let mut wrapped: Vec<Result<usize, TokenizeError>> = Vec::with_capacity(inputs.len());
+        let mut misses: Vec<usize> = Vec::new();
+        for (idx, &input) in inputs.iter().enumerate() {
+            if let Some(n) = self.cached(input) {
+                wrapped.push(Ok(n));
             } else {
-                let body_text = resp.text().await.unwrap_or_default();
-                return Err(TokenizeError::ApiError {
-                    status,
-                    body: body_text,
-                });
+                wrapped.push(Ok(0)); // Replaced once the request completes.
+                misses.push(idx);
             }
         }
 
-        Err(TokenizeError::RateLimitExceeded)
+        let fetched: Vec<_> = stream::iter(misses)
+            .map(|idx| async move {
+                let result = match inputs[idx] {
+                    Input::Text(content) => self.count_wrapped(content).await,
+                    Input::Document(data) => self.count_document_wrapped(data).await,
+                };
+                (idx, result)
+            })
+            // The scheduler decides how many actually run.
+            .buffer_unordered(self.concurrency())
+            .collect()
+            .await;
+        for (idx, result) in fetched {
+            if let Ok(n) = result {
+                self.record(inputs[idx], n);
+            }
+            wrapped[idx] = result;
+        }
+
+        let any_text = inputs
+            .iter()
+            .zip(&wrapped)
+            .any(|(input, result)| matches!(input, Input::Text(_)) && result.is_ok());
+        let overhead = if any_text {
+            self.envelope_overhead().await.unwrap_or_else(|e| {
+                eprintln!("warning: could not calibrate Claude envelope overhead: {e}");
+                0
+            })
+        } else {
+            0
+        };
+        if let Err(e) = self.save_cache() {
+            eprintln!("warning: could not write token cache: {e}");
+        }
+
+        wrapped
+            .into_iter()
+            .zip(inputs)
+            .map(|(result, input)| {
+                result.map(|n| Counted {
+                    tokens: match input {
+                        Input::Text(_) => n.saturating_sub(overhead),
+                        Input::Document(_) => n,
+                    },
+                    wrapped: Some(n),
+                })
+            })
+            .collect()
+    }
+}
+
+impl Tokenizer for ClaudeTokenizer {
+    fn id(&self) -> TokenizerId {
+        TokenizerId::Claude
+    }
+
+    /// Count one item on the tokenizer's own runtime.  Prefer
+    /// [`count_many`](Tokenizer::count_many) for many files, which overlaps
+    /// their requests and writes the cache once.
+    fn count_tokens(&self, content: &str) -> Result<usize, TokenizeError> {
+        self.runtime.block_on(async {
+            let wrapped = self.cached_or_fetched(content).await?;
+            Ok(wrapped.saturating_sub(self.envelope_overhead().await?))
+        })?
+    }
+
+    fn count_many<'a>(
+        &'a self,
+        contents: &'a [&'a str],
+    ) -> LocalBoxFuture<'a, Vec<Result<Counted, TokenizeError>>> {
+        Box::pin(async move {
+            let inputs: Vec<_> = contents.iter().map(|c| Input::Text(c)).collect();
+            self.count_all(&inputs).await
+        })
+    }
+
+    fn reads_documents(&self) -> bool {
+        true
+    }
+
+    /// PDFs are sent whole as `document` blocks, so their counts are exact.
+    fn count_documents<'a>(
+        &'a self,
+        documents: &'a [&'a [u8]],
+    ) -> Option<LocalBoxFuture<'a, Vec<Result<Counted, TokenizeError>>>> {
+        Some(Box::pin(async move {
+            let inputs: Vec<_> = documents.iter().map(|d| Input::Document(d)).collect();
+            self.count_all(&inputs).await
+        }))
+    }
+
+    fn count_image(&self, width: u32, height: u32) -> Option<usize> {
+        Some(image::claude_tokens(width, height))
+    }
+
+    fn fallback(&self) -> Option<Box<dyn Tokenizer>> {
+        Some(Box::new(CtocTokenizer::new()))
     }
 }
 
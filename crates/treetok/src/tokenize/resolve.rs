@@ -1,36 +1,102 @@
+use std::path::PathBuf;
+
 use super::TokenizerId;
+use super::cache::CountCache;
 use super::error::TokenizeError;
-use super::local::{CtocTokenizer, O200kTokenizer, Tokenizer};
+use super::gemini_api::{self, GeminiApiTokenizer};
+use super::local::{CtocTokenizer, GeminiTokenizer, HfTokenizer, TiktokenTokenizer};
+use super::model::lookup_model;
 use super::remote::{self, ClaudeTokenizer};
+use super::server::ServerTokenizer;
+use super::spec::TokenizerSpec;
+use super::tokenizer::Tokenizer;
 
-/// Split tokenizer set: local (synchronous) and optional Claude (async).
+/// The active tokenizers, local and remote alike, in resolution order.
 pub struct ResolvedTokenizers {
-    /// Offline tokenizers (o200k, etc.).
-    pub local: Vec<Box<dyn Tokenizer>>,
-    /// Optional Claude API tokenizer.
-    pub claude: Option<ClaudeTokenizer>,
+    /// Every tokenizer to run.
+    pub tokenizers: Vec<Box<dyn Tokenizer>>,
 }
 
 impl ResolvedTokenizers {
     /// Total number of active tokenizers.
     pub fn count(&self) -> usize {
-        self.local.len() + usize::from(self.claude.is_some())
+        self.tokenizers.len()
+    }
+
+    /// Iterate over the active tokenizers.
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Tokenizer> {
+        self.tokenizers.iter().map(AsRef::as_ref)
     }
 }
 
+/// Settings that shape how tokenizers are activated.
+#[derive(Default)]
+pub struct ResolveOptions {
+    /// If `true`, the Claude and Gemini APIs are skipped even if a key is
+    /// present.  `server:` tokenizers are still used; they were asked for by URL.
+    pub offline: bool,
+    /// Pre-resolved API key (`None` = unavailable or offline).
+    pub api_key: Option<String>,
+    /// Pre-tokenization regex for `tiktoken:` specs (`None` = the o200k pattern).
+    pub tiktoken_pattern: Option<String>,
+    /// Special tokens (text, rank) registered with `tiktoken:` specs.
+    pub tiktoken_specials: Vec<(String, u32)>,
+    /// Model names from `--model`, resolved through the model registry.
+    pub models: Vec<String>,
+    /// Directory of the Claude count cache (`None` = `--no-cache`).
+    pub cache_dir: Option<PathBuf>,
+    /// Anthropic API root (`None` = the public API).
+    pub api_base: Option<String>,
+    /// Header carrying the API key (`None` = `x-api-key`).
+    pub auth_header: Option<String>,
+    /// Extra `(name, value)` headers sent with every Claude request.
+    pub api_headers: Vec<(String, String)>,
+    /// Most concurrent requests per API tokenizer (`None` = the default).
+    pub concurrency: Option<usize>,
+    /// Retries of a throttled API request (`None` = the default).
+    pub max_retries: Option<u32>,
+    /// Count Gemini with the `countTokens` API instead of the embedded
+    /// vocab.  Off by default, since it uploads file contents to Google.
+    pub gemini_api: bool,
+    /// Pre-resolved Gemini API key, used only with
+    /// [`gemini_api`](Self::gemini_api).
+    pub gemini_api_key: Option<String>,
+    /// Generative Language API root (`None` = the public API).
+    pub gemini_api_base: Option<String>,
+}
+
 /// Decide which tokenizers to activate.
 ///
-/// * `explicit`  – names from `-t` flags (empty = use all available).
-/// * `offline`   – if `true`, online tokenizers are skipped even if a key is present.
-/// * `api_key`   – pre-resolved API key (`None` = unavailable or offline).
+/// `explicit` holds the specs from `-t` flags: a built-in name, `hf:PATH`,
+/// `tiktoken:PATH`, `server:URL`, or any of the last three prefixed with
+/// `NAME=`.  Models from
+/// [`ResolveOptions::models`] add their tokenizer on top.  With neither, all
+/// available tokenizers are used.
 pub fn resolve_tokenizers(
     explicit: &[String],
-    offline: bool,
-    api_key: Option<String>,
+    opts: &ResolveOptions,
 ) -> Result<ResolvedTokenizers, TokenizeError> {
-    if explicit.is_empty() {
+    let (mut tokenizers, claude) = resolve_split(explicit, opts)?;
+    if let Some(claude) = claude {
+        tokenizers.push(Box::new(configure_claude(claude, opts)));
+    }
+    Ok(ResolvedTokenizers { tokenizers })
+}
+
+/// Every tokenizer but Claude, plus the Claude tokenizer, if any, before it
+/// is configured and boxed.
+type Split = (Vec<Box<dyn Tokenizer>>, Option<ClaudeTokenizer>);
+
+/// The body of [`resolve_tokenizers`].  Claude is kept apart so later
+/// `--model`s can see which model it counts.
+fn resolve_split(explicit: &[String], opts: &ResolveOptions) -> Result<Split, TokenizeError> {
+    let offline = opts.offline;
+    let api_key = opts.api_key.clone();
+
+    if explicit.is_empty() && opts.models.is_empty() {
         // Range mode: use all available tokenizers.
-        let mut local: Vec<Box<dyn Tokenizer>> = vec![Box::new(O200kTokenizer::new()?)];
+        let mut tokenizers: Vec<Box<dyn Tokenizer>> =
+            vec![Box::new(TiktokenTokenizer::openai(TokenizerId::O200k)?)];
 
         let claude = match (offline, api_key) {
             (false, Some(key)) => Some(ClaudeTokenizer::with_key(key)),
@@ -47,38 +113,217 @@ pub fn resolve_tokenizers(
 
         // Fall back to ctoc whenever the Claude API tokenizer is unavailable.
         if claude.is_none() {
-            local.push(Box::new(CtocTokenizer::new()));
+            tokenizers.push(Box::new(CtocTokenizer::new()));
+        }
+
+        // The Gemini API with `--gemini-api`; otherwise the embedded vocab,
+        // which is only present once fetched.
+        match gemini(opts, None) {
+            Ok(gemini) => tokenizers.push(gemini),
+            Err(e) => eprintln!("note: skipping gemini: {e}"),
         }
 
-        Ok(ResolvedTokenizers { local, claude })
+        Ok((tokenizers, claude))
     } else {
         // Explicit mode: instantiate only what was requested.
-        let mut local: Vec<Box<dyn Tokenizer>> = Vec::new();
+        let mut tokenizers: Vec<Box<dyn Tokenizer>> = Vec::new();
         let mut claude: Option<ClaudeTokenizer> = None;
 
         for name in explicit {
-            match name.parse::<TokenizerId>() {
-                Ok(TokenizerId::O200k) => local.push(Box::new(O200kTokenizer::new()?)),
-                Ok(TokenizerId::Ctoc) => local.push(Box::new(CtocTokenizer::new())),
-                Ok(TokenizerId::Claude) => match (offline, api_key.clone()) {
-                    (true, _) => eprintln!("warning: --offline set, skipping -t claude"),
-                    (false, Some(key)) => claude = Some(ClaudeTokenizer::with_key(key)),
-                    (false, None) => return Err(TokenizeError::NoApiKey),
-                },
-                Err(()) => {
+            match TokenizerSpec::parse(name)? {
+                Some(TokenizerSpec::Builtin(TokenizerId::Claude)) => {
+                    match (offline, api_key.clone()) {
+                        (true, _) => eprintln!("warning: --offline set, skipping -t claude"),
+                        (false, Some(key)) => claude = Some(ClaudeTokenizer::with_key(key)),
+                        (false, None) => return Err(TokenizeError::NoApiKey),
+                    }
+                }
+                Some(TokenizerSpec::HuggingFace { id, path }) => {
+                    ensure_unused(&tokenizers, &id, name)?;
+                    tokenizers.push(Box::new(HfTokenizer::from_file(id, &path)?));
+                }
+                Some(TokenizerSpec::Tiktoken { id, path }) => {
+                    ensure_unused(&tokenizers, &id, name)?;
+                    let pattern = opts
+                        .tiktoken_pattern
+                        .as_deref()
+                        .unwrap_or(tiktoken_rs::O200K_BASE_PAT_STR);
+                    tokenizers.push(Box::new(TiktokenTokenizer::from_file(
+                        id,
+                        &path,
+                        pattern,
+                        &opts.tiktoken_specials,
+                    )?));
+                }
+                Some(TokenizerSpec::Server { id, url }) => {
+                    ensure_unused(&tokenizers, &id, name)?;
+                    tokenizers.push(Box::new(configure_server(
+                        ServerTokenizer::new(id, &url),
+                        opts,
+                    )));
+                }
+                Some(TokenizerSpec::Builtin(TokenizerId::Gemini)) => {
+                    tokenizers.push(gemini(opts, None)?);
+                }
+                Some(TokenizerSpec::Builtin(id)) => tokenizers.push(local_builtin(id)?),
+                None => {
                     eprintln!("warning: unknown tokenizer {name:?}, skipping");
                 }
             }
         }
 
-        if local.is_empty() && claude.is_none() {
+        // The Claude model a `--model` picked, if any; `-t claude` alone
+        // counts for the default model.
+        let mut claude_model: Option<&str> = None;
+        for name in &opts.models {
+            let info = lookup_model(name)?;
+            if info.tokenizer == TokenizerId::Claude {
+                match (claude.take(), offline, api_key.clone()) {
+                    (Some(existing), ..) => match claude_model {
+                        Some(first) if first != name => {
+                            return Err(TokenizeError::ConflictingModels {
+                                first: first.to_string(),
+                                second: name.clone(),
+                            });
+                        }
+                        Some(_) => claude = Some(existing),
+                        // `-t claude --model claude-…`: count for the model.
+                        None => {
+                            claude = Some(existing.with_model(name.clone()));
+                            claude_model = Some(name);
+                        }
+                    },
+                    (None, false, Some(key)) => {
+                        claude = Some(ClaudeTokenizer::with_key(key).with_model(name.clone()));
+                        claude_model = Some(name);
+                    }
+                    // The model was asked for by name, so fall back to its
+                    // offline approximation rather than failing.
+                    (None, ..) => {
+                        eprintln!(
+                            "note: Claude API unavailable \u{2014} using ctoc to approximate {name}"
+                        );
+                        if !tokenizers.iter().any(|t| t.id() == TokenizerId::Ctoc) {
+                            tokenizers.push(Box::new(CtocTokenizer::new()));
+                        }
+                    }
+                }
+            } else if tokenizers.iter().any(|t| t.id() == info.tokenizer) {
+                // Already counted, e.g. by `-t gemini` or an earlier model.
+            } else if info.tokenizer == TokenizerId::Gemini {
+                tokenizers.push(gemini(opts, Some(name))?);
+            } else {
+                tokenizers.push(local_builtin(info.tokenizer.clone())?);
+            }
+        }
+
+        if tokenizers.is_empty() && claude.is_none() {
             return Err(TokenizeError::Init(
                 "no valid tokenizers available".to_string(),
             ));
         }
 
-        Ok(ResolvedTokenizers { local, claude })
+        Ok((tokenizers, claude))
+    }
+}
+
+/// Apply endpoint, header, scheduling, and cache settings to `claude`.
+fn configure_claude(mut claude: ClaudeTokenizer, opts: &ResolveOptions) -> ClaudeTokenizer {
+    if let Some(base) = &opts.api_base {
+        claude = claude.with_api_base(base);
+    }
+    if let Some(name) = &opts.auth_header {
+        claude = claude.with_auth_header(name.clone());
+    }
+    if !opts.api_headers.is_empty() {
+        claude = claude.with_headers(opts.api_headers.clone());
+    }
+    if let Some(n) = opts.concurrency {
+        claude = claude.with_concurrency(n);
+    }
+    if let Some(n) = opts.max_retries {
+        claude = claude.with_max_retries(n);
+    }
+    // The on-disk count cache, unless `--no-cache` was set.
+    match &opts.cache_dir {
+        Some(dir) => claude.with_cache(CountCache::open(dir)),
+        None => claude,
+    }
+}
+
+/// Apply scheduling settings to a `server:` tokenizer.
+fn configure_server(mut server: ServerTokenizer, opts: &ResolveOptions) -> ServerTokenizer {
+    if let Some(n) = opts.concurrency {
+        server = server.with_concurrency(n);
+    }
+    if let Some(n) = opts.max_retries {
+        server = server.with_max_retries(n);
+    }
+    server
+}
+
+/// The Gemini tokenizer: the `countTokens` API for `model` with
+/// `--gemini-api` and not `--offline`, else the embedded Gemma 3 vocab.  A
+/// key alone never sends files to Google.
+fn gemini(opts: &ResolveOptions, model: Option<&str>) -> Result<Box<dyn Tokenizer>, TokenizeError> {
+    if !opts.gemini_api || opts.offline {
+        return Ok(Box::new(GeminiTokenizer::new()?));
+    }
+    let key = opts
+        .gemini_api_key
+        .clone()
+        .ok_or(TokenizeError::NoGeminiApiKey)?;
+    let mut api = GeminiApiTokenizer::with_key(key);
+    if let Some(model) = model {
+        api = api.with_model(model);
+    }
+    if let Some(base) = &opts.gemini_api_base {
+        api = api.with_api_base(base);
+    }
+    if let Some(n) = opts.concurrency {
+        api = api.with_concurrency(n);
+    }
+    if let Some(n) = opts.max_retries {
+        api = api.with_max_retries(n);
+    }
+    // The on-disk count cache, unless `--no-cache` was set.
+    if let Some(dir) = &opts.cache_dir {
+        api = api.with_cache(CountCache::open(dir));
+    }
+    Ok(Box::new(api))
+}
+
+/// Instantiate a built-in offline tokenizer.
+///
+/// `Claude` and `Custom` ids are handled by the caller; passing them here is
+/// an init error.
+fn local_builtin(id: TokenizerId) -> Result<Box<dyn Tokenizer>, TokenizeError> {
+    Ok(match id {
+        TokenizerId::Ctoc => Box::new(CtocTokenizer::new()),
+        TokenizerId::Gemini => Box::new(GeminiTokenizer::new()?),
+        TokenizerId::Claude | TokenizerId::Custom(_) => {
+            return Err(TokenizeError::Init(format!(
+                "{} is not a built-in offline tokenizer",
+                id.as_str()
+            )));
+        }
+        openai => Box::new(TiktokenTokenizer::openai(openai)?),
+    })
+}
+
+/// Reject a user-defined label that an earlier `-t` already claimed.
+fn ensure_unused(
+    tokenizers: &[Box<dyn Tokenizer>],
+    id: &TokenizerId,
+    spec: &str,
+) -> Result<(), TokenizeError> {
+    if tokenizers.iter().any(|t| t.id() == *id) {
+        return Err(TokenizeError::InvalidSpec(format!(
+            "{spec:?}: tokenizer name {:?} is already in use",
+            id.as_str()
+        )));
     }
+    Ok(())
 }
 
 /// Load the API key from the environment for use with [`resolve_tokenizers`].
@@ -90,12 +335,30 @@ pub fn load_api_key() -> Option<String> {
     remote::load_api_key().ok()
 }
 
+/// Load the Gemini API key from the environment for use with
+/// [`resolve_tokenizers`].
+///
+/// Prefers `GEMINI_API_KEY` over `GOOGLE_API_KEY`; returns `None` if neither
+/// is set.
+#[must_use]
+pub fn load_gemini_api_key() -> Option<String> {
+    gemini_api::load_api_key()
+}
+
 #[cfg(test)]
 mod tests {
     use super::super::TokenizerId;
     use super::*;
     use rstest::rstest;
 
+    fn opts(offline: bool, api_key: Option<String>) -> ResolveOptions {
+        ResolveOptions {
+            offline,
+            api_key,
+            ..ResolveOptions::default()
+        }
+    }
+
     fn no_key() -> Option<String> {
         None
     }
@@ -104,6 +367,10 @@ mod tests {
         Some("test-key".to_string())
     }
 
+    fn ids(r: &ResolvedTokenizers) -> Vec<TokenizerId> {
+        r.iter().map(Tokenizer::id).collect()
+    }
+
     #[rstest]
     #[case::offline(true, some_key())]
     #[case::no_api_key(false, no_key())]
@@ -111,59 +378,240 @@ mod tests {
         #[case] offline: bool,
         #[case] api_key: Option<String>,
     ) {
-        let r = resolve_tokenizers(&[], offline, api_key).unwrap();
-        assert_eq!(r.local.len(), 2);
-        assert_eq!(r.local[0].id(), TokenizerId::O200k);
-        assert_eq!(r.local[1].id(), TokenizerId::Ctoc);
-        assert!(r.claude.is_none());
+        let r = resolve_tokenizers(&[], &opts(offline, api_key)).unwrap();
+        assert_eq!(
+            ids(&r),
+            vec![TokenizerId::O200k, TokenizerId::Ctoc, TokenizerId::Gemini]
+        );
     }
 
     #[test]
     fn range_mode_with_key_uses_claude() {
-        let r = resolve_tokenizers(&[], false, some_key()).unwrap();
-        assert_eq!(r.local.len(), 1);
-        assert_eq!(r.local[0].id(), TokenizerId::O200k);
-        assert!(r.claude.is_some());
+        let r = resolve_tokenizers(&[], &opts(false, some_key())).unwrap();
+        assert_eq!(
+            ids(&r),
+            vec![TokenizerId::O200k, TokenizerId::Gemini, TokenizerId::Claude]
+        );
+    }
+
+    /// `--gemini-api` with a key makes Gemini available without the
+    /// embedded vocab.
+    #[test]
+    fn range_mode_with_gemini_api_uses_gemini_api() {
+        let resolve_opts = ResolveOptions {
+            gemini_api: true,
+            gemini_api_key: some_key(),
+            ..opts(false, no_key())
+        };
+        let r = resolve_tokenizers(&[], &resolve_opts).unwrap();
+        assert_eq!(
+            ids(&r),
+            vec![TokenizerId::O200k, TokenizerId::Ctoc, TokenizerId::Gemini]
+        );
+    }
+
+    #[test]
+    fn gemini_api_without_key_is_an_error() {
+        let resolve_opts = ResolveOptions {
+            gemini_api: true,
+            ..opts(false, no_key())
+        };
+        let err = resolve_tokenizers(&["gemini".to_string()], &resolve_opts)
+            .err()
+            .unwrap();
+        assert!(matches!(err, TokenizeError::NoGeminiApiKey), "{err:?}");
+    }
+
+    #[test]
+    fn explicit_gemini_uses_embedded_vocab() {
+        let r = resolve_tokenizers(&["gemini".to_string()], &opts(true, no_key())).unwrap();
+        assert_eq!(ids(&r), vec![TokenizerId::Gemini]);
+        assert!(!r.tokenizers[0].is_approximate());
     }
 
     #[rstest]
     #[case::o200k("o200k", false)]
+    #[case::o200k_base("o200k_base", false)]
+    #[case::cl100k("cl100k", false)]
+    #[case::p50k("p50k", false)]
+    #[case::p50k_edit("p50k_edit", false)]
+    #[case::r50k("r50k", false)]
     #[case::ctoc("ctoc", true)]
     fn explicit_local_tokenizer(
         #[case] name: &str,
         #[case] is_approx: bool,
         #[values(false, true)] offline: bool,
     ) {
-        let r = resolve_tokenizers(&[name.to_string()], offline, no_key()).unwrap();
-        assert_eq!(r.local.len(), 1);
-        assert_eq!(r.local[0].id().as_str(), name);
-        assert_eq!(r.local[0].is_approximate(), is_approx);
-        assert!(r.claude.is_none());
+        let r = resolve_tokenizers(&[name.to_string()], &opts(offline, no_key())).unwrap();
+        assert_eq!(r.count(), 1);
+        assert_eq!(r.tokenizers[0].id().as_str(), name);
+        assert_eq!(r.tokenizers[0].is_approximate(), is_approx);
     }
 
     #[test]
     fn explicit_o200k_and_ctoc() {
-        let r = resolve_tokenizers(&["o200k".to_string(), "ctoc".to_string()], false, no_key())
-            .unwrap();
-        assert_eq!(r.local.len(), 2);
-        assert_eq!(r.local[0].id(), TokenizerId::O200k);
-        assert_eq!(r.local[1].id(), TokenizerId::Ctoc);
-        assert!(r.claude.is_none());
+        let r = resolve_tokenizers(
+            &["o200k".to_string(), "ctoc".to_string()],
+            &opts(false, no_key()),
+        )
+        .unwrap();
+        assert_eq!(r.count(), 2);
+        assert_eq!(r.tokenizers[0].id(), TokenizerId::O200k);
+        assert_eq!(r.tokenizers[1].id(), TokenizerId::Ctoc);
     }
 
     #[test]
     fn unknown_alongside_valid_is_skipped() {
         let r = resolve_tokenizers(
             &["o200k".to_string(), "unknown".to_string()],
-            false,
-            no_key(),
+            &opts(false, no_key()),
+        )
+        .unwrap();
+        assert_eq!(r.count(), 1);
+        assert_eq!(r.tokenizers[0].id(), TokenizerId::O200k);
+    }
+
+    #[test]
+    fn explicit_hf_file_gets_its_own_column() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = super::super::tests::write_word_level_tokenizer(dir.path());
+        let spec = format!("qwen=hf:{}", path.display());
+        let r = resolve_tokenizers(&["o200k".to_string(), spec], &opts(false, no_key())).unwrap();
+        assert_eq!(
+            ids(&r),
+            vec![TokenizerId::O200k, TokenizerId::custom("qwen")]
+        );
+    }
+
+    #[test]
+    fn duplicate_hf_names_are_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = super::super::tests::write_word_level_tokenizer(dir.path());
+        let spec = format!("m=hf:{}", path.display());
+        let r = resolve_tokenizers(&[spec.clone(), spec], &opts(false, no_key()));
+        assert!(matches!(r, Err(TokenizeError::InvalidSpec(_))));
+    }
+
+    #[test]
+    fn explicit_tiktoken_file_uses_pattern_and_specials() {
+        let resolve_opts = ResolveOptions {
+            tiktoken_pattern: Some(r"\S+|\s+".to_string()),
+            tiktoken_specials: vec![("<|im_end|>".to_string(), 257)],
+            ..ResolveOptions::default()
+        };
+        let spec = format!("kimi=tiktoken:{}", crate::tokenize::tests::BYTE_RANKS);
+        let r = resolve_tokenizers(&[spec], &resolve_opts).unwrap();
+        assert_eq!(ids(&r), vec![TokenizerId::custom("kimi")]);
+        let kimi = &r.tokenizers[0];
+        // Bytes only: the pattern splits "hi there" into "hi", " ", "there".
+        assert_eq!(kimi.count_tokens("hi there").unwrap(), 8);
+        assert_eq!(kimi.count_tokens("<|im_end|>").unwrap(), 1);
+        assert_eq!(kimi.count_tokens("hi<|im_end|>").unwrap(), 3);
+    }
+
+    fn model_opts(models: &[&str], offline: bool, api_key: Option<String>) -> ResolveOptions {
+        ResolveOptions {
+            models: models
+                .iter()
+                .map(std::string::ToString::to_string)
+                .collect(),
+            ..opts(offline, api_key)
+        }
+    }
+
+    #[test]
+    fn models_add_their_tokenizers_once() {
+        let r = resolve_tokenizers(
+            &[],
+            &model_opts(&["gpt-4o", "o3", "gpt-4"], false, no_key()),
         )
         .unwrap();
-        assert_eq!(r.local.len(), 1);
-        assert_eq!(r.local[0].id(), TokenizerId::O200k);
+        assert_eq!(ids(&r), vec![TokenizerId::O200kBase, TokenizerId::Cl100k]);
+    }
+
+    #[test]
+    fn model_combines_with_explicit_tokenizers() {
+        let r = resolve_tokenizers(
+            &["o200k".to_string()],
+            &model_opts(&["gpt-4o"], false, no_key()),
+        )
+        .unwrap();
+        assert_eq!(ids(&r), vec![TokenizerId::O200k, TokenizerId::O200kBase]);
+    }
+
+    #[test]
+    fn claude_model_sets_request_model() {
+        let (local, claude) = resolve_split(
+            &[],
+            &model_opts(&["claude-opus-4-1-20250805"], false, some_key()),
+        )
+        .unwrap();
+        assert_eq!(
+            claude.as_ref().map(ClaudeTokenizer::model),
+            Some("claude-opus-4-1-20250805")
+        );
+        assert!(local.is_empty());
+    }
+
+    /// `-t claude` plus a Claude `--model` counts for that model rather
+    /// than the default.
+    #[test]
+    fn claude_model_applies_to_explicit_claude() {
+        let (_, claude) = resolve_split(
+            &["claude".to_string()],
+            &model_opts(&["claude-opus-4-6"], false, some_key()),
+        )
+        .unwrap();
+        assert_eq!(
+            claude.as_ref().map(ClaudeTokenizer::model),
+            Some("claude-opus-4-6")
+        );
+    }
+
+    #[rstest]
+    #[case::repeated(&["claude-opus-4-6", "claude-opus-4-6"][..], true)]
+    #[case::different(&["claude-opus-4-6", "claude-sonnet-4-6"][..], false)]
+    fn second_claude_model_must_match(#[case] models: &[&str], #[case] ok: bool) {
+        let result = resolve_split(&[], &model_opts(models, false, some_key()));
+        match result {
+            Ok((_, claude)) => {
+                assert!(ok);
+                assert_eq!(
+                    claude.as_ref().map(ClaudeTokenizer::model),
+                    Some("claude-opus-4-6")
+                );
+            }
+            Err(e) => {
+                assert!(!ok);
+                assert!(
+                    matches!(e, TokenizeError::ConflictingModels { .. }),
+                    "{e:?}"
+                );
+            }
+        }
+    }
+
+    #[rstest]
+    #[case::offline(true, some_key())]
+    #[case::no_api_key(false, no_key())]
+    fn claude_model_without_api_falls_back_to_ctoc(
+        #[case] offline: bool,
+        #[case] api_key: Option<String>,
+    ) {
+        let r =
+            resolve_tokenizers(&[], &model_opts(&["claude-sonnet-4-6"], offline, api_key)).unwrap();
+        assert_eq!(ids(&r), vec![TokenizerId::Ctoc]);
+    }
+
+    #[test]
+    fn unknown_model_is_error() {
+        let r = resolve_tokenizers(&[], &model_opts(&["gtp-4o"], false, no_key()));
+        assert!(matches!(r, Err(TokenizeError::UnknownModel { .. })));
     }
 
     #[rstest]
+    #[case::missing_tiktoken_file(&["tiktoken:/does/not/exist.tiktoken"][..], false, no_key())]
+    #[case::missing_hf_file(&["hf:/does/not/exist/tokenizer.json"][..], false, no_key())]
     #[case::unknown_only(&["not_a_real_tokenizer"][..], false, no_key())]
     #[case::offline_skips_claude(&["claude"][..],       true,  some_key())]
     #[case::no_key_for_claude(&["claude"][..],          false, no_key())]
@@ -173,6 +621,6 @@ mod tests {
         #[case] api_key: Option<String>,
     ) {
         let names: Vec<String> = names.iter().map(std::string::ToString::to_string).collect();
-        assert!(resolve_tokenizers(&names, offline, api_key).is_err());
+        assert!(resolve_tokenizers(&names, &opts(offline, api_key)).is_err());
     }
 }
@@ -1,116 +1,589 @@
-use std::collections::BTreeMap;
+use std::borrow::Cow;
+use std::cell::OnceCell;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet};
+use std::future::Future;
+use std::sync::OnceLock;
 
-use futures::stream::{self, StreamExt};
-
-use super::remote::ClaudeTokenizer;
+use super::error::TokenizeError;
 use super::resolve::ResolvedTokenizers;
+use super::tokenizer::{Counted, Tokenizer};
 use super::{TokenCount, TokenizerId};
-use crate::walk::FileKind;
+use crate::output::FileResult;
+use crate::walk::{FileEntry, FileKind};
 
 /// Tokenize a slice of file entries and return results.
 ///
-/// Phase 1: run local tokenizers sequentially (microseconds per file).
-/// Phase 2: run Claude tokenizer asynchronously with concurrency limiting.
+/// Every tokenizer receives the full batch of text files through
+/// [`Tokenizer::count_many`], and the batches run concurrently on one
+/// runtime: local tokenizers finish as soon as they are polled, while remote
+/// ones overlap their requests.  A file a tokenizer fails on gets the
+/// tokenizer's [`fallback`](Tokenizer::fallback) estimate, if it has one,
+/// flagged in [`FileResult::fallback`](crate::output::FileResult::fallback).
+/// Images are counted from their dimensions by
+/// [`Tokenizer::count_image`], without a request.  PDFs go whole to
+/// tokenizers that [count documents](Tokenizer::count_documents) natively;
+/// the rest count the extracted text and [estimate](Tokenizer::count_pdf)
+/// the pages on top.  [`FileKind::Chunked`] files are read and counted a
+/// batch of chunks at a time after everything else; their counts are the
+/// sums over chunks, widened into a range by a token per cut.
 #[must_use]
-pub fn tokenize_entries(
-    entries: &[crate::walk::FileEntry],
-    tokenizers: &ResolvedTokenizers,
-) -> Vec<crate::output::FileResult> {
-    // Phase 1: local tokenizers (sequential).
-    let mut results: Vec<crate::output::FileResult> = entries
+pub fn tokenize_entries(entries: &[FileEntry], tokenizers: &ResolvedTokenizers) -> Vec<FileResult> {
+    let mut results: Vec<FileResult> = entries
         .iter()
         .map(|entry| {
-            let (kind, tokens) = match &entry.kind {
-                FileKind::Binary => (FileKind::Binary, BTreeMap::new()),
-                FileKind::TooLarge => (FileKind::TooLarge, BTreeMap::new()),
-                FileKind::Error(msg) => {
-                    eprintln!("warning: {}: {msg}", entry.path.display());
-                    (FileKind::Error(msg.clone()), BTreeMap::new())
-                }
-                FileKind::Text => {
-                    let content = entry.content.as_deref().unwrap_or("");
-                    let mut counts: BTreeMap<TokenizerId, TokenCount> = BTreeMap::new();
-
-                    for tok in &tokenizers.local {
-                        match tok.count_tokens(content) {
-                            Ok(n) => {
-                                let tc = if tok.is_approximate() {
-                                    TokenCount::from_approx(n)
-                                } else {
-                                    TokenCount::Exact(n)
-                                };
-                                counts.insert(tok.id(), tc);
-                            }
-                            Err(e) => {
-                                eprintln!(
-                                    "warning: {} [{}]: {e}",
-                                    entry.path.display(),
-                                    tok.id().as_str()
-                                );
-                            }
-                        }
-                    }
-
-                    (FileKind::Text, counts)
-                }
-            };
-
-            crate::output::FileResult {
+            if let FileKind::Error(msg) = &entry.kind {
+                eprintln!("warning: {}: {msg}", entry.path.display());
+            }
+            FileResult {
                 rel_path: entry.rel_path.clone(),
-                kind,
-                tokens,
+                kind: entry.kind.clone(),
+                tokens: BTreeMap::new(),
+                wrapped: BTreeMap::new(),
+                fallback: BTreeSet::new(),
             }
         })
         .collect();
 
-    // Phase 2: Claude (async, concurrency-limited).
-    if let Some(claude) = &tokenizers.claude {
-        #[allow(clippy::expect_used)] // Infallible in practice; no recovery path.
-        let rt = tokio::runtime::Builder::new_current_thread()
-            .enable_all()
-            .build()
-            .expect("tokio runtime");
-        rt.block_on(claude_tokenize_all(&mut results, entries, claude));
+    let texts: Vec<usize> = (0..entries.len())
+        .filter(|&idx| matches!(entries[idx].kind, FileKind::Text(_)))
+        .collect();
+    let pdfs: Vec<usize> = (0..entries.len())
+        .filter(|&idx| matches!(entries[idx].kind, FileKind::Pdf(_)))
+        .collect();
+    // Text files, then the text extracted from each PDF.
+    let contents: Vec<&str> = texts
+        .iter()
+        .chain(&pdfs)
+        .map(|&idx| entries[idx].content.as_deref().unwrap_or(""))
+        .collect();
+    // Raw PDF bytes, read only if some tokenizer takes documents whole.
+    let loaded: Vec<Cow<'_, [u8]>> = if tokenizers.iter().any(Tokenizer::reads_documents) {
+        pdfs.iter()
+            .map(|&idx| load_document(&entries[idx]))
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let documents: Vec<&[u8]> = loaded.iter().map(AsRef::as_ref).collect();
+
+    // One runtime for the whole run, reused by every chunk batch below.
+    let runtime = Blocking::default();
+    let (contents, text_contents, documents) = (&contents, &contents[..texts.len()], &documents);
+    let batches = runtime.block_on(futures::future::join_all(tokenizers.iter().map(
+        |tok| async move {
+            let native = if tok.reads_documents() {
+                tok.count_documents(documents)
+            } else {
+                None
+            };
+            match native {
+                Some(native) => {
+                    let (counts, native) =
+                        futures::future::join(tok.count_many(text_contents), native).await;
+                    (counts, Some(native))
+                }
+                // Pages alone: every PDF's text counts as zero.
+                None if !tok.counts_pdf_text() => {
+                    let mut counts = tok.count_many(text_contents).await;
+                    counts.resize_with(contents.len(), || Ok(Counted::raw(0)));
+                    (counts, None)
+                }
+                None => (tok.count_many(contents).await, None),
+            }
+        },
+    )));
+    // Inside a caller's runtime nothing can be awaited, so every count fails
+    // and falls back like a failed request.
+    let batches = batches.unwrap_or_else(|e| {
+        tokenizers
+            .iter()
+            .map(|_| (vec![Err(e.clone()); contents.len()], None))
+            .collect()
+    });
+
+    for (tok, (counts, native)) in tokenizers.iter().zip(batches) {
+        let recorder = Recorder::new(tok);
+        for (entry, file) in entries.iter().zip(&mut results) {
+            if let FileKind::Image(image) = &entry.kind
+                && let Some(n) = tok.count_image(image.width, image.height)
+            {
+                // A formula estimate, even from an exact text tokenizer.
+                recorder.add(file, TokenCount::from_approx(n));
+            }
+        }
+        let estimate_pdfs = native.is_none();
+        let counts = counts.into_iter().chain(native.into_iter().flatten());
+        for ((&idx, content), result) in texts.iter().chain(&pdfs).zip(contents).zip(counts) {
+            recorder.record(
+                &entries[idx],
+                &mut results[idx],
+                content,
+                result,
+                estimate_pdfs,
+            );
+        }
+    }
+
+    // Large files last, a batch of chunks at a time, so memory stays flat.
+    let recorders: Vec<Recorder<'_>> = tokenizers.iter().map(Recorder::new).collect();
+    for (entry, file) in entries.iter().zip(&mut results) {
+        if matches!(entry.kind, FileKind::Chunked(_))
+            && let Err(e) = count_chunked(entry, file, &recorders, &runtime)
+        {
+            eprintln!("warning: {}: {e}", entry.path.display());
+            *file = FileResult {
+                kind: FileKind::Error(e.to_string()),
+                tokens: BTreeMap::new(),
+                wrapped: BTreeMap::new(),
+                fallback: BTreeSet::new(),
+                rel_path: std::mem::take(&mut file.rel_path),
+            };
+        }
     }
 
     results
 }
 
-async fn claude_tokenize_all(
-    results: &mut [crate::output::FileResult],
-    entries: &[crate::walk::FileEntry],
-    claude: &ClaudeTokenizer,
-) {
-    let text_indices: Vec<usize> = entries
-        .iter()
-        .enumerate()
-        .filter(|(_, e)| matches!(e.kind, crate::walk::FileKind::Text))
-        .map(|(i, _)| i)
-        .collect();
+/// `entry`'s raw PDF bytes, or none if they can no longer be read; the
+/// document count then fails and falls back like any other.
+fn load_document(entry: &FileEntry) -> Cow<'_, [u8]> {
+    entry.document().unwrap_or_else(|e| {
+        eprintln!("warning: {}: {e}", entry.path.display());
+        Cow::Borrowed(&[])
+    })
+}
 
-    let counts: Vec<_> = stream::iter(text_indices)
-        .map(|idx| {
-            let content = entries[idx].content.as_deref().unwrap_or("");
-            async move { (idx, claude.count_tokens(content).await) }
-        })
-        .buffer_unordered(20)
-        .collect()
-        .await;
+/// Chunks of a [`FileKind::Chunked`] file counted per
+/// [`count_many`](Tokenizer::count_many) call: enough for remote tokenizers
+/// to overlap requests, while holding at most this many
+/// [`CHUNK_BYTES`](crate::walk::CHUNK_BYTES) in memory.
+const CHUNK_BATCH: usize = 16;
+
+/// Count `entry` a batch of chunks at a time, summing each tokenizer's
+/// counts into `file`.
+///
+/// A cut can still change the merges around it (a blank line after the cut
+/// is one token with the line break before it when counted whole), so each
+/// sum is widened by a token either way per cut.
+fn count_chunked(
+    entry: &FileEntry,
+    file: &mut FileResult,
+    recorders: &[Recorder<'_>],
+    runtime: &Blocking,
+) -> std::io::Result<()> {
+    let mut chunks = entry.chunks()?;
+    let mut cuts = 0;
+    loop {
+        let batch = chunks
+            .by_ref()
+            .take(CHUNK_BATCH)
+            .collect::<std::io::Result<Vec<String>>>()?;
+        if batch.is_empty() {
+            break;
+        }
+        cuts += batch.len();
+        let contents: Vec<&str> = batch.iter().map(String::as_str).collect();
+        let batches = runtime
+            .block_on(futures::future::join_all(
+                recorders.iter().map(|r| r.tok.count_many(&contents)),
+            ))
+            .map_err(std::io::Error::other)?;
+        for (recorder, counts) in recorders.iter().zip(batches) {
+            for (content, result) in contents.iter().zip(counts) {
+                recorder.record(entry, file, content, result, false);
+            }
+        }
+    }
+    // One cut fewer than chunks.
+    let cuts = cuts.saturating_sub(1);
+    if cuts > 0 {
+        for count in file.tokens.values_mut() {
+            count.widen(cuts);
+        }
+    }
+    Ok(())
+}
 
-    for (idx, result) in counts {
+/// Records one tokenizer's results into [`FileResult`]s.  Counts add up, so
+/// a chunked file is recorded once per chunk.
+struct Recorder<'t> {
+    tok: &'t dyn Tokenizer,
+    id: TokenizerId,
+    /// Built on the first failure only; most runs never need it.
+    fallback: OnceCell<Option<Box<dyn Tokenizer>>>,
+}
+
+impl<'t> Recorder<'t> {
+    fn new(tok: &'t dyn Tokenizer) -> Self {
+        Self {
+            tok,
+            id: tok.id(),
+            fallback: OnceCell::new(),
+        }
+    }
+
+    fn count_of(&self, n: usize) -> TokenCount {
+        if self.tok.is_approximate() {
+            TokenCount::from_approx(n)
+        } else {
+            TokenCount::Exact(n)
+        }
+    }
+
+    fn add(&self, file: &mut FileResult, count: TokenCount) {
+        match file.tokens.entry(self.id.clone()) {
+            Entry::Occupied(mut total) => total.get_mut().add(&count),
+            Entry::Vacant(slot) => {
+                slot.insert(count);
+            }
+        }
+    }
+
+    /// Record `result`, the count of `content` (all or part of `entry`).
+    /// With `estimate_pdf`, a PDF's text count is priced with
+    /// [`Tokenizer::count_pdf`].  A failure gets the fallback's estimate,
+    /// flagged, and voids the file's wrapped count.
+    fn record(
+        &self,
+        entry: &FileEntry,
+        file: &mut FileResult,
+        content: &str,
+        result: Result<Counted, TokenizeError>,
+        estimate_pdf: bool,
+    ) {
+        // A PDF's text count, plus its pages, by `tok` or its fallback.
+        let priced = |tok: &dyn Tokenizer, n: usize| match &entry.kind {
+            FileKind::Pdf(pdf) => tok.count_pdf(n, pdf),
+            _ => n,
+        };
         match result {
-            Ok(n) => {
-                results[idx]
-                    .tokens
-                    .insert(TokenizerId::Claude, TokenCount::Exact(n));
+            Ok(counted) if estimate_pdf && matches!(entry.kind, FileKind::Pdf(_)) => {
+                self.add(
+                    file,
+                    TokenCount::from_approx(priced(self.tok, counted.tokens)),
+                );
+            }
+            Ok(counted) => {
+                self.add(file, self.count_of(counted.tokens));
+                if let Some(n) = counted.wrapped
+                    && !file.fallback.contains(&self.id)
+                {
+                    *file.wrapped.entry(self.id.clone()).or_insert(0) += n;
+                }
             }
             Err(e) => {
+                let fallback = self.fallback.get_or_init(|| self.tok.fallback());
+                let note = fallback.as_ref().map_or_else(String::new, |fb| {
+                    format!("; using {} estimate", fb.id().as_str())
+                });
                 eprintln!(
-                    "warning: {} [{}]: {e}",
-                    entries[idx].path.display(),
-                    TokenizerId::Claude.as_str()
+                    "warning: {} [{}]: {e}{note}",
+                    entry.path.display(),
+                    self.id.as_str()
                 );
+                let estimate = fallback
+                    .as_ref()
+                    .map(|fb| fb.count_tokens(content).map(|n| priced(fb.as_ref(), n)));
+                if let Some(Ok(n)) = estimate {
+                    self.add(file, TokenCount::from_approx(n));
+                    file.fallback.insert(self.id.clone());
+                    file.wrapped.remove(&self.id);
+                }
+            }
+        }
+    }
+}
+
+/// A single-threaded runtime for driving requests from synchronous code,
+/// built on first use and reused by every later call.
+#[derive(Default)]
+pub(super) struct Blocking(OnceLock<tokio::runtime::Runtime>);
+
+impl Blocking {
+    /// Run `future` to completion on this runtime.  Fails instead of
+    /// panicking when called from inside another runtime, whose thread the
+    /// wait would stall.
+    pub(super) fn block_on<F: Future>(&self, future: F) -> Result<F::Output, TokenizeError> {
+        if tokio::runtime::Handle::try_current().is_ok() {
+            return Err(TokenizeError::NestedRuntime);
+        }
+        #[allow(clippy::expect_used)] // Infallible in practice; no recovery path.
+        let runtime = self.0.get_or_init(|| {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("tokio runtime")
+        });
+        Ok(runtime.block_on(future))
+    }
+}
+
+impl Drop for Blocking {
+    /// Shut down without waiting, which unlike a plain drop is allowed
+    /// inside another runtime.
+    fn drop(&mut self) {
+        if let Some(runtime) = self.0.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future::LocalBoxFuture;
+
+    use super::*;
+    use crate::encoding::Encoding;
+    use crate::tokenize::{CtocTokenizer, TiktokenTokenizer};
+
+    /// A remote-style tokenizer: one token per word plus a two-token
+    /// envelope, failing on content that contains `"fail"`.
+    struct Words;
+
+    impl Tokenizer for Words {
+        fn id(&self) -> TokenizerId {
+            TokenizerId::custom("words")
+        }
+
+        fn count_tokens(&self, content: &str) -> Result<usize, TokenizeError> {
+            if content.contains("fail") {
+                return Err(TokenizeError::Network("unreachable".to_string()));
             }
+            Ok(content.split_whitespace().count())
+        }
+
+        fn count_many<'a>(
+            &'a self,
+            contents: &'a [&'a str],
+        ) -> LocalBoxFuture<'a, Vec<Result<Counted, TokenizeError>>> {
+            Box::pin(async move {
+                contents
+                    .iter()
+                    .map(|c| {
+                        self.count_tokens(c).map(|n| Counted {
+                            tokens: n,
+                            wrapped: Some(n + 2),
+                        })
+                    })
+                    .collect()
+            })
+        }
+
+        fn fallback(&self) -> Option<Box<dyn Tokenizer>> {
+            Some(Box::new(CtocTokenizer::new()))
+        }
+    }
+
+    fn entry(name: &str, kind: FileKind, content: Option<&str>) -> FileEntry {
+        FileEntry {
+            path: name.into(),
+            rel_path: name.into(),
+            kind,
+            content: content.map(str::to_string),
+            document: None,
+        }
+    }
+
+    #[test]
+    fn every_tokenizer_runs_through_count_many() {
+        let entries = [
+            entry(
+                "a.txt",
+                FileKind::Text(Encoding::Utf8),
+                Some("one two three"),
+            ),
+            entry("b.bin", FileKind::Binary, None),
+            entry(
+                "c.txt",
+                FileKind::Text(Encoding::Utf8),
+                Some("please fail here"),
+            ),
+        ];
+        let tokenizers = ResolvedTokenizers {
+            tokenizers: vec![Box::new(Words), Box::new(CtocTokenizer::new())],
+        };
+
+        let results = tokenize_entries(&entries, &tokenizers);
+
+        let words = TokenizerId::custom("words");
+        assert!(matches!(results[0].tokens[&words], TokenCount::Exact(3)));
+        assert_eq!(results[0].wrapped[&words], 5);
+        assert!(matches!(
+            results[0].tokens[&TokenizerId::Ctoc],
+            TokenCount::Approx { .. }
+        ));
+        assert!(results[1].tokens.is_empty());
+        // The failed file gets the fallback estimate, flagged.
+        assert!(matches!(
+            results[2].tokens[&words],
+            TokenCount::Approx { .. }
+        ));
+        assert!(results[2].fallback.contains(&words));
+        assert!(!results[2].wrapped.contains_key(&words));
+    }
+
+    #[test]
+    fn images_are_priced_by_tokenizers_with_a_formula() {
+        let image = crate::image::ImageInfo {
+            format: crate::image::ImageFormat::Png,
+            width: 200,
+            height: 200,
+        };
+        let entries = [entry("a.png", FileKind::Image(image), None)];
+        let tokenizers = ResolvedTokenizers {
+            tokenizers: vec![
+                Box::new(Words),
+                Box::new(CtocTokenizer::new()),
+                Box::new(TiktokenTokenizer::openai(TokenizerId::O200k).unwrap()),
+            ],
+        };
+
+        let results = tokenize_entries(&entries, &tokenizers);
+
+        // ctoc stands for Claude: 200 × 200 / 750.  Every formula count is
+        // an estimate, even o200k's.
+        assert!(
+            !results[0]
+                .tokens
+                .contains_key(&TokenizerId::custom("words"))
+        );
+        let expected = [
+            (TokenizerId::Ctoc, 54),
+            (TokenizerId::O200k, crate::image::openai_tokens(200, 200)),
+        ];
+        for (id, n) in expected {
+            let count = &results[0].tokens[&id];
+            assert!(
+                matches!(count, TokenCount::Approx { .. }),
+                "{id:?}: {count:?}"
+            );
+            assert_eq!(count.lo(), TokenCount::from_approx(n).lo());
+        }
+    }
+
+    /// Without native document counting, a PDF is its extracted text plus a
+    /// page image per page, and always approximate.
+    #[test]
+    fn pdfs_are_estimated_from_text_and_pages() {
+        let pdf = crate::pdf::PdfInfo {
+            pages: 2,
+            width_pt: 612,
+            height_pt: 792,
+        };
+        let mut spec = entry("spec.pdf", FileKind::Pdf(pdf), Some("one two three"));
+        spec.document = Some(b"%PDF-1.7".to_vec());
+        let tokenizers = ResolvedTokenizers {
+            tokenizers: vec![Box::new(Words), Box::new(CtocTokenizer::new())],
+        };
+
+        let results = tokenize_entries(&[spec], &tokenizers);
+
+        // Words has no image formula: just the text, but still a range.
+        let words = &results[0].tokens[&TokenizerId::custom("words")];
+        assert_eq!(words.lo(), TokenCount::from_approx(3).lo());
+        assert!(results[0].wrapped.is_empty());
+        // ctoc adds a letter page at 150 dpi, capped at 1,600, per page.
+        let ctoc = &results[0].tokens[&TokenizerId::Ctoc];
+        assert!(ctoc.lo() >= TokenCount::from_approx(3_200).lo(), "{ctoc:?}");
+    }
+
+    /// Prices PDFs by page alone, recording every text it is asked to count.
+    #[derive(Default)]
+    struct Pages {
+        seen: std::rc::Rc<std::cell::RefCell<Vec<String>>>,
+    }
+
+    impl Tokenizer for Pages {
+        fn id(&self) -> TokenizerId {
+            TokenizerId::custom("pages")
+        }
+
+        fn count_tokens(&self, content: &str) -> Result<usize, TokenizeError> {
+            self.seen.borrow_mut().push(content.to_string());
+            Ok(content.len())
+        }
+
+        fn count_pdf(&self, _text_tokens: usize, pdf: &crate::pdf::PdfInfo) -> usize {
+            100 * pdf.pages
         }
+
+        fn counts_pdf_text(&self) -> bool {
+            false
+        }
+    }
+
+    /// A tokenizer that prices PDFs by page never counts their text, and
+    /// nothing reads the PDF's bytes when no tokenizer takes documents.
+    #[test]
+    fn page_priced_pdfs_skip_their_text() {
+        let pdf = crate::pdf::PdfInfo {
+            pages: 2,
+            width_pt: 612,
+            height_pt: 792,
+        };
+        let entries = [
+            entry("a.txt", FileKind::Text(Encoding::Utf8), Some("abc")),
+            entry("missing.pdf", FileKind::Pdf(pdf), Some("one two three")),
+        ];
+        let pages = Pages::default();
+        let seen = std::rc::Rc::clone(&pages.seen);
+        let tokenizers = ResolvedTokenizers {
+            tokenizers: vec![Box::new(pages)],
+        };
+
+        let results = tokenize_entries(&entries, &tokenizers);
+
+        let id = TokenizerId::custom("pages");
+        assert!(matches!(results[0].tokens[&id], TokenCount::Exact(3)));
+        assert_eq!(
+            results[1].tokens[&id].lo(),
+            TokenCount::from_approx(200).lo()
+        );
+        assert_eq!(*seen.borrow(), ["abc"]);
+    }
+
+    /// A chunked file's counts are summed over its chunks, wrapped counts
+    /// included, and widened by a token per cut.
+    #[test]
+    fn chunked_files_sum_their_chunks() {
+        let text = "one two three four\n".repeat(30_000);
+        let entries = [entry(
+            "big.log",
+            FileKind::Chunked(Encoding::Utf8),
+            Some(&text),
+        )];
+        let tokenizers = ResolvedTokenizers {
+            tokenizers: vec![Box::new(Words)],
+        };
+
+        let results = tokenize_entries(&entries, &tokenizers);
+
+        let words = TokenizerId::custom("words");
+        let chunks = text.len().div_ceil(crate::walk::CHUNK_BYTES);
+        let cuts = chunks - 1;
+        let count = &results[0].tokens[&words];
+        assert!(matches!(count, TokenCount::Approx { .. }));
+        assert_eq!((count.lo(), count.hi()), (120_000 - cuts, 120_000 + cuts));
+        assert_eq!(results[0].wrapped[&words], 120_000 + 2 * chunks);
+        assert!(matches!(results[0].kind, FileKind::Chunked(_)));
+    }
+
+    /// A chunked file that can no longer be read becomes an error row.
+    #[test]
+    fn unreadable_chunked_file_is_an_error() {
+        let entries = [entry(
+            "/nonexistent/big.log",
+            FileKind::Chunked(Encoding::Utf8),
+            None,
+        )];
+        let tokenizers = ResolvedTokenizers {
+            tokenizers: vec![Box::new(Words)],
+        };
+
+        let results = tokenize_entries(&entries, &tokenizers);
+
+        assert!(matches!(results[0].kind, FileKind::Error(_)));
+        assert!(results[0].tokens.is_empty());
     }
 }
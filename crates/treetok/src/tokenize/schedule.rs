@@ -0,0 +1,414 @@
+//! Rate-limit aware scheduling for API requests.
+//!
+//! [`Scheduler`] caps in-flight requests with an AIMD window: each success
+//! grows the window by `1 / window` (about one slot per window's worth of
+//! successes) up to the configured maximum, and each throttle halves it.  A
+//! throttle also pauses *every* request until the server's `retry-after`
+//! (or the exhausted bucket's `anthropic-ratelimit-*-reset`) has passed, so
+//! concurrent requests back off together instead of hammering the limit.
+
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, SystemTime};
+
+use reqwest::header::HeaderMap;
+use serde::de::DeserializeOwned;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+use super::error::TokenizeError;
+
+/// Window size the scheduler starts from before it has seen any responses.
+const INITIAL_WINDOW: f64 = 4.0;
+/// First back-off step when the server gives no timing hint.
+const BASE_BACKOFF: Duration = Duration::from_secs(1);
+/// Longest back-off step when the server gives no timing hint.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// Upper bound of the jitter added to server-provided delays.
+const MAX_JITTER: Duration = Duration::from_millis(250);
+
+/// Statuses that mean "slow down" rather than "failed": rate limited, service
+/// unavailable (e.g. a model server still loading), and Anthropic's
+/// overloaded.
+const THROTTLE_STATUSES: [u16; 3] = [429, 503, 529];
+
+/// Rate-limit buckets Anthropic reports as `anthropic-ratelimit-<bucket>-*`.
+const BUCKETS: [&str; 4] = ["requests", "tokens", "input-tokens", "output-tokens"];
+
+/// Month names of an HTTP date, in order.
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Timing hints from a response's rate-limit headers.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(super) struct RateLimitHints {
+    /// `retry-after`: its seconds, or the time left until its HTTP date.
+    pub retry_after: Option<Duration>,
+    /// Latest `…-reset` among buckets whose `…-remaining` is zero.
+    pub exhausted_until: Option<SystemTime>,
+}
+
+impl RateLimitHints {
+    /// Extract hints from response headers.  Unparseable values are ignored.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let text = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+
+        let retry_after = text("retry-after").map(str::trim).and_then(|v| {
+            v.parse::<f64>().map_or_else(
+                |_| {
+                    parse_http_date(v)
+                        .map(|t| t.duration_since(SystemTime::now()).unwrap_or_default())
+                },
+                |secs| Duration::try_from_secs_f64(secs).ok(),
+            )
+        });
+
+        let exhausted_until = BUCKETS
+            .iter()
+            .filter(|bucket| {
+                text(&format!("anthropic-ratelimit-{bucket}-remaining"))
+                    .and_then(|v| v.trim().parse::<u64>().ok())
+                    == Some(0)
+            })
+            .filter_map(|bucket| text(&format!("anthropic-ratelimit-{bucket}-reset")))
+            .filter_map(|v| humantime::parse_rfc3339_weak(v.trim()).ok())
+            .max();
+
+        Self {
+            retry_after,
+            exhausted_until,
+        }
+    }
+
+    /// How long the server asked us to wait, if it said.
+    fn server_delay(&self) -> Option<Duration> {
+        let reset = self
+            .exhausted_until
+            .map(|t| t.duration_since(SystemTime::now()).unwrap_or_default());
+        match (self.retry_after, reset) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+/// Parse an HTTP date in its IMF-fixdate form, `Sun, 06 Nov 1994 08:49:37
+/// GMT` (RFC 9110 §5.6.7).  The obsolete RFC 850 and asctime forms, which
+/// servers must no longer send, are not recognised.
+fn parse_http_date(v: &str) -> Option<SystemTime> {
+    let (_weekday, rest) = v.split_once(", ")?;
+    let rest = rest.strip_suffix(" GMT")?;
+    let [day, month, year, time] = rest.split(' ').collect::<Vec<_>>()[..] else {
+        return None;
+    };
+    let month = MONTHS.iter().position(|m| *m == month)? + 1;
+    humantime::parse_rfc3339(&format!("{year}-{month:02}-{day}T{time}Z")).ok()
+}
+
+struct State {
+    /// Current AIMD window; fractional so increases can accumulate.
+    window: f64,
+    max: usize,
+    in_flight: usize,
+    /// No request starts before this instant.
+    paused_until: Option<Instant>,
+}
+
+impl State {
+    fn slots(&self) -> usize {
+        (self.window as usize).clamp(1, self.max)
+    }
+
+    fn paused_until(&self) -> Option<Instant> {
+        self.paused_until.filter(|&until| until > Instant::now())
+    }
+
+    /// Take a slot, or say why not: `Err(Some(t))` while paused until `t`,
+    /// `Err(None)` while the window is full.
+    fn admit(&mut self) -> Result<(), Option<Instant>> {
+        if let Some(until) = self.paused_until() {
+            return Err(Some(until));
+        }
+        if self.in_flight >= self.slots() {
+            return Err(None);
+        }
+        self.in_flight += 1;
+        Ok(())
+    }
+
+    /// Additive increase, plus a pause if a bucket is already empty.
+    fn grow(&mut self, hints: &RateLimitHints) {
+        self.window = (self.window + 1.0 / self.window).min(self.max as f64);
+        if let Some(reset) = hints.exhausted_until {
+            self.pause_for(reset.duration_since(SystemTime::now()).unwrap_or_default());
+        }
+    }
+
+    /// Multiplicative decrease, plus a pause of `delay`.
+    fn shrink(&mut self, delay: Duration) {
+        // Requests already in flight when the first 429 arrived will see one
+        // too; only the first of a burst shrinks the window.
+        if self.paused_until().is_none() {
+            self.window = (self.window / 2.0).max(1.0);
+        }
+        self.pause_for(delay);
+    }
+
+    /// Extend the shared pause to at least `wait` from now.
+    fn pause_for(&mut self, wait: Duration) {
+        let until = Instant::now() + wait;
+        self.paused_until = Some(self.paused_until.map_or(until, |u| u.max(until)));
+    }
+}
+
+/// Admission control shared by all requests of one tokenizer.
+pub(super) struct Scheduler {
+    state: Mutex<State>,
+    wake: Notify,
+}
+
+/// An in-flight slot; released on drop.
+pub(super) struct Permit<'a> {
+    scheduler: &'a Scheduler,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        self.scheduler.state().in_flight -= 1;
+        self.scheduler.wake.notify_waiters();
+    }
+}
+
+impl Scheduler {
+    /// A scheduler allowing at most `max` concurrent requests (minimum 1).
+    pub fn new(max: usize) -> Self {
+        let max = max.max(1);
+        Self {
+            state: Mutex::new(State {
+                window: INITIAL_WINDOW.min(max as f64),
+                max,
+                in_flight: 0,
+                paused_until: None,
+            }),
+            wake: Notify::new(),
+        }
+    }
+
+    /// The configured concurrency ceiling.
+    pub fn max(&self) -> usize {
+        self.state().max
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Wait for a free slot outside any back-off pause.
+    pub async fn acquire(&self) -> Permit<'_> {
+        loop {
+            // Register for wake-ups before checking, so a release between the
+            // check and the await is not missed.
+            let released = self.wake.notified();
+            let admitted = self.state().admit();
+            match admitted {
+                Ok(()) => return Permit { scheduler: self },
+                Err(Some(until)) => tokio::time::sleep_until(until).await,
+                Err(None) => released.await,
+            }
+        }
+    }
+
+    /// Record a successful response: grow the window, and pause if a
+    /// rate-limit bucket is already empty.
+    pub fn on_success(&self, hints: &RateLimitHints) {
+        self.state().grow(hints);
+    }
+
+    /// Record a throttled response (`429`/`529`) on retry `attempt` (0-based):
+    /// halve the window and pause everyone for the server's delay, or an
+    /// exponential back-off with jitter when it gave none.
+    pub fn on_throttle(&self, hints: &RateLimitHints, attempt: u32) {
+        let delay = hints.server_delay().map_or_else(
+            || backoff(attempt),
+            |d| d + MAX_JITTER.mul_f64(fastrand::f64()),
+        );
+        self.state().shrink(delay);
+    }
+
+    /// Send the request `build` makes under this scheduler and parse the
+    /// `200` response body as JSON.
+    ///
+    /// Throttled responses are retried after the server's delay (or a
+    /// jittered back-off) up to `max_retries` times; any other status is an
+    /// [`ApiError`](TokenizeError::ApiError).
+    pub async fn send<T: DeserializeOwned>(
+        &self,
+        max_retries: u32,
+        build: impl Fn() -> reqwest::RequestBuilder,
+    ) -> Result<T, TokenizeError> {
+        for attempt in 0..=max_retries {
+            let _permit = self.acquire().await;
+            let resp = build()
+                .send()
+                .await
+                .map_err(|e| TokenizeError::Network(e.to_string()))?;
+
+            let status = resp.status().as_u16();
+            let hints = RateLimitHints::from_headers(resp.headers());
+
+            if status == 200 {
+                self.on_success(&hints);
+                return resp
+                    .json()
+                    .await
+                    .map_err(|e| TokenizeError::Parse(e.to_string()));
+            } else if THROTTLE_STATUSES.contains(&status) {
+                self.on_throttle(&hints, attempt);
+            } else {
+                let body = resp.text().await.unwrap_or_default();
+                return Err(TokenizeError::ApiError { status, body });
+            }
+        }
+
+        Err(TokenizeError::RateLimitExceeded)
+    }
+}
+
+/// Exponential back-off for retry `attempt` with "equal jitter": half the
+/// step is fixed, half random, so retries spread out but still wait.
+fn backoff(attempt: u32) -> Duration {
+    let step = BASE_BACKOFF
+        .saturating_mul(1 << attempt.min(16))
+        .min(MAX_BACKOFF);
+    step / 2 + (step / 2).mul_f64(fastrand::f64())
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::FutureExt;
+    use reqwest::header::HeaderValue;
+    use rstest::rstest;
+
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(k, v)| (*k, HeaderValue::from_str(v).unwrap()))
+            .map(|(k, v)| (reqwest::header::HeaderName::from_static(k), v))
+            .collect()
+    }
+
+    fn window(s: &Scheduler) -> f64 {
+        s.state().window
+    }
+
+    #[rstest]
+    #[case::none(&[], None)]
+    #[case::seconds(&[("retry-after", "3")], Some(Duration::from_secs(3)))]
+    #[case::fractional(&[("retry-after", "0.5")], Some(Duration::from_millis(500)))]
+    #[case::garbage(&[("retry-after", "soon")], None)]
+    #[case::past_http_date(&[("retry-after", "Sun, 06 Nov 1994 08:49:37 GMT")], Some(Duration::ZERO))]
+    #[case::obsolete_http_date(&[("retry-after", "Sunday, 06-Nov-94 08:49:37 GMT")], None)]
+    fn retry_after_parses(
+        #[case] pairs: &[(&'static str, &str)],
+        #[case] expected: Option<Duration>,
+    ) {
+        assert_eq!(
+            RateLimitHints::from_headers(&headers(pairs)).retry_after,
+            expected
+        );
+    }
+
+    /// An HTTP-date `retry-after` waits until that time.
+    #[test]
+    fn retry_after_http_date_is_time_left() {
+        let hints = RateLimitHints::from_headers(&headers(&[(
+            "retry-after",
+            "Fri, 01 Jan 2100 00:00:00 GMT",
+        )]));
+        let expected = humantime::parse_rfc3339("2100-01-01T00:00:00Z")
+            .unwrap()
+            .duration_since(SystemTime::now())
+            .unwrap();
+        let delay = hints.retry_after.unwrap();
+        assert!(
+            expected.abs_diff(delay) < Duration::from_secs(5),
+            "{delay:?}"
+        );
+    }
+
+    #[test]
+    fn exhausted_bucket_reset_is_reported() {
+        let hints = RateLimitHints::from_headers(&headers(&[
+            ("anthropic-ratelimit-requests-remaining", "0"),
+            ("anthropic-ratelimit-requests-reset", "2030-01-01T00:00:30Z"),
+            // Not exhausted, so its later reset is ignored.
+            ("anthropic-ratelimit-tokens-remaining", "900"),
+            ("anthropic-ratelimit-tokens-reset", "2030-01-01T00:05:00Z"),
+        ]));
+        let expected = humantime::parse_rfc3339("2030-01-01T00:00:30Z").unwrap();
+        assert_eq!(hints.exhausted_until, Some(expected));
+    }
+
+    #[test]
+    fn window_grows_additively_up_to_max() {
+        let s = Scheduler::new(5);
+        assert!((window(&s) - 4.0).abs() < f64::EPSILON);
+        s.on_success(&RateLimitHints::default());
+        assert!((window(&s) - 4.25).abs() < f64::EPSILON);
+        for _ in 0..10 {
+            s.on_success(&RateLimitHints::default());
+        }
+        assert!((window(&s) - 5.0).abs() < f64::EPSILON, "capped at max");
+    }
+
+    #[test]
+    fn throttle_halves_window_once_per_pause() {
+        let s = Scheduler::new(20);
+        let hints = RateLimitHints {
+            retry_after: Some(Duration::from_secs(60)),
+            exhausted_until: None,
+        };
+        s.on_throttle(&hints, 0);
+        assert!((window(&s) - 2.0).abs() < f64::EPSILON);
+        // A second 429 from the same burst doesn't shrink it again.
+        s.on_throttle(&hints, 0);
+        assert!((window(&s) - 2.0).abs() < f64::EPSILON);
+        assert!(s.state().paused_until.unwrap() > Instant::now() + Duration::from_secs(59));
+    }
+
+    #[test]
+    fn window_never_drops_below_one() {
+        let s = Scheduler::new(1);
+        s.on_throttle(&RateLimitHints::default(), 0);
+        assert!((window(&s) - 1.0).abs() < f64::EPSILON);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn acquire_respects_window_and_release() {
+        let s = Scheduler::new(1);
+        let first = s.acquire().await;
+        assert!(s.acquire().now_or_never().is_none(), "second slot granted");
+        drop(first);
+        assert!(s.acquire().now_or_never().is_some(), "slot not released");
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn acquire_waits_out_pause() {
+        let s = Scheduler::new(4);
+        s.state().pause_for(Duration::from_secs(60));
+        assert!(s.acquire().now_or_never().is_none(), "ignored the pause");
+    }
+
+    #[rstest]
+    #[case(0, 500, 1_000)]
+    #[case(2, 2_000, 4_000)]
+    #[case(10, 15_000, 30_000)]
+    fn backoff_is_jittered_within_step(#[case] attempt: u32, #[case] lo: u64, #[case] hi: u64) {
+        for _ in 0..20 {
+            let d = backoff(attempt).as_millis() as u64;
+            assert!((lo..=hi).contains(&d), "attempt {attempt}: {d}ms");
+        }
+    }
+}
@@ -0,0 +1,230 @@
+//! Tokenizers served by self-hosted inference servers (`-t server:URL`).
+//!
+//! llama.cpp, vLLM, and TGI all expose `POST /tokenize`, but each expects a
+//! different request body and answers in a different shape.  The protocol is
+//! detected once per server by probing with a one-token text, then every
+//! file is sent in that protocol through the same [`Scheduler`] and retry
+//! policy as the Claude API.  Any tokens the probe's count has beyond its
+//! one are special tokens the server adds to every text (TGI's BOS), and are
+//! subtracted from each count.
+
+use futures::future::LocalBoxFuture;
+use futures::stream::{self, StreamExt};
+use serde_json::{Value, json};
+use tokio::sync::OnceCell;
+
+use super::TokenizerId;
+use super::error::TokenizeError;
+use super::remote::{DEFAULT_CONCURRENCY, DEFAULT_MAX_RETRIES};
+use super::run::Blocking;
+use super::schedule::Scheduler;
+use super::tokenizer::{Counted, Tokenizer};
+
+/// Endpoint path appended to the server root.
+const TOKENIZE_PATH: &str = "/tokenize";
+/// Text used to detect the protocol; every vocabulary encodes it as exactly
+/// one token, so an empty answer means the body was not understood and any
+/// extra tokens are specials the server added.
+const PROTOCOL_PROBE: &str = "a";
+
+/// The request/response dialect of a server's tokenize endpoint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Protocol {
+    /// `{"content": …}` → `{"tokens": [...]}`.
+    LlamaCpp,
+    /// `{"prompt": …}` → `{"count": n, "tokens": [...]}`.
+    Vllm,
+    /// `{"inputs": …}` → `[{"id": …}, ...]`.
+    Tgi,
+}
+
+impl Protocol {
+    /// Detection order.  llama.cpp comes first because it answers bodies
+    /// without `content` with an empty token list instead of an error.
+    const ALL: [Self; 3] = [Self::LlamaCpp, Self::Vllm, Self::Tgi];
+
+    /// The request body for `content`, without BOS/EOS where the server
+    /// lets us choose, so counts match the content alone.  TGI does not, so
+    /// its specials are measured by the probe instead.
+    fn body(self, content: &str) -> Value {
+        match self {
+            Self::LlamaCpp => json!({ "content": content }),
+            Self::Vllm => json!({ "prompt": content, "add_special_tokens": false }),
+            Self::Tgi => json!({ "inputs": content }),
+        }
+    }
+}
+
+/// The token count in a tokenize response of any supported shape.
+fn parse_count(response: &Value) -> Result<usize, TokenizeError> {
+    let count = match response {
+        Value::Array(tokens) => Some(tokens.len()),
+        Value::Object(obj) => obj
+            .get("count")
+            .and_then(Value::as_u64)
+            .and_then(|n| usize::try_from(n).ok())
+            .or_else(|| obj.get("tokens").and_then(Value::as_array).map(Vec::len)),
+        _ => None,
+    };
+    count.ok_or_else(|| TokenizeError::Parse(format!("no token list in {response}")))
+}
+
+/// Remote tokenizer backed by an inference server's tokenize endpoint.
+pub struct ServerTokenizer {
+    id: TokenizerId,
+    /// Server root as given, for messages.
+    base: String,
+    /// Full tokenize endpoint URL.
+    url: String,
+    client: reqwest::Client,
+    /// Detected on first use, with the number of special tokens the server
+    /// adds to every text; a failed detection is remembered too, so a down
+    /// server costs one probe rather than one per file.
+    protocol: OnceCell<Result<(Protocol, usize), TokenizeError>>,
+    scheduler: Scheduler,
+    max_retries: u32,
+    /// Drives [`count_tokens`](Tokenizer::count_tokens) calls.
+    runtime: Blocking,
+}
+
+impl ServerTokenizer {
+    /// A tokenizer reporting under `id` for the server rooted at `base`.
+    /// A `base` that already ends in `/tokenize` is used as is.
+    #[must_use]
+    pub fn new(id: TokenizerId, base: &str) -> Self {
+        let root = base.trim_end_matches('/');
+        let url = if root.ends_with(TOKENIZE_PATH) {
+            root.to_string()
+        } else {
+            format!("{root}{TOKENIZE_PATH}")
+        };
+        Self {
+            id,
+            base: base.to_string(),
+            url,
+            client: reqwest::Client::new(),
+            protocol: OnceCell::new(),
+            scheduler: Scheduler::new(DEFAULT_CONCURRENCY),
+            max_retries: DEFAULT_MAX_RETRIES,
+            runtime: Blocking::default(),
+        }
+    }
+
+    /// Builder: allow at most `n` requests in flight.
+    #[must_use]
+    pub fn with_concurrency(mut self, n: usize) -> Self {
+        self.scheduler = Scheduler::new(n);
+        self
+    }
+
+    /// Builder: retry a throttled (`429`/`503`) request up to `n` times.
+    #[must_use]
+    pub fn with_max_retries(mut self, n: u32) -> Self {
+        self.max_retries = n;
+        self
+    }
+
+    /// Count `content` in `protocol`, special tokens included.
+    async fn count_in(&self, protocol: Protocol, content: &str) -> Result<usize, TokenizeError> {
+        let body = protocol.body(content);
+        let response: Value = self
+            .scheduler
+            .send(self.max_retries, || self.client.post(&self.url).json(&body))
+            .await?;
+        parse_count(&response)
+    }
+
+    /// The server's protocol and added special tokens, detected on first
+    /// call.
+    async fn protocol(&self) -> Result<(Protocol, usize), TokenizeError> {
+        self.protocol.get_or_init(|| self.detect()).await.clone()
+    }
+
+    /// Probe each protocol in turn; the first to return a non-empty count
+    /// wins, and the count beyond the probe's one token is the specials.
+    /// Transport failures end the search, since no body will help.
+    async fn detect(&self) -> Result<(Protocol, usize), TokenizeError> {
+        for protocol in Protocol::ALL {
+            match self.count_in(protocol, PROTOCOL_PROBE).await {
+                Ok(n) if n > 0 => return Ok((protocol, n - 1)),
+                Err(e @ (TokenizeError::Network(_) | TokenizeError::RateLimitExceeded)) => {
+                    return Err(e);
+                }
+                _ => {}
+            }
+        }
+        Err(TokenizeError::UnknownServer {
+            url: self.base.clone(),
+        })
+    }
+
+    /// Count `content` alone, without the server's added specials.
+    async fn count_content(&self, content: &str) -> Result<usize, TokenizeError> {
+        let (protocol, specials) = self.protocol().await?;
+        let n = self.count_in(protocol, content).await?;
+        Ok(n.saturating_sub(specials))
+    }
+
+    async fn count_all(&self, contents: &[&str]) -> Vec<Result<Counted, TokenizeError>> {
+        if let Err(e) = self.protocol().await {
+            return vec![Err(e); contents.len()];
+        }
+        stream::iter(contents)
+            .map(|content| async move { self.count_content(content).await.map(Counted::raw) })
+            // The scheduler decides how many actually run.
+            .buffered(self.scheduler.max())
+            .collect()
+            .await
+    }
+}
+
+impl Tokenizer for ServerTokenizer {
+    fn id(&self) -> TokenizerId {
+        self.id.clone()
+    }
+
+    fn count_tokens(&self, content: &str) -> Result<usize, TokenizeError> {
+        self.runtime.block_on(self.count_content(content))?
+    }
+
+    fn count_many<'a>(
+        &'a self,
+        contents: &'a [&'a str],
+    ) -> LocalBoxFuture<'a, Vec<Result<Counted, TokenizeError>>> {
+        Box::pin(self.count_all(contents))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case::llama_cpp(json!({ "tokens": [1, 2, 3] }), 3)]
+    #[case::vllm(json!({ "count": 4, "max_model_len": 8192, "tokens": [1, 2, 3, 4] }), 4)]
+    #[case::tgi(json!([{ "id": 1, "text": "a" }, { "id": 2, "text": "b" }]), 2)]
+    fn parse_count_accepts_each_shape(#[case] response: Value, #[case] expected: usize) {
+        assert_eq!(parse_count(&response).unwrap(), expected);
+    }
+
+    #[test]
+    fn parse_count_rejects_unknown_shape() {
+        assert!(matches!(
+            parse_count(&json!({ "error": "nope" })),
+            Err(TokenizeError::Parse(_))
+        ));
+    }
+
+    #[rstest]
+    #[case::root("http://localhost:8080", "http://localhost:8080/tokenize")]
+    #[case::trailing_slash("http://localhost:8080/", "http://localhost:8080/tokenize")]
+    #[case::full_endpoint("http://h/v1/tokenize", "http://h/v1/tokenize")]
+    fn endpoint_url(#[case] base: &str, #[case] url: &str) {
+        assert_eq!(
+            ServerTokenizer::new(TokenizerId::custom("s"), base).url,
+            url
+        );
+    }
+}
@@ -0,0 +1,208 @@
+use std::path::{Path, PathBuf};
+
+use super::TokenizerId;
+use super::error::TokenizeError;
+
+/// A parsed `-t` argument.
+pub(super) enum TokenizerSpec {
+    /// A built-in tokenizer selected by name (`o200k`, `claude`, …).
+    Builtin(TokenizerId),
+    /// A `HuggingFace` `tokenizer.json` on disk (`hf:PATH` / `NAME=hf:PATH`).
+    HuggingFace {
+        /// Column label (`TokenizerId::Custom`).
+        id: TokenizerId,
+        /// Path to the `tokenizer.json`.
+        path: PathBuf,
+    },
+    /// A tiktoken-format rank file (`tiktoken:PATH` / `NAME=tiktoken:PATH`).
+    Tiktoken {
+        /// Column label (`TokenizerId::Custom`).
+        id: TokenizerId,
+        /// Path to the `.tiktoken` file.
+        path: PathBuf,
+    },
+    /// An inference server's tokenize endpoint (`server:URL` / `NAME=server:URL`).
+    Server {
+        /// Column label (`TokenizerId::Custom`).
+        id: TokenizerId,
+        /// Server root, e.g. `http://localhost:8080`.
+        url: String,
+    },
+}
+
+/// File-backed spec schemes, in the order they are tried.
+const FILE_SCHEMES: &[&str] = &["hf:", "tiktoken:"];
+/// Scheme of inference-server specs.
+const SERVER_SCHEME: &str = "server:";
+
+impl TokenizerSpec {
+    /// Parse a `-t` value.
+    ///
+    /// Returns `Ok(None)` for an unrecognised plain name (callers skip it with
+    /// a warning, as before); malformed file-backed specs are errors.
+    pub(super) fn parse(arg: &str) -> Result<Option<Self>, TokenizeError> {
+        // `NAME=scheme:…` — the alias may not itself look like a scheme or path.
+        let (alias, spec) = match arg.split_once('=') {
+            Some((name, rest)) if !name.contains([':', '/']) => (Some(name), rest),
+            _ => (None, arg),
+        };
+
+        if let Some(url) = spec.strip_prefix(SERVER_SCHEME) {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err(TokenizeError::InvalidSpec(format!(
+                    "{arg:?}: server URL must start with http:// or https://"
+                )));
+            }
+            let label = alias.map_or_else(|| server_label(url), str::to_string);
+            return Ok(Some(Self::Server {
+                id: custom_id(&label)?,
+                url: url.to_string(),
+            }));
+        }
+
+        for &scheme in FILE_SCHEMES {
+            let Some(path) = spec.strip_prefix(scheme) else {
+                continue;
+            };
+            if path.is_empty() {
+                return Err(TokenizeError::InvalidSpec(format!(
+                    "{arg:?}: missing file path"
+                )));
+            }
+            let path = PathBuf::from(path);
+            let label = alias.map_or_else(|| default_label(&path), str::to_string);
+            let id = custom_id(&label)?;
+            return Ok(Some(if scheme == "hf:" {
+                Self::HuggingFace { id, path }
+            } else {
+                Self::Tiktoken { id, path }
+            }));
+        }
+
+        if alias.is_some() {
+            return Err(TokenizeError::InvalidSpec(format!(
+                "{arg:?}: only hf:PATH, tiktoken:PATH, and server:URL tokenizers can be named"
+            )));
+        }
+        Ok(spec.parse::<TokenizerId>().ok().map(Self::Builtin))
+    }
+}
+
+/// Build a custom id, rejecting labels that would shadow a built-in column.
+fn custom_id(label: &str) -> Result<TokenizerId, TokenizeError> {
+    if label.is_empty() || label.parse::<TokenizerId>().is_ok() {
+        return Err(TokenizeError::InvalidSpec(format!(
+            "{label:?} cannot be used as a tokenizer name"
+        )));
+    }
+    Ok(TokenizerId::custom(label))
+}
+
+/// Column label for an unnamed server spec: the URL's `host:port`.
+fn server_label(url: &str) -> String {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    rest.split(['/', '?', '#'])
+        .next()
+        .unwrap_or(rest)
+        .to_string()
+}
+
+/// Column label for an unnamed file-backed spec: the model directory for a
+/// conventionally named file (`tokenizer.json`, `tiktoken.model`), otherwise
+/// the file stem.
+fn default_label(path: &Path) -> String {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    if !matches!(stem.as_str(), "tokenizer" | "tiktoken") {
+        return stem;
+    }
+    path.parent()
+        .and_then(Path::file_name)
+        .map_or(stem, |dir| dir.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn hf_parts(arg: &str) -> (String, PathBuf) {
+        match TokenizerSpec::parse(arg).unwrap() {
+            Some(TokenizerSpec::HuggingFace { id, path }) => (id.as_str().to_string(), path),
+            _ => panic!("expected an hf spec for {arg:?}"),
+        }
+    }
+
+    fn tiktoken_label(arg: &str) -> String {
+        match TokenizerSpec::parse(arg).unwrap() {
+            Some(TokenizerSpec::Tiktoken { id, .. }) => id.as_str().to_string(),
+            _ => panic!("expected a tiktoken spec for {arg:?}"),
+        }
+    }
+
+    #[rstest]
+    #[case::model_dir("hf:/models/Qwen2.5-7B/tokenizer.json", "Qwen2.5-7B")]
+    #[case::file_stem("hf:/models/glm4.json", "glm4")]
+    #[case::alias("qwen=hf:/models/Qwen2.5-7B/tokenizer.json", "qwen")]
+    #[case::equals_in_path("hf:/models/a=b/tokenizer.json", "a=b")]
+    fn hf_spec_labels(#[case] arg: &str, #[case] label: &str) {
+        assert_eq!(hf_parts(arg).0, label);
+    }
+
+    #[test]
+    fn hf_spec_keeps_path() {
+        let (_, path) = hf_parts("llama=hf:rel/tokenizer.json");
+        assert_eq!(path, PathBuf::from("rel/tokenizer.json"));
+    }
+
+    #[rstest]
+    #[case::model_dir("tiktoken:/models/Kimi-K2/tiktoken.model", "Kimi-K2")]
+    #[case::file_stem("tiktoken:/ranks/inhouse.tiktoken", "inhouse")]
+    #[case::alias("kimi=tiktoken:/models/Kimi-K2/tiktoken.model", "kimi")]
+    fn tiktoken_spec_labels(#[case] arg: &str, #[case] label: &str) {
+        assert_eq!(tiktoken_label(arg), label);
+    }
+
+    #[rstest]
+    #[case::host_port("server:http://localhost:8080", "localhost:8080")]
+    #[case::path("server:https://gpu01.internal/vllm/", "gpu01.internal")]
+    #[case::alias("qwen=server:http://localhost:8000", "qwen")]
+    fn server_spec_labels(#[case] arg: &str, #[case] label: &str) {
+        match TokenizerSpec::parse(arg).unwrap() {
+            Some(TokenizerSpec::Server { id, .. }) => assert_eq!(id.as_str(), label),
+            _ => panic!("expected a server spec for {arg:?}"),
+        }
+    }
+
+    #[rstest]
+    #[case::builtin("o200k", TokenizerId::O200k)]
+    #[case::gemini("gemini", TokenizerId::Gemini)]
+    fn builtin_names_parse(#[case] arg: &str, #[case] id: TokenizerId) {
+        assert!(matches!(
+            TokenizerSpec::parse(arg).unwrap(),
+            Some(TokenizerSpec::Builtin(got)) if got == id
+        ));
+    }
+
+    #[test]
+    fn unknown_plain_name_is_none() {
+        assert!(TokenizerSpec::parse("nope").unwrap().is_none());
+    }
+
+    #[rstest]
+    #[case::missing_path("hf:")]
+    #[case::missing_tiktoken_path("tiktoken:")]
+    #[case::reserved_alias("o200k=hf:/m/tokenizer.json")]
+    #[case::empty_alias("=hf:/m/tokenizer.json")]
+    #[case::alias_on_builtin("x=o200k")]
+    #[case::missing_url("server:")]
+    #[case::no_http_scheme("server:localhost:8080")]
+    fn malformed_specs_are_errors(#[case] arg: &str) {
+        assert!(matches!(
+            TokenizerSpec::parse(arg),
+            Err(TokenizeError::InvalidSpec(_))
+        ));
+    }
+}
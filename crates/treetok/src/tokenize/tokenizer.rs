@@ -0,0 +1,105 @@
+use futures::future::{self, LocalBoxFuture};
+
+use super::TokenizerId;
+use super::error::TokenizeError;
+use crate::pdf::PdfInfo;
+
+/// One file's result from [`Tokenizer::count_many`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Counted {
+    /// Tokens in the content itself.
+    pub tokens: usize,
+    /// The count as an API reported it, request envelope included; `None`
+    /// for tokenizers that count the content directly.
+    pub wrapped: Option<usize>,
+}
+
+impl Counted {
+    /// A count with no envelope.
+    #[must_use]
+    pub const fn raw(tokens: usize) -> Self {
+        Self {
+            tokens,
+            wrapped: None,
+        }
+    }
+}
+
+/// Common interface for token counters, local (offline) and remote (API).
+///
+/// Local tokenizers implement [`count_tokens`](Self::count_tokens) and
+/// inherit a batch [`count_many`](Self::count_many) that calls it per file.
+/// Remote tokenizers override `count_many` to batch, cache, and schedule
+/// their requests; [`tokenize_entries`](super::tokenize_entries) drives every
+/// tokenizer through it.
+pub trait Tokenizer {
+    /// Stable identifier for this tokenizer.
+    fn id(&self) -> TokenizerId;
+
+    /// Count the tokens in `content`, blocking until done.
+    fn count_tokens(&self, content: &str) -> Result<usize, TokenizeError>;
+
+    /// Count every item of `contents`, returning one result per item in the
+    /// same order.
+    fn count_many<'a>(
+        &'a self,
+        contents: &'a [&'a str],
+    ) -> LocalBoxFuture<'a, Vec<Result<Counted, TokenizeError>>> {
+        let counts = contents
+            .iter()
+            .map(|content| self.count_tokens(content).map(Counted::raw))
+            .collect();
+        Box::pin(future::ready(counts))
+    }
+
+    /// Tokens a `width × height` image costs the models this tokenizer
+    /// stands for, or `None` if they take no images.
+    fn count_image(&self, _width: u32, _height: u32) -> Option<usize> {
+        None
+    }
+
+    /// Estimate a PDF's tokens from its extracted text's count.  Models that
+    /// see each page as text plus an image get one page-sized
+    /// [`count_image`](Self::count_image) per page on top; tokenizers without
+    /// an image formula count the text alone.
+    fn count_pdf(&self, text_tokens: usize, pdf: &PdfInfo) -> usize {
+        let (width, height) = pdf.page_pixels();
+        let per_page = self.count_image(width, height).unwrap_or(0);
+        text_tokens + per_page * pdf.pages
+    }
+
+    /// Whether [`count_pdf`](Self::count_pdf) uses its `text_tokens`.  If
+    /// not, a PDF's extracted text is never counted.
+    fn counts_pdf_text(&self) -> bool {
+        true
+    }
+
+    /// Whether this tokenizer counts whole PDFs with
+    /// [`count_documents`](Self::count_documents).  The raw bytes are only
+    /// read when some active tokenizer does.
+    fn reads_documents(&self) -> bool {
+        false
+    }
+
+    /// Count whole PDF files natively, e.g. as API `document` blocks, one
+    /// result per file in the same order; `None` (the default) estimates
+    /// them with [`count_pdf`](Self::count_pdf) instead.  Only called when
+    /// [`reads_documents`](Self::reads_documents) is `true`.
+    fn count_documents<'a>(
+        &'a self,
+        _documents: &'a [&'a [u8]],
+    ) -> Option<LocalBoxFuture<'a, Vec<Result<Counted, TokenizeError>>>> {
+        None
+    }
+
+    /// Returns `true` if this tokenizer produces approximate counts.
+    fn is_approximate(&self) -> bool {
+        false
+    }
+
+    /// An offline tokenizer whose estimate stands in when a count fails
+    /// (e.g. an API error), or `None` to leave the file without a count.
+    fn fallback(&self) -> Option<Box<dyn Tokenizer>> {
+        None
+    }
+}
@@ -1,20 +1,47 @@
 //! Directory walking and file content loading.
 
+use std::borrow::Cow;
+use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::encoding::Encoding;
+use crate::image::{ImageFormat, ImageInfo};
+use crate::pdf::{self, PdfInfo};
 
 /// Classification of a file's content type.
 #[derive(Debug, Clone)]
 pub enum FileKind {
-    /// Valid UTF-8 text — will be tokenized.
-    Text,
-    /// Non-UTF-8 binary content — shown as `[binary]`.
+    /// Text in the detected encoding, transcoded to UTF-8 — will be
+    /// tokenized.
+    Text(Encoding),
+    /// Text above 3 MB — tokenized in chunks read at count time, so it is
+    /// never held in memory whole.
+    Chunked(Encoding),
+    /// PNG, JPEG, GIF, or WebP image — counted from its dimensions.
+    Image(ImageInfo),
+    /// PDF document — counted from its extracted text and page count.
+    Pdf(PdfInfo),
+    /// Content in no recognised text encoding — shown as `[binary]`.
     Binary,
-    /// File exceeds the 3 MB size limit — shown as `[too large]`.
+    /// File exceeds `--max-size` (or 32 MB for PDFs) — shown as
+    /// `[too large]`.
     TooLarge,
     /// Could not be read — shown as `[error]`.
     Error(String),
 }
 
+impl FileKind {
+    /// Whether files of this kind get token counts.
+    #[must_use]
+    pub const fn is_counted(&self) -> bool {
+        matches!(
+            self,
+            Self::Text(_) | Self::Chunked(_) | Self::Image(_) | Self::Pdf(_)
+        )
+    }
+}
+
 /// A single file discovered during a directory walk.
 #[derive(Debug, Clone)]
 pub struct FileEntry {
@@ -24,18 +51,144 @@ pub struct FileEntry {
     pub rel_path: PathBuf,
     /// Content classification.
     pub kind: FileKind,
-    /// Full UTF-8 content, populated only for [`FileKind::Text`].
+    /// Full content as UTF-8, populated only for [`FileKind::Text`] or the
+    /// extracted text of a [`FileKind::Pdf`].
     pub content: Option<String>,
+    /// Raw bytes already read from stdin, which cannot be read again at
+    /// count time: a whole [`FileKind::Pdf`], or the start of a
+    /// [`FileKind::Chunked`] stream.  Files on disk leave this empty; see
+    /// [`document`](Self::document).
+    pub document: Option<Vec<u8>>,
+}
+
+impl FileEntry {
+    /// A PDF's raw bytes for tokenizers that read documents natively: from
+    /// `document` when stdin supplied them, otherwise read from `path`.
+    pub fn document(&self) -> std::io::Result<Cow<'_, [u8]>> {
+        self.document.as_deref().map_or_else(
+            || std::fs::read(&self.path).map(Cow::Owned),
+            |data| Ok(Cow::Borrowed(data)),
+        )
+    }
+
+    /// Read a [`FileKind::Chunked`] entry's text in chunks: from `content`
+    /// when given, from the bytes read ahead and the rest of stdin for
+    /// stdin, otherwise from `path`.
+    pub fn chunks(&self) -> std::io::Result<Chunks<Box<dyn Read + '_>>> {
+        Ok(match (&self.content, &self.document, &self.kind) {
+            (Some(content), ..) => Chunks::new(Box::new(content.as_bytes()), Encoding::Utf8),
+            (None, Some(head), FileKind::Chunked(encoding)) => stream_chunks(
+                head,
+                std::io::stdin().lock(),
+                STDIN_MAX_SIZE.load(Ordering::Relaxed),
+                *encoding,
+            ),
+            (None, None, FileKind::Chunked(encoding)) => {
+                Chunks::new(Box::new(std::fs::File::open(&self.path)?), *encoding)
+            }
+            (None, ..) => Chunks::new(Box::new(std::io::empty()), Encoding::Utf8),
+        })
+    }
 }
 
 /// Options controlling the directory walk.
 pub struct WalkOptions {
     /// Disable `.gitignore` / `.ignore` filtering when `true`.
     pub no_ignore: bool,
-    /// Maximum depth to descend (`None` = unlimited).
-    pub depth: Option<usize>,
+    /// Files larger than this many bytes are `[too large]` (`u64::MAX` =
+    /// unlimited).  Images are exempt.
+    pub max_size: u64,
+    /// Glob and file-type filters.
+    pub filter: Filter,
+}
+
+/// Glob and file-type filters, applied as the walk visits each path so
+/// that filtered-out files are never opened.
+#[derive(Clone, Default)]
+pub struct Filter {
+    /// Override globs: includes as given, excludes prefixed with `!`.
+    globs: Vec<String>,
+    /// Selected and negated file types, or `None` when neither was given.
+    types: Option<ignore::types::Types>,
+}
+
+impl Filter {
+    /// Build a filter from `--include` / `--exclude` globs (gitignore
+    /// syntax, relative to each walk root) and `--type` / `--type-not`
+    /// names (ripgrep's built-in type definitions).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FilterError`] for a malformed glob or an unknown type name.
+    pub fn new(
+        include: &[String],
+        exclude: &[String],
+        types: &[String],
+        types_not: &[String],
+    ) -> Result<Self, FilterError> {
+        let globs: Vec<String> = include
+            .iter()
+            .cloned()
+            .chain(exclude.iter().map(|glob| format!("!{glob}")))
+            .collect();
+        // Parse the globs once up front so a bad one is reported before
+        // anything is walked.
+        build_overrides(Path::new(""), &globs)?;
+
+        let types = if types.is_empty() && types_not.is_empty() {
+            None
+        } else {
+            let mut builder = ignore::types::TypesBuilder::new();
+            builder.add_defaults();
+            for name in types {
+                builder.select(name);
+            }
+            for name in types_not {
+                builder.negate(name);
+            }
+            Some(builder.build()?)
+        };
+
+        Ok(Self { globs, types })
+    }
+
+    /// Apply the filter to a walk rooted at `root`.
+    ///
+    /// The globs go through `filter_entry` rather than `overrides`: an
+    /// override whitelist would take precedence over `.gitignore` and the
+    /// type filter, while every filter here should narrow the walk.
+    fn apply(&self, builder: &mut ignore::WalkBuilder, root: &Path) -> Result<(), ignore::Error> {
+        if !self.globs.is_empty() {
+            let overrides = build_overrides(root, &self.globs)?;
+            builder.filter_entry(move |entry| {
+                let is_dir = entry.file_type().is_some_and(|ft| ft.is_dir());
+                !overrides.matched(entry.path(), is_dir).is_ignore()
+            });
+        }
+        if let Some(types) = &self.types {
+            builder.types(types.clone());
+        }
+        Ok(())
+    }
+}
+
+fn build_overrides(
+    root: &Path,
+    globs: &[String],
+) -> Result<ignore::overrides::Override, ignore::Error> {
+    let mut builder = ignore::overrides::OverrideBuilder::new(root);
+    for glob in globs {
+        builder.add(glob)?;
+    }
+    builder.build()
 }
 
+/// An invalid `--include` / `--exclude` glob or `--type` name.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+#[error("invalid file filter")]
+#[diagnostic(code(treetok::walk::filter))]
+pub struct FilterError(#[from] ignore::Error);
+
 /// Walk each path in `roots` and return one [`WalkResult`] per root.
 #[must_use]
 pub fn walk_paths(roots: &[PathBuf], opts: &WalkOptions) -> Vec<WalkResult> {
@@ -106,14 +259,21 @@ fn walk_one(root: &Path, opts: &WalkOptions) -> WalkResult {
             .git_exclude(false);
     }
 
-    if let Some(depth) = opts.depth {
-        builder.max_depth(Some(depth));
+    if let Err(e) = opts.filter.apply(&mut builder, root) {
+        return WalkResult {
+            root: root.to_path_buf(),
+            entries: Vec::new(),
+            errors: vec![WalkError {
+                message: e.to_string(),
+            }],
+        };
     }
 
     let (tx, rx) = std::sync::mpsc::channel();
     builder.build_parallel().run(|| {
         let tx = tx.clone();
         let root = root.to_path_buf();
+        let max_size = opts.max_size;
         Box::new(move |result| {
             match result {
                 Ok(dir_entry) => {
@@ -125,7 +285,7 @@ fn walk_one(root: &Path, opts: &WalkOptions) -> WalkResult {
                         .strip_prefix(&root)
                         .unwrap_or(&abs_path)
                         .to_path_buf();
-                    let _ = tx.send(Ok(process_file(abs_path, rel_path)));
+                    let _ = tx.send(Ok(process_file(abs_path, rel_path, max_size)));
                 }
                 Err(e) => {
                     let _ = tx.send(Err(WalkError {
@@ -160,62 +320,114 @@ fn walk_one(root: &Path, opts: &WalkOptions) -> WalkResult {
 /// Label used for stdin input in paths and display.
 pub const STDIN_LABEL: &str = "<stdin>";
 
-/// Read all of stdin and return a single-entry [`WalkResult`] with root
+/// `--max-size` for a [`FileKind::Chunked`] stdin entry, whose rest is
+/// only read at count time.  Stdin is process-wide, and so is its limit.
+static STDIN_MAX_SIZE: AtomicU64 = AtomicU64::new(u64::MAX);
+
+/// Read stdin and return a single-entry [`WalkResult`] with root
 /// [`STDIN_LABEL`].
 ///
-/// Classification follows the same rules as file walking: UTF-8 validity and
-/// size check (>3 MB → `TooLarge`).  The read is capped at `MAX_FILE_SIZE + 1`
-/// bytes to avoid unbounded memory allocation.
-pub fn read_stdin() -> std::io::Result<WalkResult> {
-    use std::io::Read;
+/// Classification follows the same rules as file walking: image headers,
+/// PDFs, UTF-8 validity, and size check (> `max_size` → `TooLarge`).  At
+/// most 3 MB of text (32 MB for a PDF) is read here; longer text is
+/// [`FileKind::Chunked`] and the rest of stdin is streamed through the
+/// chunker at count time, so memory stays bounded.
+pub fn read_stdin(max_size: u64) -> std::io::Result<WalkResult> {
+    STDIN_MAX_SIZE.store(max_size, Ordering::Relaxed);
+    let entry = read_stream(&mut std::io::stdin().lock(), max_size)?;
+    Ok(WalkResult {
+        root: STDIN_LABEL.into(),
+        entries: vec![entry],
+        errors: vec![],
+    })
+}
 
+/// Classify the stream `reader` as the [`STDIN_LABEL`] entry, reading no
+/// more of it than [`read_stdin`] describes.
+fn read_stream(reader: &mut impl Read, max_size: u64) -> std::io::Result<FileEntry> {
     let mut buf = Vec::new();
-    std::io::stdin()
-        .lock()
-        .take(MAX_FILE_SIZE + 1)
+    reader
+        .take(max_size.min(MAX_WHOLE_SIZE).saturating_add(1))
         .read_to_end(&mut buf)?;
+    if pdf::is_pdf(&buf) {
+        let rest = max_size.min(MAX_PDF_SIZE).saturating_add(1);
+        reader
+            .take(rest.saturating_sub(buf.len() as u64))
+            .read_to_end(&mut buf)?;
+    }
 
     let label = PathBuf::from(STDIN_LABEL);
 
-    let entry = if buf.len() as u64 > MAX_FILE_SIZE {
+    let entry = if let Some(image) = ImageInfo::parse(&buf) {
+        FileEntry {
+            path: label.clone(),
+            rel_path: label,
+            kind: FileKind::Image(image),
+            content: None,
+            document: None,
+        }
+    } else if buf.len() as u64 > max_size || (pdf::is_pdf(&buf) && buf.len() as u64 > MAX_PDF_SIZE)
+    {
         FileEntry {
             path: label.clone(),
             rel_path: label,
             kind: FileKind::TooLarge,
             content: None,
+            document: None,
         }
+    } else if pdf::is_pdf(&buf) {
+        pdf_entry(label.clone(), label, buf, true)
     } else {
-        match String::from_utf8(buf) {
-            Ok(content) => FileEntry {
+        let sniff = &buf[..buf.len().min(SNIFF_BYTES)];
+        let encoding = Encoding::detect(sniff, is_valid_utf8_sniff(sniff));
+        match encoding {
+            // More may follow: keep what was read and stream the rest.
+            Some(encoding) if buf.len() as u64 > MAX_WHOLE_SIZE => FileEntry {
                 path: label.clone(),
                 rel_path: label,
-                kind: FileKind::Text,
-                content: Some(content),
+                kind: FileKind::Chunked(encoding),
+                content: None,
+                document: Some(buf),
             },
-            Err(_) => FileEntry {
+            Some(encoding) => {
+                let (content, encoding) = decode_whole(buf, encoding);
+                FileEntry {
+                    path: label.clone(),
+                    rel_path: label,
+                    kind: FileKind::Text(encoding),
+                    content: Some(content),
+                    document: None,
+                }
+            }
+            None => FileEntry {
                 path: label.clone(),
                 rel_path: label,
                 kind: FileKind::Binary,
                 content: None,
+                document: None,
             },
         }
     };
-
-    Ok(WalkResult {
-        root: STDIN_LABEL.into(),
-        entries: vec![entry],
-        errors: vec![],
-    })
+    Ok(entry)
 }
 
-/// Maximum file size we will read (3 MB).
-const MAX_FILE_SIZE: u64 = 3 * 1024 * 1024;
+/// Default for `--max-size`: the largest file counted (100 MB).
+pub const DEFAULT_MAX_SIZE: u64 = 100 * 1024 * 1024;
+/// Largest text file read whole (3 MB); bigger ones are [`FileKind::Chunked`].
+const MAX_WHOLE_SIZE: u64 = 3 * 1024 * 1024;
+/// Maximum PDF size we will read (32 MB, the most Claude accepts in one
+/// request).
+const MAX_PDF_SIZE: u64 = 32 * 1024 * 1024;
 /// Number of bytes read for UTF-8 sniffing.
 const SNIFF_BYTES: usize = 8 * 1024;
+/// Number of bytes searched for a JPEG frame header that the sniff missed
+/// (Exif and ICC segments can push it well past [`SNIFF_BYTES`]).
+const IMAGE_HEADER_BYTES: usize = 256 * 1024;
 
 /// Process a file, classifying its content and loading text if applicable.
+/// Files over `max_size` bytes are [`FileKind::TooLarge`].
 #[must_use]
-pub fn process_file(path: PathBuf, rel_path: PathBuf) -> FileEntry {
+pub fn process_file(path: PathBuf, rel_path: PathBuf, max_size: u64) -> FileEntry {
     // Stat the file first to check size without reading.
     let meta = match std::fs::metadata(&path) {
         Err(e) => {
@@ -224,59 +436,161 @@ pub fn process_file(path: PathBuf, rel_path: PathBuf) -> FileEntry {
                 rel_path,
                 kind: FileKind::Error(e.to_string()),
                 content: None,
+                document: None,
             };
         }
         Ok(m) => m,
     };
 
-    if meta.len() > MAX_FILE_SIZE {
+    // Sniff the first 8 KB for image headers and UTF-8 validity.
+    let sniff = match read_first_bytes(&path, SNIFF_BYTES) {
+        Err(e) => {
+            return FileEntry {
+                path,
+                rel_path,
+                kind: FileKind::Error(e.to_string()),
+                content: None,
+                document: None,
+            };
+        }
+        Ok(b) => b,
+    };
+
+    // Images are counted from their header alone, so size is no limit.
+    if let Some(image) = sniff_image(&path, &sniff) {
+        return FileEntry {
+            path,
+            rel_path,
+            kind: FileKind::Image(image),
+            content: None,
+            document: None,
+        };
+    }
+
+    if meta.len() > max_size || (pdf::is_pdf(&sniff) && meta.len() > MAX_PDF_SIZE) {
         return FileEntry {
             path,
             rel_path,
             kind: FileKind::TooLarge,
             content: None,
+            document: None,
         };
     }
 
-    // Sniff the first 8 KB for UTF-8 validity.
-    let sniff = match read_first_bytes(&path, SNIFF_BYTES) {
-        Err(e) => {
-            return FileEntry {
+    if pdf::is_pdf(&sniff) {
+        return match std::fs::read(&path) {
+            Ok(data) => pdf_entry(path, rel_path, data, false),
+            Err(e) => FileEntry {
                 path,
                 rel_path,
                 kind: FileKind::Error(e.to_string()),
                 content: None,
-            };
-        }
-        Ok(b) => b,
-    };
+                document: None,
+            },
+        };
+    }
 
-    if !is_valid_utf8_sniff(&sniff) {
+    let Some(encoding) = Encoding::detect(&sniff, is_valid_utf8_sniff(&sniff)) else {
         return FileEntry {
             path,
             rel_path,
             kind: FileKind::Binary,
             content: None,
+            document: None,
+        };
+    };
+
+    // Large text is streamed from disk when it is counted.
+    if meta.len() > MAX_WHOLE_SIZE {
+        return FileEntry {
+            path,
+            rel_path,
+            kind: FileKind::Chunked(encoding),
+            content: None,
+            document: None,
         };
     }
 
-    // Read the full file as a string.
-    match std::fs::read_to_string(&path) {
-        Ok(content) => FileEntry {
+    // Read the full file and transcode it.
+    match std::fs::read(&path).map(|bytes| decode_whole(bytes, encoding)) {
+        Ok((content, encoding)) => FileEntry {
             path,
             rel_path,
-            kind: FileKind::Text,
+            kind: FileKind::Text(encoding),
             content: Some(content),
+            document: None,
         },
         Err(e) => FileEntry {
             path,
             rel_path,
             kind: FileKind::Error(e.to_string()),
             content: None,
+            document: None,
         },
     }
 }
 
+/// Decode a whole text file, returning the encoding it was read as.
+///
+/// The sniff only checked the file's start, so UTF-8 may turn out invalid
+/// further in.  Without a BOM the file is then read as Latin-1 (Windows-1252)
+/// instead; with one, invalid sequences become U+FFFD.  Other encodings
+/// decode lossily.
+fn decode_whole(bytes: Vec<u8>, encoding: Encoding) -> (String, Encoding) {
+    match encoding {
+        Encoding::Utf8 | Encoding::Utf8Bom => match String::from_utf8(bytes) {
+            Ok(mut text) => {
+                if encoding == Encoding::Utf8Bom {
+                    text.drain(..'\u{feff}'.len_utf8());
+                }
+                (text, encoding)
+            }
+            Err(e) if encoding == Encoding::Utf8Bom => {
+                (encoding.decode_lossy(e.as_bytes()), encoding)
+            }
+            Err(e) => (
+                Encoding::Latin1.decode_lossy(e.as_bytes()),
+                Encoding::Latin1,
+            ),
+        },
+        _ => (encoding.decode_lossy(&bytes), encoding),
+    }
+}
+
+/// Classify `data`, which starts like a PDF: its extracted text when it
+/// parses, binary otherwise.  The raw bytes are kept only with `keep`.
+fn pdf_entry(path: PathBuf, rel_path: PathBuf, data: Vec<u8>, keep: bool) -> FileEntry {
+    match pdf::read(&data) {
+        Some((info, text)) => FileEntry {
+            path,
+            rel_path,
+            kind: FileKind::Pdf(info),
+            content: Some(text),
+            document: keep.then_some(data),
+        },
+        None => FileEntry {
+            path,
+            rel_path,
+            kind: FileKind::Binary,
+            content: None,
+            document: None,
+        },
+    }
+}
+
+/// Parse an image header from `sniff`, reading further into `path` when a
+/// JPEG's frame header lies beyond it.  Unparseable images are `None` and
+/// end up as binary.
+fn sniff_image(path: &Path, sniff: &[u8]) -> Option<ImageInfo> {
+    let format = ImageFormat::sniff(sniff)?;
+    ImageInfo::parse(sniff).or_else(|| {
+        let more = format == ImageFormat::Jpeg && sniff.len() == SNIFF_BYTES;
+        more.then(|| read_first_bytes(path, IMAGE_HEADER_BYTES).ok())
+            .flatten()
+            .and_then(|header| ImageInfo::parse(&header))
+    })
+}
+
 /// Returns `true` if `bytes` is valid UTF-8 or only has an incomplete
 /// multi-byte sequence at the very end (i.e. the sniff buffer was truncated
 /// mid-character).  Returns `false` for any genuinely invalid byte sequence.
@@ -288,7 +602,6 @@ fn is_valid_utf8_sniff(bytes: &[u8]) -> bool {
 }
 
 fn read_first_bytes(path: &Path, limit: usize) -> std::io::Result<Vec<u8>> {
-    use std::io::Read;
     let mut file = std::fs::File::open(path)?;
     let mut buf = vec![0u8; limit];
     let n = file.read(&mut buf)?;
@@ -296,6 +609,118 @@ fn read_first_bytes(path: &Path, limit: usize) -> std::io::Result<Vec<u8>> {
     Ok(buf)
 }
 
+/// Chunk `head`, the bytes of a stream already read, followed by the rest
+/// of it from `rest`; reading fails once the whole passes `max_size`.
+fn stream_chunks<'a>(
+    head: &'a [u8],
+    rest: impl Read + 'a,
+    max_size: u64,
+    encoding: Encoding,
+) -> Chunks<Box<dyn Read + 'a>> {
+    let rest = Capped {
+        inner: rest,
+        remaining: max_size.saturating_sub(head.len() as u64),
+    };
+    Chunks::new(Box::new(head.chain(rest)), encoding)
+}
+
+/// Reads `inner`, failing once more than `remaining` bytes have come
+/// through.
+struct Capped<R> {
+    inner: R,
+    remaining: u64,
+}
+
+impl<R: Read> Read for Capped<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.remaining = self.remaining.checked_sub(n as u64).ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "larger than --max-size")
+        })?;
+        Ok(n)
+    }
+}
+
+/// Most bytes in one chunk of a [`FileKind::Chunked`] file: about 64k
+/// tokens of English, small enough for one API request.
+pub const CHUNK_BYTES: usize = 256 * 1024;
+
+/// Text read from `R` in chunks of at most [`CHUNK_BYTES`].
+///
+/// Each chunk ends after its last line break, or on a character boundary
+/// when one line is longer than a chunk, so no token straddles a cut in
+/// ordinary text.
+///
+/// Each chunk is transcoded from `encoding`; malformed sequences are
+/// replaced rather than rejected, since the sniff has already classified
+/// the file as text.
+pub struct Chunks<R> {
+    reader: R,
+    encoding: Encoding,
+    buf: Vec<u8>,
+    eof: bool,
+}
+
+impl<R: Read> Chunks<R> {
+    /// Chunk the text in `encoding` read from `reader`.
+    pub const fn new(reader: R, encoding: Encoding) -> Self {
+        Self {
+            reader,
+            encoding,
+            buf: Vec::new(),
+            eof: false,
+        }
+    }
+}
+
+impl<R: Read> Iterator for Chunks<R> {
+    type Item = std::io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.eof && self.buf.len() < CHUNK_BYTES {
+            let start = self.buf.len();
+            self.buf.resize(CHUNK_BYTES, 0);
+            let read = self.reader.read(&mut self.buf[start..]);
+            self.buf.truncate(start + *read.as_ref().unwrap_or(&0));
+            match read {
+                Ok(0) => self.eof = true,
+                Ok(_) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.eof = true;
+                    self.buf.clear();
+                    return Some(Err(e));
+                }
+            }
+        }
+        if self.buf.is_empty() {
+            return None;
+        }
+        let cut = if self.eof {
+            self.buf.len()
+        } else {
+            chunk_end(&self.buf, self.encoding)
+        };
+        let rest = self.buf.split_off(cut);
+        let chunk = std::mem::replace(&mut self.buf, rest);
+        Some(Ok(self.encoding.decode_lossy(&chunk)))
+    }
+}
+
+/// Where to cut a full chunk buffer: after the last line break, else at the
+/// start of the last character.
+fn chunk_end(buf: &[u8], encoding: Encoding) -> usize {
+    (1..=buf.len())
+        .rev()
+        .find(|&at| encoding.ends_line(buf, at))
+        .or_else(|| {
+            (1..buf.len())
+                .rev()
+                .find(|&at| encoding.is_boundary(buf, at))
+        })
+        .unwrap_or(buf.len())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -353,22 +778,23 @@ mod tests {
     fn text_file_detected_as_text(#[case] content: &[u8]) {
         let dir = tempfile::tempdir().unwrap();
         let path = temp_file(dir.path(), "f", content);
-        let entry = process_file(path, "f".into());
+        let entry = process_file(path, "f".into(), DEFAULT_MAX_SIZE);
         assert!(
-            matches!(entry.kind, FileKind::Text),
+            matches!(entry.kind, FileKind::Text(Encoding::Utf8)),
             "expected Text, got {:?}",
             entry.kind
         );
     }
 
-    /// Non-UTF-8 bytes should produce `FileKind::Binary`.
+    /// Bytes in no text encoding should produce `FileKind::Binary`.
     #[rstest]
-    #[case(b"\xFF\xFE\x00\x01" as &[u8])]
+    #[case(b"\x00\x01\x02\x03" as &[u8])]
     #[case(b"\x80\x81\x82\x83" as &[u8])]
+    #[case(b"ELF\x7F\x02\x01\x01\x00\x00\x00\x00" as &[u8])]
     fn binary_file_detected_as_binary(#[case] content: &[u8]) {
         let dir = tempfile::tempdir().unwrap();
         let path = temp_file(dir.path(), "f.bin", content);
-        let entry = process_file(path, "f.bin".into());
+        let entry = process_file(path, "f.bin".into(), DEFAULT_MAX_SIZE);
         assert!(
             matches!(entry.kind, FileKind::Binary),
             "expected Binary, got {:?}",
@@ -376,12 +802,224 @@ mod tests {
         );
     }
 
+    /// UTF-16, BOM-prefixed UTF-8, and Latin-1 files are text, transcoded
+    /// to UTF-8 with the byte-order mark dropped.
+    #[rstest]
+    #[case::utf8_bom(b"\xEF\xBB\xBFid,name\n".to_vec(), Encoding::Utf8Bom)]
+    #[case::utf16le(
+        b"\xFF\xFEi\0d\0,\0n\0a\0m\0e\0\n\0".to_vec(),
+        Encoding::Utf16Le
+    )]
+    #[case::utf16be(
+        b"\0i\0d\0,\0n\0a\0m\0e\0\n".to_vec(),
+        Encoding::Utf16Be
+    )]
+    #[case::latin1(b"id,n\xE4me\n".to_vec(), Encoding::Latin1)]
+    fn encoded_text_is_transcoded(#[case] content: Vec<u8>, #[case] encoding: Encoding) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = temp_file(dir.path(), "f.csv", &content);
+        let entry = process_file(path, "f.csv".into(), DEFAULT_MAX_SIZE);
+        assert!(
+            matches!(entry.kind, FileKind::Text(found) if found == encoding),
+            "expected Text({encoding:?}), got {:?}",
+            entry.kind
+        );
+        let expected = if encoding == Encoding::Latin1 {
+            "id,n\u{e4}me\n"
+        } else {
+            "id,name\n"
+        };
+        assert_eq!(entry.content.as_deref(), Some(expected));
+    }
+
+    /// A file whose sniff window is plain ASCII but which turns invalid
+    /// UTF-8 further in is read as Latin-1 rather than reported as an error.
+    #[test]
+    fn late_non_utf8_byte_falls_back_to_latin1() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut content = b"x".repeat(SNIFF_BYTES + 1024);
+        content.push(0xE9);
+        let path = temp_file(dir.path(), "late.txt", &content);
+        let entry = process_file(path, "late.txt".into(), DEFAULT_MAX_SIZE);
+        assert!(
+            matches!(entry.kind, FileKind::Text(Encoding::Latin1)),
+            "expected Text(Latin1), got {:?}",
+            entry.kind
+        );
+        let text = entry.content.unwrap();
+        assert_eq!(text.len(), SNIFF_BYTES + 1024 + '\u{e9}'.len_utf8());
+        assert!(text.ends_with("x\u{e9}"));
+    }
+
+    /// UTF-16 chunks are cut after a line feed's full code unit and
+    /// decoded on their own.
+    #[test]
+    fn utf16_chunks_split_at_line_breaks() {
+        let text = "line\n".repeat(CHUNK_BYTES / 8);
+        let bytes: Vec<u8> = text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let chunks: Vec<String> = Chunks::new(bytes.as_slice(), Encoding::Utf16Le)
+            .collect::<std::io::Result<_>>()
+            .unwrap();
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|chunk| chunk.ends_with('\n')));
+        assert_eq!(chunks.concat(), text);
+    }
+
+    /// Stdin over 3 MB is read only that far ahead; the rest is streamed
+    /// through the chunker, which stops once the stream passes `max_size`.
+    #[rstest]
+    #[case::within_limit(DEFAULT_MAX_SIZE, true)]
+    #[case::over_limit(MAX_WHOLE_SIZE + CHUNK_BYTES as u64, false)]
+    fn long_stdin_is_streamed_in_chunks(#[case] max_size: u64, #[case] ok: bool) {
+        let text = "one two three\n".repeat(300_000);
+        let mut stream = std::io::Cursor::new(text.as_bytes());
+
+        let entry = read_stream(&mut stream, max_size).unwrap();
+
+        assert!(matches!(entry.kind, FileKind::Chunked(Encoding::Utf8)));
+        assert!(entry.content.is_none());
+        let head = entry.document.unwrap();
+        assert_eq!(head.len() as u64, MAX_WHOLE_SIZE + 1);
+        assert_eq!(stream.position(), MAX_WHOLE_SIZE + 1);
+        let chunks: std::io::Result<Vec<String>> =
+            stream_chunks(&head, stream, max_size, Encoding::Utf8).collect();
+        match chunks {
+            Ok(chunks) => {
+                assert!(ok);
+                assert_eq!(chunks.concat(), text);
+            }
+            Err(e) => {
+                assert!(!ok);
+                assert_eq!(e.to_string(), "larger than --max-size");
+            }
+        }
+    }
+
+    /// Short stdin is read whole, as text.
+    #[test]
+    fn short_stdin_is_read_whole() {
+        let entry = read_stream(&mut b"id,name\n".as_slice(), DEFAULT_MAX_SIZE).unwrap();
+        assert!(matches!(entry.kind, FileKind::Text(Encoding::Utf8)));
+        assert_eq!(entry.content.as_deref(), Some("id,name\n"));
+        assert!(entry.document.is_none());
+    }
+
+    /// Image headers are recognised regardless of extension or size.
+    #[test]
+    fn image_file_detected_as_image() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut png = crate::image::tests::png_header(1280, 720);
+        png.resize(usize::try_from(MAX_WHOLE_SIZE).unwrap() + 1, 0);
+        let path = temp_file(dir.path(), "shot.dat", &png);
+        let entry = process_file(path, "shot.dat".into(), 1_000);
+        assert!(
+            matches!(
+                entry.kind,
+                FileKind::Image(ImageInfo {
+                    width: 1280,
+                    height: 720,
+                    ..
+                })
+            ),
+            "expected Image, got {:?}",
+            entry.kind
+        );
+        assert!(entry.content.is_none());
+    }
+
+    /// A JPEG whose frame header follows a large Exif segment is still read.
+    #[test]
+    fn jpeg_frame_beyond_sniff_is_found() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut jpeg = vec![0xFF, 0xD8];
+        // Three maximal APP1 segments: ~192 KB of metadata.
+        for _ in 0..3 {
+            jpeg.extend_from_slice(&[0xFF, 0xE1, 0xFF, 0xFF]);
+            jpeg.resize(jpeg.len() + 0xFFFD, 0);
+        }
+        jpeg.extend_from_slice(&[0xFF, 0xC0, 0x00, 0x11, 0x08, 0x01, 0x00, 0x02, 0x00]);
+        let path = temp_file(dir.path(), "photo.jpg", &jpeg);
+        let entry = process_file(path, "photo.jpg".into(), DEFAULT_MAX_SIZE);
+        assert!(
+            matches!(
+                entry.kind,
+                FileKind::Image(ImageInfo {
+                    width: 512,
+                    height: 256,
+                    ..
+                })
+            ),
+            "expected Image, got {:?}",
+            entry.kind
+        );
+    }
+
+    /// PDFs carry their extracted text, and their raw bytes are read back on
+    /// demand; a damaged one is binary.
+    #[test]
+    fn pdf_file_detected_as_pdf() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = crate::pdf::tests::sample_pdf(&["BT (Spec) Tj ET"]);
+        let path = temp_file(dir.path(), "spec.pdf", &data);
+        let entry = process_file(path, "spec.pdf".into(), DEFAULT_MAX_SIZE);
+        assert!(
+            matches!(entry.kind, FileKind::Pdf(PdfInfo { pages: 1, .. })),
+            "expected Pdf, got {:?}",
+            entry.kind
+        );
+        assert_eq!(entry.content.as_deref(), Some("Spec\n"));
+        assert!(entry.document.is_none());
+        assert_eq!(entry.document().unwrap(), data.as_slice());
+
+        let path = temp_file(dir.path(), "broken.pdf", b"%PDF-1.7\n\xff\xfe garbage");
+        let entry = process_file(path, "broken.pdf".into(), DEFAULT_MAX_SIZE);
+        assert!(matches!(entry.kind, FileKind::Binary));
+        assert!(entry.document.is_none());
+    }
+
+    /// Text above 3 MB is left on disk to be counted in chunks, and
+    /// anything above `max_size` is too large.
+    #[test]
+    fn large_text_is_chunked_or_too_large() {
+        let dir = tempfile::tempdir().unwrap();
+        let text = "a line of log output\n".repeat(200_000);
+        let path = temp_file(dir.path(), "big.log", text.as_bytes());
+
+        let entry = process_file(path.clone(), "big.log".into(), DEFAULT_MAX_SIZE);
+        assert!(
+            matches!(entry.kind, FileKind::Chunked(Encoding::Utf8)),
+            "{:?}",
+            entry.kind
+        );
+        assert!(entry.content.is_none());
+
+        let entry = process_file(path, "big.log".into(), 1024 * 1024);
+        assert!(matches!(entry.kind, FileKind::TooLarge), "{:?}", entry.kind);
+    }
+
+    /// Chunks are bounded, end at line breaks (or character boundaries for
+    /// an overlong line), and lose nothing.
+    #[rstest]
+    #[case::lines(&"one short line\n".repeat(50_000))]
+    #[case::one_long_line(&"\u{e9}".repeat(CHUNK_BYTES))]
+    fn chunks_split_at_safe_boundaries(#[case] text: &str) {
+        let chunks: Vec<String> = Chunks::new(text.as_bytes(), Encoding::Utf8)
+            .collect::<std::io::Result<_>>()
+            .unwrap();
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|c| c.len() <= CHUNK_BYTES));
+        if text.contains('\n') {
+            assert!(chunks.iter().all(|c| c.ends_with('\n')));
+        }
+        assert_eq!(chunks.concat(), text);
+    }
+
     /// Text file content should be loaded into `entry.content`.
     #[test]
     fn text_content_is_loaded() {
         let dir = tempfile::tempdir().unwrap();
         let path = temp_file(dir.path(), "hello.txt", b"hello world");
-        let entry = process_file(path, "hello.txt".into());
+        let entry = process_file(path, "hello.txt".into(), DEFAULT_MAX_SIZE);
         assert_eq!(entry.content.as_deref(), Some("hello world"));
     }
 
@@ -389,8 +1027,8 @@ mod tests {
     #[test]
     fn binary_content_is_none() {
         let dir = tempfile::tempdir().unwrap();
-        let path = temp_file(dir.path(), "x.bin", b"\xFF\xFE");
-        let entry = process_file(path, "x.bin".into());
+        let path = temp_file(dir.path(), "x.bin", b"\x00\x01\x02\x03");
+        let entry = process_file(path, "x.bin".into(), DEFAULT_MAX_SIZE);
         assert!(entry.content.is_none());
     }
 
@@ -405,7 +1043,8 @@ mod tests {
 
         let opts = WalkOptions {
             no_ignore: true,
-            depth: None,
+            max_size: DEFAULT_MAX_SIZE,
+            filter: Filter::default(),
         };
         let results = walk_paths(&[dir.path().to_path_buf()], &opts);
 
@@ -413,25 +1052,6 @@ mod tests {
         assert_eq!(results[0].entries.len(), 2);
     }
 
-    /// `--depth 1` should exclude files inside subdirectories.
-    #[test]
-    fn walk_respects_depth_limit() {
-        let dir = tempfile::tempdir().unwrap();
-        std::fs::create_dir(dir.path().join("sub")).unwrap();
-        std::fs::write(dir.path().join("top.txt"), b"top").unwrap();
-        std::fs::write(dir.path().join("sub").join("deep.txt"), b"deep").unwrap();
-
-        let opts = WalkOptions {
-            no_ignore: true,
-            depth: Some(1),
-        };
-        let results = walk_paths(&[dir.path().to_path_buf()], &opts);
-        let entries = &results[0].entries;
-
-        assert_eq!(entries.len(), 1);
-        assert!(entries[0].rel_path.ends_with("top.txt"));
-    }
-
     /// Relative paths stored in entries should not include the walk root prefix.
     #[test]
     fn walk_rel_paths_strip_root() {
@@ -440,7 +1060,8 @@ mod tests {
 
         let opts = WalkOptions {
             no_ignore: true,
-            depth: None,
+            max_size: DEFAULT_MAX_SIZE,
+            filter: Filter::default(),
         };
         let results = walk_paths(&[dir.path().to_path_buf()], &opts);
 
@@ -461,7 +1082,8 @@ mod tests {
 
         let opts = WalkOptions {
             no_ignore: true,
-            depth: None,
+            max_size: DEFAULT_MAX_SIZE,
+            filter: Filter::default(),
         };
         let results = walk_paths(&[dir.path().to_path_buf()], &opts);
         let names: Vec<&str> = results[0]
@@ -485,7 +1107,8 @@ mod tests {
 
         let opts = WalkOptions {
             no_ignore: true,
-            depth: None,
+            max_size: DEFAULT_MAX_SIZE,
+            filter: Filter::default(),
         };
         let results = walk_paths(&[dir.path().to_path_buf()], &opts);
         let names: Vec<&str> = results[0]
@@ -506,7 +1129,8 @@ mod tests {
 
         let opts = WalkOptions {
             no_ignore: true,
-            depth: None,
+            max_size: DEFAULT_MAX_SIZE,
+            filter: Filter::default(),
         };
         let results = walk_paths(&[dir.path().to_path_buf()], &opts);
 
@@ -514,6 +1138,94 @@ mod tests {
         assert_eq!(results[0].entries.len(), 1);
     }
 
+    // ── filters ───────────────────────────────────────────────────────────
+
+    /// Walk a small mixed tree with the given filter and return the paths
+    /// found.
+    fn filtered_walk(filter: Filter) -> Vec<String> {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("src/gen")).unwrap();
+        std::fs::create_dir(dir.path().join("docs")).unwrap();
+        for name in [
+            "src/main.rs",
+            "src/gen/api.rs",
+            "src/schema.json",
+            "docs/guide.md",
+        ] {
+            std::fs::write(dir.path().join(name), b"x").unwrap();
+        }
+        let opts = WalkOptions {
+            no_ignore: true,
+            max_size: DEFAULT_MAX_SIZE,
+            filter,
+        };
+        let results = walk_paths(&[dir.path().to_path_buf()], &opts);
+        assert!(results[0].errors.is_empty());
+        results[0]
+            .entries
+            .iter()
+            .map(|e| e.rel_path.to_str().unwrap().to_string())
+            .collect()
+    }
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(ToString::to_string).collect()
+    }
+
+    #[rstest]
+    #[case::include(&["*.rs"], &[], &[], &[], &["src/gen/api.rs", "src/main.rs"])]
+    #[case::include_path(&["src/*"], &[], &[], &[], &["src/main.rs", "src/schema.json"])]
+    #[case::exclude_dir(&[], &["gen/"], &[], &[], &["docs/guide.md", "src/main.rs", "src/schema.json"])]
+    #[case::include_and_exclude(&["*.rs"], &["gen/"], &[], &[], &["src/main.rs"])]
+    #[case::type_select(&[], &[], &["rust", "json"], &[], &["src/gen/api.rs", "src/main.rs", "src/schema.json"])]
+    #[case::type_not(&[], &[], &[], &["markdown"], &["src/gen/api.rs", "src/main.rs", "src/schema.json"])]
+    #[case::glob_and_type(&["src/**"], &[], &[], &["rust"], &["src/schema.json"])]
+    fn walk_applies_filters(
+        #[case] include: &[&str],
+        #[case] exclude: &[&str],
+        #[case] types: &[&str],
+        #[case] types_not: &[&str],
+        #[case] expected: &[&str],
+    ) {
+        let filter = Filter::new(
+            &strings(include),
+            &strings(exclude),
+            &strings(types),
+            &strings(types_not),
+        )
+        .unwrap();
+        assert_eq!(filtered_walk(filter), expected);
+    }
+
+    /// An include glob narrows the walk; it does not bring back files that
+    /// an ignore file excludes.
+    #[test]
+    fn include_does_not_override_ignore_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(".ignore"), b"generated.rs\n").unwrap();
+        std::fs::write(dir.path().join("generated.rs"), b"x").unwrap();
+        std::fs::write(dir.path().join("lib.rs"), b"x").unwrap();
+        let opts = WalkOptions {
+            no_ignore: false,
+            max_size: DEFAULT_MAX_SIZE,
+            filter: Filter::new(&strings(&["*.rs"]), &[], &[], &[]).unwrap(),
+        };
+        let results = walk_paths(&[dir.path().to_path_buf()], &opts);
+        let names: Vec<&str> = results[0]
+            .entries
+            .iter()
+            .map(|e| e.rel_path.to_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["lib.rs"]);
+    }
+
+    #[rstest]
+    #[case::bad_glob(&["src/[a"], &[])]
+    #[case::unknown_type(&[], &["klingon"])]
+    fn invalid_filter_is_rejected(#[case] include: &[&str], #[case] types: &[&str]) {
+        assert!(Filter::new(&strings(include), &[], &strings(types), &[]).is_err());
+    }
+
     // ── STDIN_LABEL ──────────────────────────────────────────────────────
 
     #[test]
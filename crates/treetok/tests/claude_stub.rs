@@ -0,0 +1,292 @@
+//! `ClaudeTokenizer` against an in-process stub of the `count_tokens`
+//! endpoint, exercising `--api-base`, custom auth headers, and extra headers
+//! without network access or credentials.
+//!
+//! The stub answers every request with one token per whitespace-separated
+//! word plus a fixed [`ENVELOPE`], so the `"a"` and `"a a"` probes agree on
+//! an overhead of exactly `ENVELOPE`.  A `document` block counts as [`DOCUMENT`] tokens.
+
+mod common;
+
+use common::{Reply, Stub, text_entry};
+use treetok::pdf::PdfInfo;
+use treetok::tokenize::{
+    ClaudeTokenizer, ResolveOptions, TokenCount, TokenizeError, Tokenizer, TokenizerId,
+    resolve_tokenizers, tokenize_entries,
+};
+use treetok::walk::{FileEntry, FileKind};
+
+/// Tokens the stub adds to every count, standing in for the message envelope.
+const ENVELOPE: usize = 7;
+/// Tokens the stub charges for any PDF `document` block.
+const DOCUMENT: usize = 1_500;
+
+/// Start a `count_tokens` stub that replies with the statuses in `script`,
+/// in order, then `200` forever.  Non-200 replies carry an error body
+/// instead of a count; `429`s carry `retry-after: 0`.
+fn claude_stub(script: &[u16]) -> Stub {
+    let script = script.to_vec();
+    let mut served = 0;
+    Stub::start(move |req| {
+        let status = script.get(served).copied().unwrap_or(200);
+        served += 1;
+        if status == 200 {
+            let content = &req.body["messages"][0]["content"];
+            let tokens = if content[0]["type"] == "document" {
+                DOCUMENT + ENVELOPE
+            } else {
+                content.as_str().unwrap_or("").split_whitespace().count() + ENVELOPE
+            };
+            return Reply::json(200, &serde_json::json!({ "input_tokens": tokens }));
+        }
+        let mut reply = Reply::json(
+            status,
+            &serde_json::json!({ "type": "error", "error": { "type": "api_error" } }),
+        );
+        if status == 429 {
+            reply.headers.push("retry-after: 0".to_string());
+        }
+        reply
+    })
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn count_tokens_posts_to_api_base_with_default_headers() {
+    let stub = claude_stub(&[]);
+    let claude = ClaudeTokenizer::with_key("sk-test".to_string()).with_api_base(&stub.base);
+
+    assert_eq!(
+        claude.count_wrapped("one two three").await.unwrap(),
+        3 + ENVELOPE
+    );
+
+    let requests = stub.requests();
+    let req = &requests[0];
+    assert_eq!(req.request_line, "POST /v1/messages/count_tokens HTTP/1.1");
+    assert_eq!(req.header("x-api-key"), Some("sk-test"));
+    assert_eq!(req.header("anthropic-version"), Some("2023-06-01"));
+    assert_eq!(req.body["model"], claude.model());
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn custom_auth_and_extra_headers_are_sent() {
+    let stub = claude_stub(&[]);
+    let claude = ClaudeTokenizer::with_key("sk-test".to_string())
+        .with_api_base(&format!("{}/", stub.base))
+        .with_auth_header("Authorization".to_string())
+        .with_headers(vec![("X-Gateway-Team".to_string(), "infra".to_string())]);
+
+    claude.count_wrapped("hi").await.unwrap();
+
+    let requests = stub.requests();
+    let req = &requests[0];
+    assert!(
+        req.request_line
+            .starts_with("POST /v1/messages/count_tokens ")
+    );
+    assert_eq!(req.header("authorization"), Some("Bearer sk-test"));
+    assert_eq!(req.header("x-api-key"), None);
+    assert_eq!(req.header("x-gateway-team"), Some("infra"));
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn error_status_surfaces_as_api_error() {
+    let stub = claude_stub(&[401]);
+    let claude = ClaudeTokenizer::with_key("bad".to_string()).with_api_base(&stub.base);
+
+    let err = claude.count_wrapped("hi").await.unwrap_err();
+    assert!(
+        matches!(err, TokenizeError::ApiError { status: 401, .. }),
+        "unexpected error: {err:?}"
+    );
+}
+
+/// Blocking counts share the tokenizer's runtime rather than building one
+/// per call.
+#[test]
+fn count_tokens_reuses_one_runtime() {
+    let stub = claude_stub(&[]);
+    let claude = ClaudeTokenizer::with_key("sk-test".to_string()).with_api_base(&stub.base);
+
+    assert_eq!(claude.count_tokens("one two").unwrap(), 2);
+    assert_eq!(claude.count_tokens("one two three").unwrap(), 3);
+    // Both probes once, then one request per call.
+    assert_eq!(stub.requests().len(), 4);
+}
+
+/// Blocking inside a caller's runtime would stall it, so it is refused
+/// before any request is sent.
+#[tokio::test(flavor = "current_thread")]
+async fn count_tokens_inside_a_runtime_is_an_error() {
+    let stub = claude_stub(&[]);
+    let claude = ClaudeTokenizer::with_key("sk-test".to_string()).with_api_base(&stub.base);
+
+    let err = claude.count_tokens("hi").unwrap_err();
+    assert!(
+        matches!(err, TokenizeError::NestedRuntime),
+        "unexpected error: {err:?}"
+    );
+    assert!(stub.requests().is_empty());
+}
+
+/// The full pipeline: resolve with `api_base`, tokenize, and get raw counts
+/// with the calibrated envelope removed.
+#[test]
+fn tokenize_entries_uses_configured_endpoint() {
+    let stub = claude_stub(&[]);
+    let opts = ResolveOptions {
+        api_key: Some("sk-test".to_string()),
+        api_base: Some(stub.base.clone()),
+        api_headers: vec![("x-trace".to_string(), "1".to_string())],
+        ..ResolveOptions::default()
+    };
+    let resolved = resolve_tokenizers(&["claude".to_string()], &opts).unwrap();
+    let entries = [
+        text_entry("a.txt", "alpha beta"),
+        text_entry("b.txt", "gamma delta epsilon"),
+    ];
+
+    let results = tokenize_entries(&entries, &resolved);
+
+    let claude = |i: usize| &results[i].tokens[&TokenizerId::Claude];
+    assert!(matches!(claude(0), TokenCount::Exact(2)));
+    assert!(matches!(claude(1), TokenCount::Exact(3)));
+    assert_eq!(results[1].wrapped[&TokenizerId::Claude], 3 + ENVELOPE);
+    // Two files plus the two overhead probes.
+    let requests = stub.requests();
+    assert_eq!(requests.len(), 4);
+    assert!(requests.iter().all(|r| r.header("x-trace") == Some("1")));
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn throttled_request_is_retried_after_retry_after() {
+    let stub = claude_stub(&[429, 529]);
+    let claude = ClaudeTokenizer::with_key("sk-test".to_string()).with_api_base(&stub.base);
+
+    assert_eq!(claude.count_wrapped("one two").await.unwrap(), 2 + ENVELOPE);
+    assert_eq!(stub.requests().len(), 3);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn retries_are_bounded_by_max_retries() {
+    let stub = claude_stub(&[429, 429, 429]);
+    let claude = ClaudeTokenizer::with_key("sk-test".to_string())
+        .with_api_base(&stub.base)
+        .with_max_retries(1);
+
+    let err = claude.count_wrapped("hi").await.unwrap_err();
+    assert!(
+        matches!(err, TokenizeError::RateLimitExceeded),
+        "unexpected error: {err:?}"
+    );
+    assert_eq!(stub.requests().len(), 2, "one try plus one retry");
+}
+
+/// A file whose request fails still gets a Claude count: a ctoc estimate,
+/// flagged as a fallback.
+#[test]
+fn failed_request_falls_back_to_ctoc_estimate() {
+    let stub = claude_stub(&[400]);
+    let opts = ResolveOptions {
+        api_key: Some("sk-test".to_string()),
+        api_base: Some(stub.base.clone()),
+        ..ResolveOptions::default()
+    };
+    let resolved = resolve_tokenizers(&["claude".to_string()], &opts).unwrap();
+
+    let results = tokenize_entries(&[text_entry("a.txt", "alpha beta gamma")], &resolved);
+
+    assert!(matches!(
+        results[0].tokens[&TokenizerId::Claude],
+        TokenCount::Approx { .. }
+    ));
+    assert!(results[0].fallback.contains(&TokenizerId::Claude));
+    assert!(results[0].wrapped.is_empty());
+    // No successful count, so no overhead probe either.
+    assert_eq!(stub.requests().len(), 1);
+}
+
+/// A stub whose envelope grows with the content makes the two probes
+/// disagree, so counts are reported as returned rather than corrected by a
+/// wrong overhead.
+#[test]
+fn inconsistent_overhead_is_not_subtracted() {
+    let stub = Stub::start(|req| {
+        let words = req.body["messages"][0]["content"]
+            .as_str()
+            .unwrap_or("")
+            .split_whitespace()
+            .count();
+        Reply::json(
+            200,
+            &serde_json::json!({ "input_tokens": 2 * words + ENVELOPE }),
+        )
+    });
+    let opts = ResolveOptions {
+        api_key: Some("sk-test".to_string()),
+        api_base: Some(stub.base.clone()),
+        ..ResolveOptions::default()
+    };
+    let resolved = resolve_tokenizers(&["claude".to_string()], &opts).unwrap();
+
+    let results = tokenize_entries(&[text_entry("a.txt", "alpha beta")], &resolved);
+
+    assert!(matches!(
+        results[0].tokens[&TokenizerId::Claude],
+        TokenCount::Exact(n) if n == 4 + ENVELOPE
+    ));
+    assert_eq!(results[0].wrapped[&TokenizerId::Claude], 4 + ENVELOPE);
+    assert_eq!(stub.requests().len(), 3, "one file plus both probes");
+}
+
+/// With Claude active, PDFs are read back from disk and sent whole as
+/// base64 `document` blocks.  The count is exact and keeps its envelope,
+/// since the text-calibrated overhead does not apply to documents.
+#[test]
+fn pdfs_are_counted_as_document_blocks() {
+    let stub = claude_stub(&[]);
+    let opts = ResolveOptions {
+        api_key: Some("sk-test".to_string()),
+        api_base: Some(stub.base.clone()),
+        ..ResolveOptions::default()
+    };
+    let resolved = resolve_tokenizers(&["claude".to_string()], &opts).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("spec.pdf");
+    std::fs::write(&path, b"%PDF-1.7").unwrap();
+    let pdf = FileEntry {
+        path,
+        rel_path: "spec.pdf".into(),
+        kind: FileKind::Pdf(PdfInfo {
+            pages: 3,
+            width_pt: 612,
+            height_pt: 792,
+        }),
+        content: Some("extracted text".to_string()),
+        document: None,
+    };
+
+    let results = tokenize_entries(&[text_entry("a.txt", "alpha"), pdf], &resolved);
+
+    assert!(matches!(
+        results[1].tokens[&TokenizerId::Claude],
+        TokenCount::Exact(n) if n == DOCUMENT + ENVELOPE
+    ));
+    let requests = stub.requests();
+    let documents: Vec<_> = requests
+        .iter()
+        .map(|r| &r.body["messages"][0]["content"])
+        .filter(|c| c.is_array())
+        .collect();
+    assert_eq!(
+        documents,
+        [&serde_json::json!([{
+            "type": "document",
+            "source": {
+                "type": "base64",
+                "media_type": "application/pdf",
+                "data": "JVBERi0xLjc=",
+            },
+        }])]
+    );
+}
@@ -0,0 +1,144 @@
+//! An in-process HTTP stub shared by the API-backed tokenizer tests.
+//!
+//! The stub answers each request with whatever its handler returns, and
+//! records every request so tests can inspect what the client sent.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+
+use treetok::encoding::Encoding;
+use treetok::walk::{FileEntry, FileKind};
+
+/// A UTF-8 text file named `name` holding `content`.
+pub fn text_entry(name: &str, content: &str) -> FileEntry {
+    FileEntry {
+        path: name.into(),
+        rel_path: name.into(),
+        kind: FileKind::Text(Encoding::Utf8),
+        content: Some(content.to_string()),
+        document: None,
+    }
+}
+
+/// One request as the stub saw it.
+#[derive(Clone)]
+pub struct Recorded {
+    pub request_line: String,
+    /// Header names lower-cased.
+    pub headers: Vec<(String, String)>,
+    pub body: serde_json::Value,
+}
+
+impl Recorded {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// The stub's answer to one request.
+pub struct Reply {
+    pub status: u16,
+    pub body: String,
+    /// Extra `name: value` header lines.
+    pub headers: Vec<String>,
+}
+
+impl Reply {
+    /// A `status` reply with a JSON `body` and no extra headers.
+    pub fn json(status: u16, body: &serde_json::Value) -> Self {
+        Self {
+            status,
+            body: body.to_string(),
+            headers: Vec::new(),
+        }
+    }
+}
+
+/// A stub server on a random local port, answering until the test exits.
+pub struct Stub {
+    pub base: String,
+    requests: Arc<Mutex<Vec<Recorded>>>,
+}
+
+impl Stub {
+    /// Start a stub that answers every request with `handler`'s reply.
+    #[allow(clippy::unwrap_used)] // helper only called from test fns; panic is correct behaviour
+    pub fn start(handler: impl FnMut(&Recorded) -> Reply + Send + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&requests);
+        std::thread::spawn(move || {
+            let mut handler = handler;
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else { continue };
+                serve(stream, &mut handler, &log);
+            }
+        });
+        Self { base, requests }
+    }
+
+    /// Snapshot of the requests received so far.
+    #[allow(clippy::unwrap_used)] // helper only called from test fns; panic is correct behaviour
+    pub fn requests(&self) -> Vec<Recorded> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// Handle one request-response exchange on `stream`, recording the request
+/// in `log` before replying so the client never sees a reply first.
+#[allow(clippy::unwrap_used)] // stub thread; a malformed request should fail loudly
+fn serve(
+    mut stream: TcpStream,
+    handler: &mut impl FnMut(&Recorded) -> Reply,
+    log: &Mutex<Vec<Recorded>>,
+) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).unwrap();
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line.split_once(':').unwrap();
+        headers.push((name.to_ascii_lowercase(), value.trim().to_string()));
+    }
+    let len: usize = headers
+        .iter()
+        .find(|(n, _)| n == "content-length")
+        .map_or(0, |(_, v)| v.parse().unwrap());
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body).unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+    let recorded = Recorded {
+        request_line: request_line.trim_end().to_string(),
+        headers,
+        body,
+    };
+    let reply = handler(&recorded);
+    log.lock().unwrap().push(recorded);
+
+    let extra = reply
+        .headers
+        .iter()
+        .fold(String::new(), |acc, h| acc + h + "\r\n");
+    write!(
+        stream,
+        "HTTP/1.1 {} Stub\r\ncontent-type: application/json\r\n{extra}\
+         content-length: {}\r\nconnection: close\r\n\r\n{}",
+        reply.status,
+        reply.body.len(),
+        reply.body
+    )
+    .unwrap();
+}
@@ -0,0 +1,171 @@
+//! `GeminiApiTokenizer` against an in-process stub of the Generative
+//! Language `countTokens` endpoint, exercising `--gemini-api-base`, model
+//! selection, the count cache, and `--offline` without network access or credentials.
+//!
+//! The stub counts one token per whitespace-separated word of the first
+//! text part.
+
+mod common;
+
+use common::{Reply, Stub, text_entry};
+use serde_json::json;
+use treetok::tokenize::{
+    GeminiApiTokenizer, ResolveOptions, TokenCount, TokenizeError, TokenizerId, resolve_tokenizers,
+    tokenize_entries,
+};
+
+/// Start a `countTokens` stub that answers every request with `status`;
+/// non-200 replies carry an error body instead of a count.
+fn gemini_stub(status: u16) -> Stub {
+    Stub::start(move |req| {
+        if status != 200 {
+            return Reply::json(status, &json!({ "error": { "code": status } }));
+        }
+        let text = req.body["contents"][0]["parts"][0]["text"]
+            .as_str()
+            .unwrap_or("");
+        Reply::json(
+            200,
+            &json!({ "totalTokens": text.split_whitespace().count() }),
+        )
+    })
+}
+
+fn keyed(base: &str) -> ResolveOptions {
+    ResolveOptions {
+        gemini_api: true,
+        gemini_api_key: Some("g-test".to_string()),
+        gemini_api_base: Some(base.to_string()),
+        ..ResolveOptions::default()
+    }
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn count_posts_to_model_endpoint_with_key_header() {
+    let stub = gemini_stub(200);
+    let gemini = GeminiApiTokenizer::with_key("g-test".to_string()).with_api_base(&stub.base);
+
+    assert_eq!(gemini.count("one two three").await.unwrap(), 3);
+
+    let requests = stub.requests();
+    assert_eq!(requests.len(), 1);
+    let req = &requests[0];
+    assert!(
+        req.request_line
+            .starts_with("POST /v1beta/models/gemini-2.5-flash:countTokens "),
+        "{}",
+        req.request_line
+    );
+    assert_eq!(req.header("x-goog-api-key"), Some("g-test"));
+    assert_eq!(
+        req.body,
+        json!({ "contents": [{ "parts": [{ "text": "one two three" }] }] })
+    );
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn rejected_request_is_api_error() {
+    let stub = gemini_stub(403);
+    let gemini = GeminiApiTokenizer::with_key("bad".to_string()).with_api_base(&stub.base);
+
+    let err = gemini.count("hi").await.unwrap_err();
+    assert!(
+        matches!(err, TokenizeError::ApiError { status: 403, .. }),
+        "unexpected error: {err:?}"
+    );
+}
+
+/// `--model gemini-*` counts for that model, and the counts are exact.
+#[test]
+fn model_flag_selects_request_model() {
+    let stub = gemini_stub(200);
+    let opts = ResolveOptions {
+        models: vec!["gemini-2.5-pro".to_string()],
+        ..keyed(&stub.base)
+    };
+    let resolved = resolve_tokenizers(&[], &opts).unwrap();
+    let entries = [
+        text_entry("a.txt", "alpha beta"),
+        text_entry("b.txt", "gamma delta epsilon"),
+    ];
+
+    let results = tokenize_entries(&entries, &resolved);
+
+    assert!(matches!(
+        results[0].tokens[&TokenizerId::Gemini],
+        TokenCount::Exact(2)
+    ));
+    assert!(matches!(
+        results[1].tokens[&TokenizerId::Gemini],
+        TokenCount::Exact(3)
+    ));
+    let requests = stub.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests.iter().all(|r| {
+        r.request_line
+            .contains("/models/gemini-2.5-pro:countTokens")
+    }));
+}
+
+/// A key alone does not opt into the API: without `--gemini-api` the
+/// embedded vocab counts and nothing is uploaded.
+#[test]
+fn key_without_opt_in_skips_the_api() {
+    let stub = gemini_stub(200);
+    let opts = ResolveOptions {
+        gemini_api: false,
+        ..keyed(&stub.base)
+    };
+
+    let resolved = resolve_tokenizers(&["gemini".to_string()], &opts).unwrap();
+    let results = tokenize_entries(&[text_entry("a.txt", "Hello, world!")], &resolved);
+
+    assert!(matches!(
+        results[0].tokens[&TokenizerId::Gemini],
+        TokenCount::Exact(4)
+    ));
+    assert!(stub.requests().is_empty());
+}
+
+/// Counts go through the on-disk cache: a second run over the same
+/// content sends no requests.
+#[test]
+fn cached_counts_skip_the_api() {
+    let stub = gemini_stub(200);
+    let cache_dir = tempfile::tempdir().unwrap();
+    let opts = ResolveOptions {
+        cache_dir: Some(cache_dir.path().to_path_buf()),
+        ..keyed(&stub.base)
+    };
+    let entries = [text_entry("a.txt", "alpha beta")];
+
+    for _ in 0..2 {
+        let resolved = resolve_tokenizers(&["gemini".to_string()], &opts).unwrap();
+        let results = tokenize_entries(&entries, &resolved);
+        assert!(matches!(
+            results[0].tokens[&TokenizerId::Gemini],
+            TokenCount::Exact(2)
+        ));
+    }
+    assert_eq!(stub.requests().len(), 1);
+}
+
+/// With `--offline` the key is ignored and the API is never called.
+#[test]
+fn offline_skips_the_api() {
+    let stub = gemini_stub(200);
+    let opts = ResolveOptions {
+        offline: true,
+        ..keyed(&stub.base)
+    };
+
+    // `-t gemini` falls back to the embedded Gemma 3 vocab.
+    let resolved = resolve_tokenizers(&["gemini".to_string()], &opts).unwrap();
+    let results = tokenize_entries(&[text_entry("a.txt", "Hello, world!")], &resolved);
+
+    assert!(matches!(
+        results[0].tokens[&TokenizerId::Gemini],
+        TokenCount::Exact(4)
+    ));
+    assert!(stub.requests().is_empty());
+}
@@ -0,0 +1,160 @@
+//! `server:URL` tokenizers against in-process stubs of the llama.cpp, vLLM,
+//! and TGI `/tokenize` endpoints.
+//!
+//! Each stub speaks one protocol: it counts one token per whitespace-separated
+//! word for a body it understands, and answers the others the way the real
+//! server does (llama.cpp with an empty token list, vLLM and TGI with a
+//! validation error).  Like the real TGI, whose `/tokenize` has no switch for
+//! special tokens, the TGI stub prepends a BOS token to every answer.
+
+mod common;
+
+use common::{Reply, Stub, text_entry};
+use rstest::rstest;
+use serde_json::{Value, json};
+use treetok::tokenize::{
+    ResolveOptions, TokenCount, TokenizeError, TokenizerId, resolve_tokenizers, tokenize_entries,
+};
+use treetok::walk::FileEntry;
+
+#[derive(Clone, Copy, Debug)]
+enum Flavor {
+    LlamaCpp,
+    Vllm,
+    Tgi,
+}
+
+fn words(body: &Value, key: &str) -> Option<usize> {
+    body.get(key)?
+        .as_str()
+        .map(|s| s.split_whitespace().count())
+}
+
+/// Answer `body` the way a `flavor` server would.
+fn reply(flavor: Flavor, body: &Value) -> Reply {
+    let ids = |n: usize| (0..n).collect::<Vec<_>>();
+    match flavor {
+        Flavor::LlamaCpp => {
+            let n = words(body, "content").unwrap_or(0);
+            Reply::json(200, &json!({ "tokens": ids(n) }))
+        }
+        Flavor::Vllm => words(body, "prompt").map_or_else(
+            || Reply::json(400, &json!({ "message": "prompt: field required" })),
+            |n| {
+                Reply::json(
+                    200,
+                    &json!({ "count": n, "max_model_len": 8192, "tokens": ids(n) }),
+                )
+            },
+        ),
+        Flavor::Tgi => words(body, "inputs").map_or_else(
+            || Reply::json(422, &json!({ "error": "missing field `inputs`" })),
+            |n| {
+                let bos = json!({ "id": 1, "text": "<s>", "special": true });
+                let tokens: Vec<Value> = std::iter::once(bos)
+                    .chain(ids(n).iter().map(|id| json!({ "id": id + 2 })))
+                    .collect();
+                Reply::json(200, &Value::from(tokens))
+            },
+        ),
+    }
+}
+
+fn entries() -> [FileEntry; 2] {
+    [
+        text_entry("a.txt", "alpha beta"),
+        text_entry("b.txt", "gamma delta epsilon"),
+    ]
+}
+
+#[rstest]
+#[case::llama_cpp(Flavor::LlamaCpp, 1)]
+#[case::vllm(Flavor::Vllm, 2)]
+#[case::tgi(Flavor::Tgi, 3)]
+fn protocol_is_detected_and_used(#[case] flavor: Flavor, #[case] probes: usize) {
+    let stub = Stub::start(move |req| reply(flavor, &req.body));
+    let spec = format!("local=server:{}", stub.base);
+    let resolved = resolve_tokenizers(&[spec], &ResolveOptions::default()).unwrap();
+
+    let results = tokenize_entries(&entries(), &resolved);
+
+    let local = TokenizerId::custom("local");
+    assert!(matches!(results[0].tokens[&local], TokenCount::Exact(2)));
+    assert!(matches!(results[1].tokens[&local], TokenCount::Exact(3)));
+    let requests = stub.requests();
+    assert_eq!(requests.len(), probes + 2, "probes, then one per file");
+    assert!(
+        requests
+            .iter()
+            .all(|r| r.request_line.starts_with("POST /tokenize "))
+    );
+    assert!(
+        requests
+            .iter()
+            .all(|r| r.header("content-type") == Some("application/json"))
+    );
+}
+
+#[test]
+fn vllm_requests_skip_special_tokens() {
+    let stub = Stub::start(|req| reply(Flavor::Vllm, &req.body));
+    let spec = format!("server:{}", stub.base);
+    let resolved = resolve_tokenizers(&[spec], &ResolveOptions::default()).unwrap();
+
+    let _ = tokenize_entries(&entries(), &resolved);
+
+    let last = stub.requests().pop().unwrap();
+    assert_eq!(last.body["add_special_tokens"], false);
+}
+
+#[test]
+fn unknown_server_is_probed_once_and_leaves_files_uncounted() {
+    let stub = Stub::start(|_| Reply::json(404, &json!({ "error": "not found" })));
+    let spec = format!("server:{}", stub.base);
+    let resolved = resolve_tokenizers(&[spec], &ResolveOptions::default()).unwrap();
+
+    let results = tokenize_entries(&entries(), &resolved);
+
+    assert!(results.iter().all(|r| r.tokens.is_empty()));
+    assert!(results.iter().all(|r| r.fallback.is_empty()));
+    assert_eq!(stub.requests().len(), 3, "one probe per protocol, no more");
+}
+
+#[test]
+fn unavailable_server_is_retried() {
+    let mut served = 0;
+    let stub = Stub::start(move |req| {
+        served += 1;
+        if served == 1 {
+            let mut busy = Reply::json(503, &json!({ "error": "Loading model" }));
+            busy.headers.push("retry-after: 0".to_string());
+            return busy;
+        }
+        reply(Flavor::LlamaCpp, &req.body)
+    });
+    let spec = format!("server:{}", stub.base);
+    let resolved = resolve_tokenizers(&[spec], &ResolveOptions::default()).unwrap();
+
+    let results = tokenize_entries(&entries()[..1], &resolved);
+
+    let id = TokenizerId::custom(stub.base.trim_start_matches("http://"));
+    assert!(matches!(results[0].tokens[&id], TokenCount::Exact(2)));
+}
+
+#[test]
+fn unreachable_server_is_a_network_error() {
+    // Bind, then drop, to get a port nothing listens on.
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let spec = format!("server:http://127.0.0.1:{port}");
+    let resolved = resolve_tokenizers(&[spec], &ResolveOptions::default()).unwrap();
+    let tok = resolved.iter().next().unwrap();
+
+    assert!(matches!(
+        tok.count_tokens("hello"),
+        Err(TokenizeError::Network(_))
+    ));
+}
@@ -52,7 +52,7 @@ use std::path::PathBuf;
 
 use tokenizers::Tokenizer;
 use treetok::{
-    tokenize::{CtocTokenizer, Tokenizer as _, resolve_tokenizers},
+    tokenize::{ClaudeTokenizer, CtocTokenizer, Tokenizer as _, load_api_key},
     walk::{FileKind, WalkOptions, walk_paths},
 };
 
@@ -135,12 +135,10 @@ async fn hf_claude_tokenizer_candidates_vs_api() {
 
     // ── remote tokenizer ─────────────────────────────────────────────────────
 
-    let resolved = resolve_tokenizers(&["claude".to_string()], false, None)
-        .expect("failed to resolve claude tokenizer – is an API key set?");
-    let remote = resolved
-        .claude
-        .as_ref()
-        .expect("ClaudeTokenizer unavailable (check ANTHROPIC_API_KEY / TREETOK_API_KEY)");
+    let remote = ClaudeTokenizer::with_key(
+        load_api_key()
+            .expect("ClaudeTokenizer unavailable (check ANTHROPIC_API_KEY / TREETOK_API_KEY)"),
+    );
 
     // ── envelope overhead ────────────────────────────────────────────────────
     //
@@ -154,13 +152,13 @@ async fn hf_claude_tokenizer_candidates_vs_api() {
         .expect("Xenova tokenizer must be available to establish envelope overhead");
 
     let ref1_remote: usize = remote
-        .count_tokens(REF1)
+        .count_wrapped(REF1)
         .await
         .expect("count_tokens(REF1) failed");
     let overhead = ref1_remote - local_count(&xenova, REF1);
 
     let ref2_remote: usize = remote
-        .count_tokens(REF2)
+        .count_wrapped(REF2)
         .await
         .expect("count_tokens(REF2) failed");
     let overhead2 = ref2_remote - local_count(&xenova, REF2);
@@ -170,6 +168,14 @@ async fn hf_claude_tokenizer_candidates_vs_api() {
     );
 
     eprintln!("\nAPI message-envelope overhead: {overhead} token(s)");
+    assert_eq!(
+        remote
+            .envelope_overhead()
+            .await
+            .expect("runtime overhead calibration failed"),
+        overhead,
+        "runtime calibration disagrees with the Xenova-derived overhead"
+    );
 
     // ── corpus: every text file under crates/ ────────────────────────────────
 
@@ -184,14 +190,15 @@ async fn hf_claude_tokenizer_candidates_vs_api() {
         &[repo_root.join("crates")],
         &WalkOptions {
             no_ignore: false,
-            depth: None,
+            max_size: treetok::walk::DEFAULT_MAX_SIZE,
+            filter: treetok::walk::Filter::default(),
         },
     );
 
     let text_files: Vec<(PathBuf, String)> = walk
         .into_iter()
         .flat_map(|r| r.entries)
-        .filter(|e| matches!(e.kind, FileKind::Text))
+        .filter(|e| matches!(e.kind, FileKind::Text(_)))
         .filter_map(|e| e.content.map(|c| (e.rel_path, c)))
         .collect();
 
@@ -211,7 +218,7 @@ async fn hf_claude_tokenizer_candidates_vs_api() {
     // ── remote corpus count (shared baseline) ────────────────────────────────
 
     let remote_corpus: usize = remote
-        .count_tokens(&corpus)
+        .count_wrapped(&corpus)
         .await
         .expect("count_tokens(corpus) failed");
     let adjusted = remote_corpus - overhead;
@@ -11,14 +11,15 @@ fn main() {
     let subcommand = std::env::args().nth(1);
     match subcommand.as_deref() {
         Some("update-ctoc") => update_ctoc(),
+        Some("update-gemini") => update_gemini(),
         Some(other) => {
             eprintln!("error: unknown subcommand {other:?}");
-            eprintln!("available: update-ctoc");
+            eprintln!("available: update-ctoc, update-gemini");
             std::process::exit(1);
         }
         None => {
             eprintln!("usage: cargo xtask <subcommand>");
-            eprintln!("available: update-ctoc");
+            eprintln!("available: update-ctoc, update-gemini");
             std::process::exit(1);
         }
     }
@@ -86,6 +87,57 @@ fn update_ctoc() {
     eprintln!("Written {} bytes to {}", buf.len(), out_path.display());
 }
 
+/// Download the Gemma 3 `tokenizer.json` from `HuggingFace` and write it to
+/// `crates/treetok/src/tokenize/gemma3_tokenizer.json`.
+///
+/// The Gemma repositories are gated: accept the licence on the model page and
+/// export `HF_TOKEN` before running.  The file is validated as JSON with a
+/// `model` section but otherwise embedded verbatim.
+fn update_gemini() {
+    const URL: &str = "https://huggingface.co/google/gemma-3-1b-it/resolve/main/tokenizer.json";
+
+    // ── download ─────────────────────────────────────────────────────────────
+
+    eprint!("Downloading {URL} … ");
+    let client = reqwest::blocking::Client::new();
+    let mut req = client.get(URL);
+    if let Ok(token) = std::env::var("HF_TOKEN") {
+        req = req.bearer_auth(token);
+    }
+    let resp = req.send().expect("HTTP request failed");
+    if !resp.status().is_success() {
+        eprintln!("HTTP {}", resp.status());
+        if matches!(resp.status().as_u16(), 401 | 403) {
+            eprintln!("hint: accept the Gemma licence on HuggingFace and set HF_TOKEN");
+        }
+        std::process::exit(1);
+    }
+    let body = resp.bytes().expect("failed to read response body");
+    eprintln!("done.");
+
+    // ── validate ──────────────────────────────────────────────────────────────
+
+    let root: serde_json::Value =
+        serde_json::from_slice(&body).expect("failed to parse tokenizer.json");
+    let vocab_len = root
+        .pointer("/model/vocab")
+        .and_then(|v| v.as_object().map(serde_json::Map::len))
+        .expect("missing \"model.vocab\" in tokenizer.json");
+
+    eprintln!("{vocab_len} tokens parsed.");
+
+    // ── write ─────────────────────────────────────────────────────────────────
+
+    let out_path = workspace_root().join("crates/treetok/src/tokenize/gemma3_tokenizer.json");
+
+    let mut file = std::fs::File::create(&out_path)
+        .unwrap_or_else(|e| panic!("cannot create {}: {e}", out_path.display()));
+    file.write_all(&body)
+        .unwrap_or_else(|e| panic!("write failed: {e}"));
+
+    eprintln!("Written {} bytes to {}", body.len(), out_path.display());
+}
+
 /// Resolve the workspace root as the parent of this package's manifest dir.
 fn workspace_root() -> PathBuf {
     PathBuf::from(env!("CARGO_MANIFEST_DIR"))